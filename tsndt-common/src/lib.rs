@@ -7,5 +7,155 @@ pub struct Counter {
     pub packets: u32,
 }
 
+/// Per-interface microburst tracking state. Traffic is accumulated into fixed width time
+/// buckets, and the busiest bucket seen since userspace last advanced the read epoch is kept as
+/// the peak.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct BurstCounter {
+    pub epoch: u64,
+    pub bucket_start_ns: u64,
+    pub bucket_packets: u64,
+    pub bucket_bytes: u64,
+    pub peak_packets: u64,
+    pub peak_bytes: u64,
+}
+
+/// Number of log2 buckets in an inter-arrival time histogram. Bucket 0 holds gaps of 0 ns and
+/// bucket `b` holds gaps in `[2^(b-1), 2^b)` ns, with the last bucket also holding every larger
+/// gap.
+pub const INTER_ARRIVAL_NUM_BUCKETS: usize = 32;
+
+/// Histogram of the gaps between consecutive frames received on an interface
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InterArrivalHistogram {
+    pub buckets: [u64; INTER_ARRIVAL_NUM_BUCKETS],
+}
+
+impl InterArrivalHistogram {
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; INTER_ARRIVAL_NUM_BUCKETS],
+        }
+    }
+}
+
+impl Default for InterArrivalHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maximum number of leading bytes of a sampled frame copied into a [`PacketSample`]
+pub const PACKET_SAMPLE_MAX_LEN: usize = 128;
+
+/// The start of a frame sampled by the XDP program, sent to userspace through a ring buffer
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PacketSample {
+    pub timestamp_ns: u64,
+    pub if_index: u32,
+    pub packet_len: u32,
+    pub captured_len: u32,
+    pub data: [u8; PACKET_SAMPLE_MAX_LEN],
+}
+
+/// Number of entries in the table of XDP programs that xdp_tsndt chains to, which is indexed by
+/// interface index
+pub const CHAINED_PROGRAMS_MAX_ENTRIES: u32 = 1024;
+
+/// Identifies the hardware receive queue of an interface that a frame arrived on
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RxQueueKey {
+    pub if_index: u32,
+    pub rx_queue: u32,
+}
+
+/// IGMP and MLD report flavors that can be recorded for a multicast group member
+pub const MULTICAST_REPORT_IGMP_V1: u32 = 1;
+pub const MULTICAST_REPORT_IGMP_V2: u32 = 2;
+pub const MULTICAST_REPORT_IGMP_V3: u32 = 3;
+pub const MULTICAST_REPORT_MLD_V1: u32 = 4;
+pub const MULTICAST_REPORT_MLD_V2: u32 = 5;
+
+/// Membership states recorded from the most recent report of a group member
+pub const MULTICAST_MEMBERSHIP_LEFT: u32 = 0;
+pub const MULTICAST_MEMBERSHIP_JOINED: u32 = 1;
+
+/// Identifies traffic sent to a multicast group on an interface. IPv4 groups are stored as
+/// IPv4-mapped IPv6 addresses (::ffff:a.b.c.d) so that both families share a single key type.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MulticastGroupKey {
+    pub if_index: u32,
+    pub group: [u8; 16],
+}
+
+/// Identifies a host which reported membership of a multicast group on an interface. Addresses
+/// follow the same IPv4-mapped convention as [`MulticastGroupKey`].
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MulticastMemberKey {
+    pub if_index: u32,
+    pub group: [u8; 16],
+    pub reporter: [u8; 16],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MulticastMembership {
+    pub last_report_ns: u64,
+    pub report_count: u32,
+    pub report_kind: u32,
+    pub state: u32,
+}
+
+/// Per-interface counts of received IP fragments
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IpFragmentCounter {
+    pub ipv4_first: u64,
+    pub ipv4_middle: u64,
+    pub ipv4_last: u64,
+    pub ipv6: u64,
+}
+
+/// Identifies a TTL (IPv4) or hop limit (IPv6) histogram bucket on an interface
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HopLimitKey {
+    pub if_index: u32,
+    pub hop_limit: u32,
+}
+
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for Counter {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for BurstCounter {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for InterArrivalHistogram {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for PacketSample {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for RxQueueKey {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for IpFragmentCounter {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for HopLimitKey {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for MulticastGroupKey {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for MulticastMemberKey {}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for MulticastMembership {}
@@ -1,38 +1,151 @@
 #![no_std] //
 #![no_main] //
 
-use core::mem;
+use core::{ffi::c_void, mem};
 
 use aya_ebpf::{
     bindings::xdp_action,
+    helpers::{bpf_get_prandom_u32, bpf_ktime_get_ns, bpf_xdp_load_bytes},
     macros::{map, xdp},
-    maps::{LruPerCpuHashMap, PerCpuHashMap},
+    maps::{Array, LruHashMap, LruPerCpuHashMap, PerCpuHashMap, ProgramArray, RingBuf},
     programs::XdpContext,
 };
 use aya_log_ebpf::error;
 use network_types::eth::EthHdr;
-use tsndt_common::Counter;
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+    CHAINED_PROGRAMS_MAX_ENTRIES, INTER_ARRIVAL_NUM_BUCKETS, MULTICAST_MEMBERSHIP_JOINED,
+    MULTICAST_MEMBERSHIP_LEFT, MULTICAST_REPORT_IGMP_V1, MULTICAST_REPORT_IGMP_V2,
+    MULTICAST_REPORT_IGMP_V3, MULTICAST_REPORT_MLD_V1, MULTICAST_REPORT_MLD_V2,
+    PACKET_SAMPLE_MAX_LEN,
+};
 
 const MAX_NUM_INTERFACES: u32 = 1024;
 const MAX_NUM_MAC_ADDRS: u32 = 8192;
+const MAX_NUM_RX_QUEUES: u32 = 4096;
+const MAX_NUM_MULTICAST_GROUPS: u32 = 4096;
+const MAX_NUM_MULTICAST_MEMBERS: u32 = 8192;
+const MAX_NUM_HOP_LIMIT_BUCKETS: u32 = 16384;
+const PACKET_SAMPLES_BYTE_SIZE: u32 = 256 * 1024;
+
+// The verifier needs a constant bound on the number of group records walked in a single
+// IGMPv3 or MLDv2 report. Reports carrying more records than this are only partially recorded.
+const MAX_MULTICAST_GROUP_RECORDS: usize = 8;
+// Upper bound on header offsets computed from packet contents, which keeps packet pointer
+// arithmetic within the range that the verifier accepts
+const MAX_HEADER_OFFSET: usize = 0x3fff;
+
+const ETH_HDR_LEN: usize = 14;
+const VLAN_HDR_LEN: usize = 4;
+const ETH_P_IPV4: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+const ETH_P_8021Q: u16 = 0x8100;
+const ETH_P_8021AD: u16 = 0x88a8;
+const IPV6_HDR_LEN: usize = 40;
+const IPV4_MORE_FRAGMENTS: u16 = 0x2000;
+const IPV4_FRAGMENT_OFFSET_MASK: u16 = 0x1fff;
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_IGMP: u8 = 2;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_FRAGMENT: u8 = 44;
+const IPPROTO_ICMPV6: u8 = 58;
+const IPPROTO_DSTOPTS: u8 = 60;
+
+// Number of IPv6 extension headers walked while looking for a fragment header
+const MAX_IPV6_EXT_HEADERS: usize = 4;
+
+const IGMP_V1_MEMBERSHIP_REPORT: u8 = 0x12;
+const IGMP_V2_MEMBERSHIP_REPORT: u8 = 0x16;
+const IGMP_V2_LEAVE_GROUP: u8 = 0x17;
+const IGMP_V3_MEMBERSHIP_REPORT: u8 = 0x22;
+const MLD_V1_LISTENER_REPORT: u8 = 131;
+const MLD_V1_LISTENER_DONE: u8 = 132;
+const MLD_V2_LISTENER_REPORT: u8 = 143;
+
+// IGMPv3 and MLDv2 group record types (RFC 3376 section 4.2.12, RFC 3810 section 5.2.12)
+const GROUP_RECORD_MODE_IS_INCLUDE: u8 = 1;
+const GROUP_RECORD_CHANGE_TO_INCLUDE: u8 = 3;
+
+// Width of the time buckets used for microburst detection. Userspace overrides this at load time.
+#[no_mangle]
+static BURST_BUCKET_NS: u64 = 1_000_000;
+
+// One in this many frames is sampled to userspace, with 0 disabling sampling. Userspace overrides
+// this at load time.
+#[no_mangle]
+static SAMPLE_RATE: u32 = 0;
+
+#[map]
+static PACKET_SAMPLES: RingBuf = RingBuf::with_byte_size(PACKET_SAMPLES_BYTE_SIZE, 0);
+
+#[map]
+static BURST_EPOCH: Array<u64> = Array::with_max_entries(1, 0);
 
 #[map]
-static IF_RX_COUNT: PerCpuHashMap<u32, Counter> =
+static IF_BURST_RX: PerCpuHashMap<u32, BurstCounter> =
     PerCpuHashMap::with_max_entries(MAX_NUM_INTERFACES, 0);
 
+#[map]
+static IF_LAST_RX_NS: PerCpuHashMap<u32, u64> =
+    PerCpuHashMap::with_max_entries(MAX_NUM_INTERFACES, 0);
+
+#[map]
+static IF_IAT_HIST: PerCpuHashMap<u32, InterArrivalHistogram> =
+    PerCpuHashMap::with_max_entries(MAX_NUM_INTERFACES, 0);
+
+// Histograms are too large to build on the eBPF stack, so new map entries are copied from here
+static EMPTY_IAT_HIST: InterArrivalHistogram = InterArrivalHistogram::new();
+
+// Pinned by name, as is SMAC_RX_COUNT, so that a run with --pin keeps counting into the maps of
+// the earlier one
+#[map]
+static IF_RX_COUNT: PerCpuHashMap<u32, Counter> = PerCpuHashMap::pinned(MAX_NUM_INTERFACES, 0);
+
+#[map]
+static IF_RXQ_RX_COUNT: PerCpuHashMap<RxQueueKey, Counter> =
+    PerCpuHashMap::with_max_entries(MAX_NUM_RX_QUEUES, 0);
+
 #[map]
 static SMAC_RX_COUNT: LruPerCpuHashMap<[u8; 6], Counter> =
-    LruPerCpuHashMap::with_max_entries(MAX_NUM_MAC_ADDRS, 0);
+    LruPerCpuHashMap::pinned(MAX_NUM_MAC_ADDRS, 0);
+
+#[map]
+static IP_FRAG_RX_COUNT: PerCpuHashMap<u32, IpFragmentCounter> =
+    PerCpuHashMap::with_max_entries(MAX_NUM_INTERFACES, 0);
+
+#[map]
+static HOP_LIMIT_RX_COUNT: LruPerCpuHashMap<HopLimitKey, u64> =
+    LruPerCpuHashMap::with_max_entries(MAX_NUM_HOP_LIMIT_BUCKETS, 0);
+
+#[map]
+static MCAST_GROUP_RX_COUNT: LruPerCpuHashMap<MulticastGroupKey, Counter> =
+    LruPerCpuHashMap::with_max_entries(MAX_NUM_MULTICAST_GROUPS, 0);
+
+#[map]
+static MCAST_GROUP_MEMBERS: LruHashMap<MulticastMemberKey, MulticastMembership> =
+    LruHashMap::with_max_entries(MAX_NUM_MULTICAST_MEMBERS, 0);
+
+/// XDP programs which were attached to the interfaces before tsndt, indexed by interface index
+#[map]
+static CHAINED_PROGRAMS: ProgramArray =
+    ProgramArray::with_max_entries(CHAINED_PROGRAMS_MAX_ENTRIES, 0);
 
 #[xdp]
 pub fn xdp_tsndt(ctx: XdpContext) -> u32 {
-    match unsafe { try_xdp_tsndt(ctx) } {
+    let ret = match unsafe { try_xdp_tsndt(&ctx) } {
         Ok(ret) => ret,
         Err(_) => xdp_action::XDP_ABORTED,
-    }
+    };
+
+    // A program displaced from the interface decides the fate of the frame, as if it was still
+    // attached. The tail call only returns if no program is chained on the interface.
+    let index = unsafe { (*ctx.ctx).ingress_ifindex };
+    let _ = unsafe { CHAINED_PROGRAMS.tail_call(&ctx, index) };
+    ret
 }
 
-unsafe fn try_xdp_tsndt(ctx: XdpContext) -> Result<u32, u32> {
+unsafe fn try_xdp_tsndt(ctx: &XdpContext) -> Result<u32, u32> {
     // Using a modified version of Aya for this, but I've asked about it
     // See https://github.com/aya-rs/aya/discussions/1130
     let index = ctx.ingress_ifindex() as u32;
@@ -52,13 +165,40 @@ unsafe fn try_xdp_tsndt(ctx: XdpContext) -> Result<u32, u32> {
                 },
                 0,
             );
-            if let Err(e) = res {
-                error!(&ctx, "Failed to insert new ingress counter values");
-                return Err(e as u32);
+            // Counting is best effort, so a full map must not drop the frame
+            if res.is_err() {
+                error!(ctx, "Failed to insert new ingress counter values");
+            }
+        }
+
+        track_burst(index, packet_byte_count);
+        track_inter_arrival(index);
+        sample_packet(ctx, index);
+
+        let rx_queue_key = RxQueueKey {
+            if_index: index,
+            rx_queue: ctx.rx_queue_index(),
+        };
+        let counter_opt = IF_RXQ_RX_COUNT.get_ptr_mut(&rx_queue_key);
+        if let Some(counter) = counter_opt {
+            (*counter).packets += 1;
+            (*counter).bytes += packet_byte_count;
+        } else {
+            let res = IF_RXQ_RX_COUNT.insert(
+                &rx_queue_key,
+                &Counter {
+                    packets: 1,
+                    bytes: packet_byte_count,
+                },
+                0,
+            );
+            // Counting is best effort, so a full map must not drop the frame
+            if res.is_err() {
+                error!(ctx, "Failed to insert new ingress RX queue counter values");
             }
         }
 
-        let tmp = ptr_at(&ctx, 0);
+        let tmp = ptr_at(ctx, 0);
         let eth_hdr: *const EthHdr = if tmp.is_ok() {
             tmp.unwrap()
         } else {
@@ -82,17 +222,510 @@ unsafe fn try_xdp_tsndt(ctx: XdpContext) -> Result<u32, u32> {
             );
             if let Err(e) = res {
                 error!(
-                    &ctx,
+                    ctx,
                     "Failed to insert new ingress source MAC packet counter value"
                 );
                 return Err(e as u32);
             }
         }
+
+        // Network layer accounting is best effort: frames which cannot be parsed are still passed
+        count_network_layer(ctx, index, packet_byte_count);
     }
 
     Ok(xdp_action::XDP_PASS)
 }
 
+/// Accumulates the frame into the current burst bucket of the interface, and raises the peak
+/// for the current read epoch if the bucket is now the busiest one seen.
+#[inline(always)]
+unsafe fn track_burst(if_index: u32, packet_byte_count: u64) {
+    let bucket_ns = core::ptr::read_volatile(&BURST_BUCKET_NS);
+    if bucket_ns == 0 {
+        return;
+    }
+
+    let epoch = match BURST_EPOCH.get(0) {
+        Some(epoch) => *epoch,
+        None => 0,
+    };
+    let now_ns = bpf_ktime_get_ns();
+    let bucket_start_ns = now_ns - (now_ns % bucket_ns);
+
+    let counter = match IF_BURST_RX.get_ptr_mut(&if_index) {
+        Some(counter) => counter,
+        None => {
+            let res = IF_BURST_RX.insert(
+                &if_index,
+                &BurstCounter {
+                    epoch,
+                    bucket_start_ns,
+                    ..Default::default()
+                },
+                0,
+            );
+            if res.is_err() {
+                return;
+            }
+            match IF_BURST_RX.get_ptr_mut(&if_index) {
+                Some(counter) => counter,
+                None => return,
+            }
+        }
+    };
+
+    // Userspace advances the epoch each time it reads the peaks
+    if (*counter).epoch != epoch {
+        (*counter).epoch = epoch;
+        (*counter).peak_packets = 0;
+        (*counter).peak_bytes = 0;
+    }
+
+    if (*counter).bucket_start_ns != bucket_start_ns {
+        (*counter).bucket_start_ns = bucket_start_ns;
+        (*counter).bucket_packets = 0;
+        (*counter).bucket_bytes = 0;
+    }
+
+    (*counter).bucket_packets += 1;
+    (*counter).bucket_bytes += packet_byte_count;
+    if (*counter).bucket_packets > (*counter).peak_packets {
+        (*counter).peak_packets = (*counter).bucket_packets;
+    }
+    if (*counter).bucket_bytes > (*counter).peak_bytes {
+        (*counter).peak_bytes = (*counter).bucket_bytes;
+    }
+}
+
+/// Copies the start of one in every `SAMPLE_RATE` frames into the packet sample ring buffer
+#[inline(always)]
+unsafe fn sample_packet(ctx: &XdpContext, if_index: u32) {
+    let sample_rate = core::ptr::read_volatile(&SAMPLE_RATE);
+    if sample_rate == 0 || bpf_get_prandom_u32() % sample_rate != 0 {
+        return;
+    }
+
+    let packet_len = (ctx.data_end() - ctx.data()) as u32;
+    let captured_len = if packet_len < PACKET_SAMPLE_MAX_LEN as u32 {
+        packet_len
+    } else {
+        PACKET_SAMPLE_MAX_LEN as u32
+    };
+    if captured_len == 0 {
+        return;
+    }
+
+    // Samples are dropped rather than blocking when userspace falls behind
+    let Some(mut entry) = PACKET_SAMPLES.reserve::<PacketSample>(0) else {
+        return;
+    };
+    let sample = entry.as_mut_ptr();
+    (*sample).timestamp_ns = bpf_ktime_get_ns();
+    (*sample).if_index = if_index;
+    (*sample).packet_len = packet_len;
+    (*sample).captured_len = captured_len;
+    let res = bpf_xdp_load_bytes(
+        ctx.ctx,
+        0,
+        (*sample).data.as_mut_ptr() as *mut c_void,
+        captured_len,
+    );
+    if res != 0 {
+        entry.discard(0);
+        return;
+    }
+    entry.submit(0);
+}
+
+/// Records the gap since the previous frame received on the same interface and CPU in a log2
+/// histogram
+#[inline(always)]
+unsafe fn track_inter_arrival(if_index: u32) {
+    let now_ns = bpf_ktime_get_ns();
+    let prev_ns = match IF_LAST_RX_NS.get_ptr_mut(&if_index) {
+        Some(last_rx_ns) => {
+            let prev_ns = *last_rx_ns;
+            *last_rx_ns = now_ns;
+            prev_ns
+        }
+        None => {
+            // There is no gap to record for the first frame seen on this CPU
+            let _ = IF_LAST_RX_NS.insert(&if_index, &now_ns, 0);
+            return;
+        }
+    };
+
+    let histogram = match IF_IAT_HIST.get_ptr_mut(&if_index) {
+        Some(histogram) => histogram,
+        None => {
+            if IF_IAT_HIST.insert(&if_index, &EMPTY_IAT_HIST, 0).is_err() {
+                return;
+            }
+            match IF_IAT_HIST.get_ptr_mut(&if_index) {
+                Some(histogram) => histogram,
+                None => return,
+            }
+        }
+    };
+
+    let bucket = log2_bucket(now_ns.saturating_sub(prev_ns));
+    (*histogram).buckets[bucket & (INTER_ARRIVAL_NUM_BUCKETS - 1)] += 1;
+}
+
+/// Maps a gap to its inter-arrival histogram bucket: 0 for no gap, otherwise one more than the
+/// position of the highest set bit, saturating at the last bucket
+#[inline(always)]
+fn log2_bucket(gap_ns: u64) -> usize {
+    if gap_ns == 0 {
+        return 0;
+    }
+
+    let mut val = gap_ns;
+    let mut log2 = 0;
+    if val >> 32 != 0 {
+        val >>= 32;
+        log2 += 32;
+    }
+    if val >> 16 != 0 {
+        val >>= 16;
+        log2 += 16;
+    }
+    if val >> 8 != 0 {
+        val >>= 8;
+        log2 += 8;
+    }
+    if val >> 4 != 0 {
+        val >>= 4;
+        log2 += 4;
+    }
+    if val >> 2 != 0 {
+        val >>= 2;
+        log2 += 2;
+    }
+    if val >> 1 != 0 {
+        log2 += 1;
+    }
+
+    let bucket = log2 + 1;
+    if bucket >= INTER_ARRIVAL_NUM_BUCKETS {
+        INTER_ARRIVAL_NUM_BUCKETS - 1
+    } else {
+        bucket
+    }
+}
+
+/// Locates the network layer header, skipping over up to one 802.1Q or 802.1ad tag. Returns the
+/// EtherType of the network layer along with the offset of its header.
+#[inline(always)]
+unsafe fn l3_offset(ctx: &XdpContext) -> Result<(u16, usize), ()> {
+    let ether_type = u16::from_be_bytes(*ptr_at::<[u8; 2]>(ctx, 12)?);
+    if ether_type == ETH_P_8021Q || ether_type == ETH_P_8021AD {
+        let inner_ether_type = u16::from_be_bytes(*ptr_at::<[u8; 2]>(ctx, 16)?);
+        Ok((inner_ether_type, ETH_HDR_LEN + VLAN_HDR_LEN))
+    } else {
+        Ok((ether_type, ETH_HDR_LEN))
+    }
+}
+
+#[inline(always)]
+fn ipv4_mapped(addr: [u8; 4]) -> [u8; 16] {
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, addr[0], addr[1], addr[2], addr[3],
+    ]
+}
+
+#[inline(always)]
+unsafe fn count_network_layer(ctx: &XdpContext, if_index: u32, packet_byte_count: u64) {
+    let Ok((ether_type, offset)) = l3_offset(ctx) else {
+        return;
+    };
+
+    match ether_type {
+        ETH_P_IPV4 => {
+            let _ = count_ipv4_diagnostics(ctx, if_index, offset);
+            let _ = count_ipv4_multicast(ctx, if_index, offset, packet_byte_count);
+        }
+        ETH_P_IPV6 => {
+            let _ = count_ipv6_diagnostics(ctx, if_index, offset);
+            let _ = count_ipv6_multicast(ctx, if_index, offset, packet_byte_count);
+        }
+        _ => {}
+    }
+}
+
+#[inline(always)]
+unsafe fn count_ipv4_diagnostics(ctx: &XdpContext, if_index: u32, offset: usize) -> Result<(), ()> {
+    let flags_fragment_offset = u16::from_be_bytes(*ptr_at::<[u8; 2]>(ctx, offset + 6)?);
+    let ttl = *ptr_at::<u8>(ctx, offset + 8)?;
+
+    count_hop_limit(if_index, ttl);
+
+    let more_fragments = flags_fragment_offset & IPV4_MORE_FRAGMENTS != 0;
+    let fragment_offset = flags_fragment_offset & IPV4_FRAGMENT_OFFSET_MASK;
+    if !more_fragments && fragment_offset == 0 {
+        return Ok(());
+    }
+
+    let counter = fragment_counter(if_index).ok_or(())?;
+    if fragment_offset == 0 {
+        (*counter).ipv4_first += 1;
+    } else if more_fragments {
+        (*counter).ipv4_middle += 1;
+    } else {
+        (*counter).ipv4_last += 1;
+    }
+
+    Ok(())
+}
+
+#[inline(always)]
+unsafe fn count_ipv6_diagnostics(ctx: &XdpContext, if_index: u32, offset: usize) -> Result<(), ()> {
+    let mut next_header = *ptr_at::<u8>(ctx, offset + 6)?;
+    let hop_limit = *ptr_at::<u8>(ctx, offset + 7)?;
+
+    count_hop_limit(if_index, hop_limit);
+
+    let mut ext_offset = offset + IPV6_HDR_LEN;
+    for _ in 0..MAX_IPV6_EXT_HEADERS {
+        match next_header {
+            IPPROTO_FRAGMENT => {
+                let counter = fragment_counter(if_index).ok_or(())?;
+                (*counter).ipv6 += 1;
+                return Ok(());
+            }
+            IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                if ext_offset > MAX_HEADER_OFFSET {
+                    return Ok(());
+                }
+                next_header = *ptr_at::<u8>(ctx, ext_offset)?;
+                let ext_header_len = *ptr_at::<u8>(ctx, ext_offset + 1)? as usize;
+                ext_offset += (ext_header_len + 1) * 8;
+            }
+            _ => return Ok(()),
+        }
+    }
+
+    Ok(())
+}
+
+#[inline(always)]
+unsafe fn fragment_counter(if_index: u32) -> Option<*mut IpFragmentCounter> {
+    if let Some(counter) = IP_FRAG_RX_COUNT.get_ptr_mut(&if_index) {
+        return Some(counter);
+    }
+
+    IP_FRAG_RX_COUNT
+        .insert(&if_index, &IpFragmentCounter::default(), 0)
+        .ok()?;
+    IP_FRAG_RX_COUNT.get_ptr_mut(&if_index)
+}
+
+#[inline(always)]
+unsafe fn count_hop_limit(if_index: u32, hop_limit: u8) {
+    let key = HopLimitKey {
+        if_index,
+        hop_limit: hop_limit as u32,
+    };
+    if let Some(count) = HOP_LIMIT_RX_COUNT.get_ptr_mut(&key) {
+        *count += 1;
+    } else {
+        let _ = HOP_LIMIT_RX_COUNT.insert(&key, &1, 0);
+    }
+}
+
+#[inline(always)]
+unsafe fn count_ipv4_multicast(
+    ctx: &XdpContext,
+    if_index: u32,
+    offset: usize,
+    packet_byte_count: u64,
+) -> Result<(), ()> {
+    let version_ihl = *ptr_at::<u8>(ctx, offset)?;
+    let header_len = ((version_ihl & 0x0f) as usize) * 4;
+    let protocol = *ptr_at::<u8>(ctx, offset + 9)?;
+    let src_addr = *ptr_at::<[u8; 4]>(ctx, offset + 12)?;
+    let dst_addr = *ptr_at::<[u8; 4]>(ctx, offset + 16)?;
+
+    // 224.0.0.0/4
+    if dst_addr[0] & 0xf0 != 0xe0 {
+        return Ok(());
+    }
+
+    count_group_traffic(if_index, ipv4_mapped(dst_addr), packet_byte_count);
+
+    if protocol != IPPROTO_IGMP {
+        return Ok(());
+    }
+
+    let reporter = ipv4_mapped(src_addr);
+    let igmp_offset = offset + header_len;
+    let igmp_type = *ptr_at::<u8>(ctx, igmp_offset)?;
+    match igmp_type {
+        IGMP_V1_MEMBERSHIP_REPORT | IGMP_V2_MEMBERSHIP_REPORT | IGMP_V2_LEAVE_GROUP => {
+            let group = *ptr_at::<[u8; 4]>(ctx, igmp_offset + 4)?;
+            let (report_kind, state) = match igmp_type {
+                IGMP_V1_MEMBERSHIP_REPORT => {
+                    (MULTICAST_REPORT_IGMP_V1, MULTICAST_MEMBERSHIP_JOINED)
+                }
+                IGMP_V2_MEMBERSHIP_REPORT => {
+                    (MULTICAST_REPORT_IGMP_V2, MULTICAST_MEMBERSHIP_JOINED)
+                }
+                _ => (MULTICAST_REPORT_IGMP_V2, MULTICAST_MEMBERSHIP_LEFT),
+            };
+            record_membership(if_index, ipv4_mapped(group), reporter, report_kind, state);
+        }
+        IGMP_V3_MEMBERSHIP_REPORT => {
+            let num_records = u16::from_be_bytes(*ptr_at::<[u8; 2]>(ctx, igmp_offset + 6)?);
+            let mut record_offset = igmp_offset + 8;
+            for record_id in 0..MAX_MULTICAST_GROUP_RECORDS {
+                if record_id >= num_records as usize || record_offset > MAX_HEADER_OFFSET {
+                    break;
+                }
+                let record_type = *ptr_at::<u8>(ctx, record_offset)?;
+                let aux_data_len = *ptr_at::<u8>(ctx, record_offset + 1)? as usize;
+                let num_sources = u16::from_be_bytes(*ptr_at::<[u8; 2]>(ctx, record_offset + 2)?);
+                let group = *ptr_at::<[u8; 4]>(ctx, record_offset + 4)?;
+                let state = group_record_state(record_type, num_sources);
+                record_membership(
+                    if_index,
+                    ipv4_mapped(group),
+                    reporter,
+                    MULTICAST_REPORT_IGMP_V3,
+                    state,
+                );
+                record_offset += 8 + (num_sources as usize) * 4 + aux_data_len * 4;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[inline(always)]
+unsafe fn count_ipv6_multicast(
+    ctx: &XdpContext,
+    if_index: u32,
+    offset: usize,
+    packet_byte_count: u64,
+) -> Result<(), ()> {
+    let mut next_header = *ptr_at::<u8>(ctx, offset + 6)?;
+    let src_addr = *ptr_at::<[u8; 16]>(ctx, offset + 8)?;
+    let dst_addr = *ptr_at::<[u8; 16]>(ctx, offset + 24)?;
+
+    // ff00::/8
+    if dst_addr[0] != 0xff {
+        return Ok(());
+    }
+
+    count_group_traffic(if_index, dst_addr, packet_byte_count);
+
+    // MLD messages are sent with a hop-by-hop router alert option ahead of the ICMPv6 header
+    let mut icmp_offset = offset + IPV6_HDR_LEN;
+    if next_header == IPPROTO_HOPOPTS {
+        next_header = *ptr_at::<u8>(ctx, icmp_offset)?;
+        let ext_header_len = *ptr_at::<u8>(ctx, icmp_offset + 1)? as usize;
+        icmp_offset += (ext_header_len + 1) * 8;
+    }
+
+    if next_header != IPPROTO_ICMPV6 {
+        return Ok(());
+    }
+
+    let icmp_type = *ptr_at::<u8>(ctx, icmp_offset)?;
+    match icmp_type {
+        MLD_V1_LISTENER_REPORT | MLD_V1_LISTENER_DONE => {
+            let group = *ptr_at::<[u8; 16]>(ctx, icmp_offset + 8)?;
+            let state = if icmp_type == MLD_V1_LISTENER_REPORT {
+                MULTICAST_MEMBERSHIP_JOINED
+            } else {
+                MULTICAST_MEMBERSHIP_LEFT
+            };
+            record_membership(if_index, group, src_addr, MULTICAST_REPORT_MLD_V1, state);
+        }
+        MLD_V2_LISTENER_REPORT => {
+            let num_records = u16::from_be_bytes(*ptr_at::<[u8; 2]>(ctx, icmp_offset + 6)?);
+            let mut record_offset = icmp_offset + 8;
+            for record_id in 0..MAX_MULTICAST_GROUP_RECORDS {
+                if record_id >= num_records as usize || record_offset > MAX_HEADER_OFFSET {
+                    break;
+                }
+                let record_type = *ptr_at::<u8>(ctx, record_offset)?;
+                let aux_data_len = *ptr_at::<u8>(ctx, record_offset + 1)? as usize;
+                let num_sources = u16::from_be_bytes(*ptr_at::<[u8; 2]>(ctx, record_offset + 2)?);
+                let group = *ptr_at::<[u8; 16]>(ctx, record_offset + 4)?;
+                let state = group_record_state(record_type, num_sources);
+                record_membership(if_index, group, src_addr, MULTICAST_REPORT_MLD_V2, state);
+                record_offset += 20 + (num_sources as usize) * 16 + aux_data_len * 4;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// An INCLUDE record with an empty source list is how IGMPv3 and MLDv2 hosts leave a group. Any
+/// other record means that the host still wants at least some traffic sent to the group.
+#[inline(always)]
+fn group_record_state(record_type: u8, num_sources: u16) -> u32 {
+    if (record_type == GROUP_RECORD_MODE_IS_INCLUDE
+        || record_type == GROUP_RECORD_CHANGE_TO_INCLUDE)
+        && num_sources == 0
+    {
+        MULTICAST_MEMBERSHIP_LEFT
+    } else {
+        MULTICAST_MEMBERSHIP_JOINED
+    }
+}
+
+#[inline(always)]
+unsafe fn count_group_traffic(if_index: u32, group: [u8; 16], packet_byte_count: u64) {
+    let key = MulticastGroupKey { if_index, group };
+    if let Some(counter) = MCAST_GROUP_RX_COUNT.get_ptr_mut(&key) {
+        (*counter).packets += 1;
+        (*counter).bytes += packet_byte_count;
+    } else {
+        let _ = MCAST_GROUP_RX_COUNT.insert(
+            &key,
+            &Counter {
+                packets: 1,
+                bytes: packet_byte_count,
+            },
+            0,
+        );
+    }
+}
+
+#[inline(always)]
+unsafe fn record_membership(
+    if_index: u32,
+    group: [u8; 16],
+    reporter: [u8; 16],
+    report_kind: u32,
+    state: u32,
+) {
+    let key = MulticastMemberKey {
+        if_index,
+        group,
+        reporter,
+    };
+    let report_count = match MCAST_GROUP_MEMBERS.get(&key) {
+        Some(membership) => membership.report_count + 1,
+        None => 1,
+    };
+    let _ = MCAST_GROUP_MEMBERS.insert(
+        &key,
+        &MulticastMembership {
+            last_report_ns: bpf_ktime_get_ns(),
+            report_count,
+            report_kind,
+            state,
+        },
+        0,
+    );
+}
+
 #[inline(always)]
 unsafe fn ptr_at<T>(ctx: &XdpContext, offset: usize) -> Result<*const T, ()> {
     let start = ctx.data();
@@ -0,0 +1,216 @@
+// Times the ticks of the Ethernet context against the per-CPU counters of thousands of source MAC
+// addresses, as on a busy segment with receive side scaling, without needing root, an interface
+// or traffic. Each address receives frames on every CPU at every tick, so none of them goes idle
+// and the series of those which keep a history grow. Run with `cargo bench --bench ethernet_tick`.
+
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{eyre, Result};
+use network_interface::NetworkInterface;
+use tsndt::bench::{DataSource, EthernetContext, Settings, TsndtContext};
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+};
+
+const NUM_MACS: u32 = 8192;
+const NUM_TICKS: u64 = 1000;
+// Numbers of CPUs that the frames are spread across
+const NUM_CPUS: [usize; 4] = [1, 4, 16, 64];
+
+/// Holds the per-CPU counters of the source MAC addresses, like the eBPF map does, and counts
+/// frames for every address on every CPU at each tick
+struct SimulatedSource {
+    interfaces: Vec<NetworkInterface>,
+    mac_counters: Vec<([u8; 6], Vec<Counter>)>,
+}
+
+impl SimulatedSource {
+    fn new(num_macs: u32, num_cpus: usize) -> Self {
+        let interface = NetworkInterface {
+            name: String::from("bench0"),
+            addr: Vec::new(),
+            mac_addr: None,
+            index: 1,
+            internal: false,
+        };
+        // Locally administered addresses, numbered from 02:00:00:00:00:00
+        let mac_counters = (0..num_macs)
+            .map(|index| {
+                let [a, b, c, d] = index.to_be_bytes();
+                let counters = vec![
+                    Counter {
+                        bytes: 0,
+                        packets: 0,
+                    };
+                    num_cpus
+                ];
+                ([0x02, 0, a, b, c, d], counters)
+            })
+            .collect();
+
+        Self {
+            interfaces: vec![interface],
+            mac_counters,
+        }
+    }
+}
+
+impl DataSource for SimulatedSource {
+    fn interfaces(&self) -> &[NetworkInterface] {
+        &self.interfaces
+    }
+
+    fn on_tick(&mut self) -> Result<bool> {
+        for (index, (_, counters)) in self.mac_counters.iter_mut().enumerate() {
+            for (cpu_id, counter) in counters.iter_mut().enumerate() {
+                let packets = 1 + (index + cpu_id) as u32 % 8;
+                counter.packets += packets;
+                counter.bytes += packets as u64 * (64 + index as u64 % 1400);
+            }
+        }
+        Ok(true)
+    }
+
+    fn attach(&mut self, _interface: &NetworkInterface) -> Result<()> {
+        Ok(())
+    }
+
+    fn detach(&mut self, _interface: &NetworkInterface) -> Result<()> {
+        Ok(())
+    }
+
+    fn interface_counters(&self, _if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()> {
+        visit(&[]);
+        Ok(())
+    }
+
+    fn rx_queue_counters(&self, _visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()> {
+        Ok(())
+    }
+
+    fn burst_counters(
+        &self,
+        _if_index: u32,
+        _visit: &mut dyn FnMut(&[BurstCounter]),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_burst_epoch(&mut self, _epoch: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn inter_arrival_histograms(
+        &self,
+        _if_index: u32,
+        _visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()> {
+        for (src_mac, counters) in &self.mac_counters {
+            visit(src_mac, counters);
+        }
+        Ok(())
+    }
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()> {
+        self.mac_counters
+            .retain(|(other_src_mac, _)| other_src_mac != src_mac);
+        Ok(())
+    }
+
+    fn fragment_counters(
+        &self,
+        _if_index: u32,
+        _visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn hop_limit_counts(&self, _visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()> {
+        Ok(())
+    }
+
+    fn multicast_group_counters(
+        &self,
+        _visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove_multicast_group_counters(&mut self, _key: &MulticastGroupKey) -> Result<()> {
+        Ok(())
+    }
+
+    fn multicast_members(
+        &self,
+        _visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove_multicast_member(&mut self, _key: &MulticastMemberKey) -> Result<()> {
+        Ok(())
+    }
+
+    fn packet_samples(&mut self, _visit: &mut dyn FnMut(&PacketSample)) {}
+
+    fn now_ns(&self) -> u64 {
+        0
+    }
+
+    fn realtime_offset_ns(&self) -> u64 {
+        0
+    }
+}
+
+/// Runs the given number of ticks over the given number of source MAC addresses spread across
+/// the given number of CPUs, then prints how long the ticks took
+fn run(settings: &Settings, num_macs: u32, num_cpus: usize, num_ticks: u64) -> Result<()> {
+    if num_macs == 0 || num_cpus == 0 || num_ticks == 0 {
+        return Err(eyre!(
+            "The benchmark needs at least one MAC address, one CPU and one tick"
+        ));
+    }
+
+    let mut source = SimulatedSource::new(num_macs, num_cpus);
+    let mut ethernet = EthernetContext::new(&source, settings);
+
+    let mut tick_durations = Vec::with_capacity(num_ticks as usize);
+    for _ in 0..num_ticks {
+        source.on_tick()?;
+
+        let start = Instant::now();
+        ethernet.handle_tick(&mut source)?;
+        tick_durations.push(start.elapsed());
+    }
+
+    tick_durations.sort();
+    let mean = tick_durations.iter().sum::<Duration>() / tick_durations.len() as u32;
+    let percentile = |fraction: f64| {
+        tick_durations[((tick_durations.len() - 1) as f64 * fraction).round() as usize]
+    };
+    println!(
+        "Ethernet context tick with {} source MAC addresses on {} CPUs over {} ticks:",
+        num_macs, num_cpus, num_ticks
+    );
+    println!(
+        "  mean {:?}, median {:?}, p99 {:?}, max {:?}",
+        mean,
+        percentile(0.5),
+        percentile(0.99),
+        percentile(1.0)
+    );
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let settings = Settings::from_args(&[]);
+    for num_cpus in NUM_CPUS {
+        run(&settings, NUM_MACS, num_cpus, NUM_TICKS)?;
+    }
+    Ok(())
+}
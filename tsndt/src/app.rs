@@ -1,10 +1,12 @@
 use std::{
+    fs::File,
     path::PathBuf,
+    sync::OnceLock,
     time::{Duration, Instant},
 };
 
 use color_eyre::eyre::Result;
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{self, Event};
 use directories::ProjectDirs;
 use lazy_static::lazy_static;
 use ratatui::{
@@ -16,23 +18,38 @@ use ratatui::{
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{self, layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
-use crate::context::{
-    ethernet::EthernetContext, network_interface::NetworkInterfaceContext, ContextId, TsndtContext,
+use crate::{
+    cli::Cli,
+    context::{
+        diagnostics::DiagnosticsContext, ethernet::EthernetContext, multicast::MulticastContext,
+        network_interface::NetworkInterfaceContext, packets::PacketsContext, ContextKind,
+        TsndtContext,
+    },
+    export::{self, ExportFormat, TickTimestamps},
+    keymap::{Action, Keymap},
+    metrics::MetricsExporter,
+    settings::Settings,
+    source::DataSource,
+    teardown,
 };
 
-const DEFAULT_CONTEXT_ID: ContextId = 0;
-pub(crate) const TICK_RATE_MS: u64 = 200;
-
 lazy_static! {
     pub static ref PROJECT_NAME: String = env!("CARGO_CRATE_NAME").to_uppercase().to_string();
     pub static ref DATA_FOLDER: Option<PathBuf> =
         std::env::var(format!("{}_DATA", PROJECT_NAME.clone()))
             .ok()
             .map(PathBuf::from);
+    pub static ref CONFIG_FOLDER: Option<PathBuf> =
+        std::env::var(format!("{}_CONFIG", PROJECT_NAME.clone()))
+            .ok()
+            .map(PathBuf::from);
     pub static ref LOG_ENV: String = format!("{}_LOGLEVEL", PROJECT_NAME.clone());
     pub static ref LOG_FILE: String = format!("{}.log", env!("CARGO_PKG_NAME"));
 }
 
+// Another handle on the log file, for flushing it to disk on the way out
+static LOG_FILE_HANDLE: OnceLock<File> = OnceLock::new();
+
 fn project_directory() -> Option<ProjectDirs> {
     ProjectDirs::from("com", "gth828r", env!("CARGO_PKG_NAME"))
 }
@@ -48,17 +65,40 @@ pub fn get_data_dir() -> PathBuf {
     directory
 }
 
-pub fn initialize_logging() -> Result<()> {
+/// Where config.toml is looked for
+pub fn get_config_dir() -> PathBuf {
+    if let Some(s) = CONFIG_FOLDER.clone() {
+        s
+    } else if let Some(proj_dirs) = project_directory() {
+        proj_dirs.config_local_dir().to_path_buf()
+    } else {
+        PathBuf::from(".").join(".config")
+    }
+}
+
+/// Logs to a file in the data directory. The log level is taken from `log_level` if given, then
+/// from the environment, and otherwise from `config_log_level`, the one in the configuration file.
+pub fn initialize_logging(log_level: Option<&str>, config_log_level: Option<&str>) -> Result<()> {
     let directory = get_data_dir();
     std::fs::create_dir_all(directory.clone())?;
     let log_path = directory.join(LOG_FILE.clone());
-    let log_file = std::fs::File::create(log_path)?;
-    std::env::set_var(
-        "RUST_LOG",
-        std::env::var("RUST_LOG")
+    let log_file = File::create(log_path)?;
+    let _ = LOG_FILE_HANDLE.set(log_file.try_clone()?);
+    // A bare level applies to tsndt only, while anything else is a full filter directive
+    let to_filter = |log_level: &str| match log_level.parse::<tracing::Level>() {
+        Ok(level) => format!("{}={}", env!("CARGO_CRATE_NAME"), level),
+        Err(_) => log_level.to_string(),
+    };
+    let log_filter = match log_level {
+        Some(log_level) => to_filter(log_level),
+        None => std::env::var("RUST_LOG")
             .or_else(|_| std::env::var(LOG_ENV.clone()))
-            .unwrap_or_else(|_| format!("{}=info", env!("CARGO_CRATE_NAME"))),
-    );
+            .unwrap_or_else(|_| match config_log_level {
+                Some(config_log_level) => to_filter(config_log_level),
+                None => format!("{}=info", env!("CARGO_CRATE_NAME")),
+            }),
+    };
+    std::env::set_var("RUST_LOG", log_filter);
     let file_subscriber = tracing_subscriber::fmt::layer()
         .with_file(true)
         .with_line_number(true)
@@ -73,6 +113,13 @@ pub fn initialize_logging() -> Result<()> {
     Ok(())
 }
 
+/// Makes sure that everything logged so far is on disk
+pub(crate) fn sync_log() {
+    if let Some(log_file) = LOG_FILE_HANDLE.get() {
+        let _ = log_file.sync_all();
+    }
+}
+
 #[derive(Eq, PartialEq)]
 pub(crate) enum AppRunState {
     Running,
@@ -83,12 +130,19 @@ pub(crate) struct App {
     contexts: Vec<Box<dyn TsndtContext>>,
     selected_context_id: usize,
     run_state: AppRunState,
+    tick_rate: Duration,
+    keymap: Keymap,
+    metrics: Option<MetricsExporter>,
+    tick_timestamps: TickTimestamps,
+    // Format of the time series dumped on exit, if they are
+    export_format: Option<ExportFormat>,
 }
 
 fn draw(
     tab_titles: Vec<String>,
     selected_tab: usize,
     frame: &mut Frame,
+    keymap: &Keymap,
     context_command_help: Vec<String>,
 ) -> Rect {
     // 3 comes from 1 lines of global application commands and
@@ -102,7 +156,7 @@ fn draw(
     .areas(frame.area());
 
     render_tabs(tab_titles, selected_tab, frame, tabs_area);
-    render_commands(frame, commands_area, context_command_help);
+    render_commands(frame, commands_area, keymap, context_command_help);
     context_area
 }
 
@@ -111,8 +165,21 @@ fn render_tabs(tab_titles: Vec<String>, selected_tab: usize, frame: &mut Frame,
     frame.render_widget(tabs, area);
 }
 
-fn render_commands(frame: &mut Frame, commands_area: Rect, context_command_help: Vec<String>) {
-    let application_line = Line::from(vec!["(q) Quit, (←/→): Change contexts".into()]).centered();
+fn render_commands(
+    frame: &mut Frame,
+    commands_area: Rect,
+    keymap: &Keymap,
+    context_command_help: Vec<String>,
+) {
+    let application_line = Line::from(keymap.help(&[
+        (&[Action::Quit], "Quit"),
+        (
+            &[Action::PreviousContext, Action::NextContext],
+            "Change contexts",
+        ),
+        (&[Action::ExportSeries], "Export time series"),
+    ]))
+    .centered();
     let context_lines: Vec<Line<'_>> = context_command_help
         .iter()
         .map(|help_text_line| Line::from(help_text_line.clone()).centered())
@@ -125,24 +192,67 @@ fn render_commands(frame: &mut Frame, commands_area: Rect, context_command_help:
 }
 
 impl App {
-    pub(crate) fn new(bpf: &mut aya::Ebpf) -> Self {
-        let contexts: Vec<Box<dyn TsndtContext>> = vec![
-            Box::new(NetworkInterfaceContext::new(bpf)),
-            Box::new(EthernetContext::new()),
-        ];
+    pub(crate) fn new(
+        source: &dyn DataSource,
+        cli: &Cli,
+        settings: &Settings,
+        metrics: Option<MetricsExporter>,
+    ) -> Result<Self> {
+        let mut contexts: Vec<Box<dyn TsndtContext>> = Vec::new();
+        for context_kind in &settings.contexts {
+            contexts.push(match context_kind {
+                ContextKind::Interfaces => Box::new(NetworkInterfaceContext::new(source, settings)),
+                ContextKind::Ethernet => Box::new(EthernetContext::new(source, settings)),
+                ContextKind::Multicast => Box::new(MulticastContext::new(source, settings)),
+                ContextKind::Diagnostics => Box::new(DiagnosticsContext::new(source, settings)),
+                ContextKind::Packets => Box::new(PacketsContext::new(
+                    source.interfaces().to_vec(),
+                    cli.pcapng.as_deref(),
+                    settings.sample_rate,
+                )?),
+            });
+        }
+        let selected_context_id = settings
+            .contexts
+            .iter()
+            .position(|context_kind| *context_kind == settings.initial_context)
+            .unwrap_or(0);
 
-        Self {
+        Ok(Self {
             contexts,
-            selected_context_id: DEFAULT_CONTEXT_ID,
+            selected_context_id,
             run_state: AppRunState::Running,
+            tick_rate: Duration::from_millis(settings.tick_rate_ms),
+            keymap: settings.keymap.clone(),
+            metrics,
+            tick_timestamps: TickTimestamps::new(settings.history_ticks),
+            export_format: cli.export,
+        })
+    }
+
+    fn export_series(&self, format: ExportFormat) {
+        let contexts = self.contexts.iter().map(|context| context.as_ref());
+        match export::export_series(contexts, &self.tick_timestamps, format) {
+            Ok(export) => export.log(),
+            Err(report) => tracing::warn!("Failed to export time series: {}", report),
         }
     }
 
-    pub(crate) fn run(mut self, bpf: &mut aya::Ebpf, mut terminal: DefaultTerminal) -> Result<()> {
-        let tick_rate = Duration::from_millis(TICK_RATE_MS);
+    pub(crate) fn run(
+        mut self,
+        source: &mut dyn DataSource,
+        mut terminal: DefaultTerminal,
+    ) -> Result<()> {
+        let tick_rate = self.tick_rate;
         let mut last_tick = Instant::now();
         let num_contexts = self.contexts.len();
         while self.run_state == AppRunState::Running {
+            // A signal stops the app the same way that quitting does
+            if teardown::shutdown_requested() {
+                self.run_state = AppRunState::Stopped;
+                break;
+            }
+
             let tab_titles: Vec<String> = self
                 .contexts
                 .iter()
@@ -154,56 +264,81 @@ impl App {
             // The app only handles events and renders the terminal for the active context
             let context = self.contexts.get_mut(selected_tab).unwrap();
 
+            let keymap = &self.keymap;
             terminal.draw(|frame| {
-                let context_command_help = context.get_command_help();
-                let context_area = draw(tab_titles, selected_tab, frame, context_command_help);
+                let mut context_command_help = source.get_command_help(keymap);
+                context_command_help.extend(context.get_command_help(keymap));
+                let context_area = draw(
+                    tab_titles,
+                    selected_tab,
+                    frame,
+                    keymap,
+                    context_command_help,
+                );
                 context.draw(frame, context_area)
             })?;
 
             let timeout = tick_rate.saturating_sub(last_tick.elapsed());
             if event::poll(timeout)? {
-                if let Event::Key(key) = event::read()? {
-                    match key.code {
-                        KeyCode::Char('q') => {
+                // Keys are only handled through the actions that the keymap binds them to
+                let action = match event::read()? {
+                    Event::Key(key) => self.keymap.action(key),
+                    _ => None,
+                };
+                if let Some(action) = action {
+                    match action {
+                        Action::Quit => {
                             self.run_state = AppRunState::Stopped;
                             break;
                         }
-                        KeyCode::Right => {
-                            if key.modifiers.is_empty() {
-                                // modify tab and selected context
-                                let candidate = self.selected_context_id + 1;
-                                if candidate < num_contexts {
-                                    self.selected_context_id += 1;
-                                } else {
-                                    self.selected_context_id = 0;
-                                }
+                        Action::NextContext => {
+                            // modify tab and selected context
+                            let candidate = self.selected_context_id + 1;
+                            if candidate < num_contexts {
+                                self.selected_context_id += 1;
+                            } else {
+                                self.selected_context_id = 0;
                             }
                         }
-                        KeyCode::Left => {
-                            if key.modifiers.is_empty() {
-                                // modify tab and selected context
-                                if self.selected_context_id > 0 {
-                                    self.selected_context_id -= 1;
-                                } else {
-                                    self.selected_context_id = num_contexts - 1;
-                                };
-                            }
+                        Action::PreviousContext => {
+                            // modify tab and selected context
+                            if self.selected_context_id > 0 {
+                                self.selected_context_id -= 1;
+                            } else {
+                                self.selected_context_id = num_contexts - 1;
+                            };
                         }
                         _ => {}
                     }
 
-                    context.handle_key_event(key, bpf)?;
+                    source.handle_action(action);
+                    context.handle_action(action, source)?;
+                    // Exporting covers every context, which the app holds rather than any one of them
+                    if action == Action::ExportSeries {
+                        self.export_series(self.export_format.unwrap_or_default());
+                    }
                 }
             }
             if last_tick.elapsed() >= tick_rate {
-                // Update models at each tick for all contexts, not just the active one
-                for context in self.contexts.iter_mut() {
-                    context.handle_tick(bpf)?;
+                // Update models at each tick for all contexts, not just the active one. A paused
+                // data source holds the contexts still.
+                if source.on_tick()? {
+                    for context in self.contexts.iter_mut() {
+                        context.handle_tick(source)?;
+                    }
+                    self.tick_timestamps.on_tick(source);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.update(source)?;
+                    }
                 }
                 last_tick = Instant::now();
             }
         }
 
+        if let Some(format) = self.export_format {
+            self.export_series(format);
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,257 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand};
+use regex::Regex;
+
+use crate::{
+    context::ContextKind,
+    export::ExportFormat,
+    headless::{HeadlessOptions, OutputFormat},
+    settings::parse_interface_pattern,
+    source::ebpf::{ExistingXdpPolicy, XdpMode, XdpModes},
+};
+
+/// Time-sensitive network debugging tool
+///
+/// Defaults for most options can be set in config.toml, in the tsndt config directory or in the
+/// directory given by TSNDT_CONFIG. The options given here override it.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
+    /// Only collect on interfaces matching this name or regex, which can be given several times
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        value_parser = parse_interface_pattern,
+        conflicts_with = "replay"
+    )]
+    pub(crate) include: Vec<Regex>,
+
+    /// Never collect on interfaces matching this name or regex, which can be given several times
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        value_parser = parse_interface_pattern,
+        conflicts_with = "replay"
+    )]
+    pub(crate) exclude: Vec<Regex>,
+
+    /// Time between updates of the counters and plots [default: 200]
+    #[arg(
+        long,
+        value_name = "MS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub(crate) tick_rate: Option<u64>,
+
+    /// Number of ticks shown in the time series plots [default: 50]
+    #[arg(
+        long,
+        value_name = "TICKS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub(crate) window: Option<u64>,
+
+    /// Number of ticks kept for scrolling back through the time series plots while the display is
+    /// paused, which has to be at least the window [default: 3000]
+    #[arg(
+        long,
+        value_name = "TICKS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub(crate) history: Option<u64>,
+
+    /// Contexts to show, in tab order. All of them are shown by default.
+    #[arg(long, value_name = "CONTEXT", value_delimiter = ',')]
+    pub(crate) contexts: Vec<ContextKind>,
+
+    /// Context selected at startup, which defaults to the first one shown
+    #[arg(long, value_name = "CONTEXT")]
+    pub(crate) start_context: Option<ContextKind>,
+
+    /// Time after which MAC addresses which received nothing are removed from the Ethernet
+    /// context [default: 300]
+    #[arg(long, value_name = "SECONDS")]
+    pub(crate) mac_idle_timeout: Option<u64>,
+
+    /// Width of the time buckets in which the busiest moment of each tick is looked for, to show
+    /// microbursts which the per-tick counts average out [default: 1000]
+    #[arg(
+        long,
+        value_name = "US",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub(crate) burst_bucket: Option<u64>,
+
+    /// Sample one in N received frames to the Packets context and to pcapng, copying the first
+    /// 128 bytes of each. 0 turns sampling off and is the default, since sampling costs time on
+    /// every frame.
+    #[arg(long, value_name = "N")]
+    pub(crate) sample_rate: Option<u32>,
+
+    /// Log level (error, warn, info, debug or trace) or tracing filter directive, overriding
+    /// RUST_LOG and TSNDT_LOGLEVEL
+    #[arg(long, value_name = "LEVEL")]
+    pub(crate) log_level: Option<String>,
+
+    /// Serve the interface and source MAC address counters to Prometheus at
+    /// http://ADDRESS/metrics, with or without the TUI
+    #[arg(long, value_name = "ADDRESS")]
+    pub(crate) metrics_listen: Option<SocketAddr>,
+
+    /// Dump the packet and byte time series of every context which keeps them to files of this
+    /// format in the data directory on exit, logging the contexts left out. The export key dumps
+    /// them in this format too, or as CSV if not given.
+    #[arg(long, value_name = "FORMAT")]
+    pub(crate) export: Option<ExportFormat>,
+
+    /// Record the counters read at every tick to this session file, to be played back later with
+    /// --replay
+    #[arg(long, value_name = "PATH")]
+    pub(crate) record: Option<PathBuf>,
+
+    /// Write the frames sampled at --sample-rate to this pcapng file from startup. Needs the TUI
+    /// and the Packets context.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) pcapng: Option<PathBuf>,
+
+    /// XDP attach mode (native, skb or hw), for all interfaces or for one with IFACE=MODE. Modes
+    /// which fail fall back to native, then skb, then an AF_PACKET socket.
+    #[arg(
+        long = "xdp-mode",
+        value_name = "[IFACE=]MODE",
+        value_parser = parse_xdp_mode,
+        conflicts_with = "replay"
+    )]
+    pub(crate) xdp_modes: Vec<(Option<String>, XdpMode)>,
+
+    /// Chain to XDP programs already attached to the interfaces instead of leaving those
+    /// interfaces to AF_PACKET. Each program is moved to run after xdp_tsndt, and is put back
+    /// when tsndt stops collecting on the interface, or by `tsndt cleanup` if tsndt is killed.
+    #[arg(long, conflicts_with_all = ["force", "replay", "pin"])]
+    pub(crate) chain: bool,
+
+    /// Replace XDP programs already attached to the interfaces. Each program is put back when
+    /// tsndt stops collecting on the interface, or by `tsndt cleanup` if tsndt is killed, but
+    /// sees no frames in the meantime.
+    #[arg(long, conflicts_with_all = ["replay", "pin"])]
+    pub(crate) force: bool,
+
+    /// Pin the interface and MAC address counters and the XDP links under /sys/fs/bpf/tsndt.
+    /// Counting then carries on after exiting, and the next run with --pin continues from the
+    /// pinned counters.
+    #[arg(long, conflicts_with = "replay")]
+    pub(crate) pin: bool,
+
+    /// View the counters pinned by a run with --pin, without attaching anything
+    #[arg(long, conflicts_with_all = ["replay", "pin", "xdp_modes", "chain", "force"])]
+    pub(crate) viewer: bool,
+
+    /// Replay a pcap or pcapng file, or a session recorded with --record, instead of attaching to
+    /// the host interfaces
+    #[arg(long, value_name = "PATH")]
+    pub(crate) replay: Option<PathBuf>,
+
+    /// Replay speed as a multiple of the capture's real time
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value_t = 1.0,
+        requires = "replay"
+    )]
+    pub(crate) replay_speed: f64,
+
+    /// Start the replay paused, to be stepped through one tick at a time
+    #[arg(long, requires = "replay", conflicts_with = "no_tui")]
+    pub(crate) step: bool,
+
+    /// Print the interface and top MAC address rates at each tick instead of showing the TUI
+    #[arg(long, conflicts_with = "pcapng")]
+    pub(crate) no_tui: bool,
+
+    /// Output format without the TUI
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "table",
+        requires = "no_tui"
+    )]
+    pub(crate) output: OutputFormat,
+
+    /// Stop after printing this many samples
+    #[arg(long, value_name = "SAMPLES", requires = "no_tui")]
+    pub(crate) count: Option<u64>,
+
+    /// Stop after this many seconds
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_duration,
+        requires = "no_tui"
+    )]
+    pub(crate) duration: Option<Duration>,
+
+    /// Number of the busiest source MAC addresses printed in each sample
+    #[arg(long, value_name = "N", default_value_t = 5, requires = "no_tui")]
+    pub(crate) top_macs: usize,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Put back the XDP programs that a killed run displaced, remove the pins under
+    /// /sys/fs/bpf/tsndt, which detaches the pinned XDP links, and detach xdp_tsndt from
+    /// interfaces that a crashed run left it attached to
+    Cleanup,
+}
+
+fn parse_xdp_mode(arg: &str) -> Result<(Option<String>, XdpMode), String> {
+    match arg.split_once('=') {
+        Some((interface_name, mode)) => Ok((Some(interface_name.to_string()), mode.parse()?)),
+        None => Ok((None, arg.parse()?)),
+    }
+}
+
+fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let seconds: f64 = arg.parse().map_err(|error| format!("{error}"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|error| format!("{error}"))
+}
+
+impl Cli {
+    pub(crate) fn xdp_modes(&self) -> XdpModes {
+        let mut xdp_modes = XdpModes::default();
+        for (interface_name, xdp_mode) in &self.xdp_modes {
+            match interface_name {
+                Some(interface_name) => {
+                    xdp_modes
+                        .per_interface
+                        .insert(interface_name.clone(), *xdp_mode);
+                }
+                None => xdp_modes.default = *xdp_mode,
+            }
+        }
+        xdp_modes
+    }
+
+    pub(crate) fn headless_options(&self) -> HeadlessOptions {
+        HeadlessOptions {
+            format: self.output,
+            count: self.count,
+            duration: self.duration,
+            top_macs: self.top_macs,
+            export: self.export,
+        }
+    }
+
+    pub(crate) fn existing_xdp_policy(&self) -> ExistingXdpPolicy {
+        if self.chain {
+            ExistingXdpPolicy::Chain
+        } else if self.force {
+            ExistingXdpPolicy::Replace
+        } else {
+            ExistingXdpPolicy::Keep
+        }
+    }
+}
@@ -0,0 +1,96 @@
+// The configuration file, config.toml in the config directory, which lets every machine start tsndt
+// the same way. Everything in it is optional, and command-line options override it.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use color_eyre::eyre::{Context, Result};
+use ratatui::style::Color;
+use serde::Deserialize;
+
+use crate::{app, context::ContextKind};
+
+pub(crate) const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Names or regexes of the interfaces to collect on, as with --include
+    pub(crate) interfaces: Vec<String>,
+    /// Names or regexes of the interfaces never to collect on, as with --exclude
+    pub(crate) exclude_interfaces: Vec<String>,
+    pub(crate) tick_rate_ms: Option<u64>,
+    pub(crate) window_ticks: Option<u64>,
+    pub(crate) history_ticks: Option<u64>,
+    pub(crate) mac_idle_timeout_sec: Option<u64>,
+    pub(crate) burst_bucket_us: Option<u64>,
+    /// One in this many received frames is sampled, as with --sample-rate
+    pub(crate) sample_rate: Option<u32>,
+    pub(crate) contexts: Vec<ContextKind>,
+    pub(crate) start_context: Option<ContextKind>,
+    pub(crate) log_level: Option<String>,
+    pub(crate) plots: PlotsConfig,
+    pub(crate) colors: ColorsConfig,
+    /// Names shown alongside interface names or MAC addresses, keyed by either
+    pub(crate) aliases: HashMap<String, String>,
+    /// Keys of actions, replacing their default keys, such as resize_up = "alt+up"
+    pub(crate) keys: HashMap<String, KeysConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PlotsConfig {
+    /// Whether the time series plots start out autoscaled rather than manually zoomed
+    pub(crate) autoscaling: Option<bool>,
+    /// Upper bound of the packet plots when manually zoomed, before any zooming
+    pub(crate) packet_axis_max: Option<f64>,
+    /// Upper bound of the byte plots when manually zoomed, before any zooming
+    pub(crate) byte_axis_max: Option<f64>,
+    pub(crate) histogram_width_percentage: Option<u16>,
+    pub(crate) byte_counters_height_percentage: Option<u16>,
+    pub(crate) multicast_groups_height_percentage: Option<u16>,
+    pub(crate) hop_limit_height_percentage: Option<u16>,
+}
+
+/// Colors are given by name, as "#rrggbb" or as a terminal color index
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ColorsConfig {
+    /// Border of the plot which the zoom keys apply to
+    pub(crate) zoom_context: Option<Color>,
+    /// Interfaces, addresses and groups which are not being collected on or have gone idle
+    pub(crate) disabled: Option<Color>,
+}
+
+/// One key or several keys for an action, where an empty list leaves the action unbound
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum KeysConfig {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeysConfig {
+    pub(crate) fn keys(&self) -> &[String] {
+        match self {
+            KeysConfig::One(key) => std::slice::from_ref(key),
+            KeysConfig::Many(keys) => keys,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration file from the config directory, if there is one
+    pub(crate) fn load() -> Result<Self> {
+        let path = app::get_config_dir().join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&contents).wrap_err_with(|| format!("failed to parse {}", path.display()))
+    }
+}
@@ -4,23 +4,73 @@
 //  * Network address level monitoring
 //  * etc
 
+use std::fmt;
+
+use clap::ValueEnum;
 use color_eyre::eyre::Result;
-use crossterm::event::KeyEvent;
 use ratatui::{layout::Rect, Frame};
+use serde::Deserialize;
+
+use crate::{
+    export::SeriesPoint,
+    keymap::{Action, Keymap},
+    source::DataSource,
+};
+
+/// The contexts which can be enabled
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ContextKind {
+    Interfaces,
+    Ethernet,
+    Multicast,
+    Diagnostics,
+    Packets,
+}
 
-pub(crate) type ContextId = usize;
+impl ContextKind {
+    /// Every context, in tab order
+    pub(crate) const ALL: [ContextKind; 5] = [
+        ContextKind::Interfaces,
+        ContextKind::Ethernet,
+        ContextKind::Multicast,
+        ContextKind::Diagnostics,
+        ContextKind::Packets,
+    ];
+}
+
+impl fmt::Display for ContextKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => write!(f, "{}", value.get_name()),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
 
-pub(crate) trait TsndtContext {
-    fn handle_key_event(&mut self, key_event: KeyEvent, bpf: &mut aya::Ebpf) -> Result<()>;
+pub trait TsndtContext {
+    fn handle_action(&mut self, action: Action, source: &mut dyn DataSource) -> Result<()>;
 
-    fn handle_tick(&mut self, bpf: &mut aya::Ebpf) -> Result<()>;
+    fn handle_tick(&mut self, source: &mut dyn DataSource) -> Result<()>;
 
     fn draw(&mut self, frame: &mut Frame, context_area: Rect);
 
     fn get_context_name(&self) -> String;
 
-    fn get_command_help(&self) -> Vec<String>;
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String>;
+
+    /// The per-tick packet and byte counts held for the time series plots, or None if the context
+    /// keeps no such series, which exporting then reports
+    fn export_series(&self) -> Option<Vec<SeriesPoint>> {
+        None
+    }
 }
 
+pub(crate) mod diagnostics;
 pub(crate) mod ethernet;
+pub(crate) mod multicast;
 pub(crate) mod network_interface;
+pub(crate) mod packets;
+pub(crate) mod rollup;
+pub(crate) mod series;
+pub(crate) mod time_window;
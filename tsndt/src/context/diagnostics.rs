@@ -0,0 +1,453 @@
+use std::collections::{HashMap, VecDeque};
+
+use color_eyre::eyre::Result;
+use network_interface::NetworkInterface;
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    symbols,
+    text::Span,
+    widgets::{
+        Axis, BarChart, Block, Chart, Dataset, LegendPosition, List, ListDirection, ListItem,
+        ListState,
+    },
+    Frame,
+};
+use tsndt_common::IpFragmentCounter;
+
+use super::{series::Series, time_window::TimeWindow, TsndtContext};
+use crate::{
+    keymap::{Action, Keymap},
+    settings::{Aliases, ColorSettings, Settings},
+    source::DataSource,
+};
+
+const CONTEXT_NAME: &str = "Diagnostics";
+const NUM_FRAGMENT_KINDS: usize = 4;
+const FRAGMENT_KIND_NAMES: [&str; NUM_FRAGMENT_KINDS] =
+    ["IPv4 first", "IPv4 middle", "IPv4 last", "IPv6"];
+const NUM_HOP_LIMIT_VALUES: usize = 256;
+
+type HopLimitCounts = [u64; NUM_HOP_LIMIT_VALUES];
+
+pub(crate) struct DiagnosticsContext {
+    pub(crate) model: DiagnosticsModel,
+    pub(crate) view: DiagnosticsView,
+}
+
+pub(crate) struct DiagnosticsView {
+    interfaces_state: ListState,
+    hop_limit_height_percentage: u16,
+    colors: ColorSettings,
+    aliases: Aliases,
+}
+
+pub(crate) struct DiagnosticsModel {
+    interfaces: Vec<NetworkInterface>,
+    cumul_fragment_counts: HashMap<u32, [u64; NUM_FRAGMENT_KINDS]>,
+    tick_fragment_count_data: HashMap<u32, [Series; NUM_FRAGMENT_KINDS]>,
+    cumul_hop_limit_counts: HashMap<u32, HopLimitCounts>,
+    // Snapshots of the cumulative hop limit counts at each tick of the history, oldest first,
+    // used to compute the histogram of the current window
+    hop_limit_snapshots: HashMap<u32, VecDeque<(f64, HopLimitCounts)>>,
+    tick_count: f64,
+    tick_rate_ms: u64,
+    time_window: TimeWindow,
+}
+
+fn get_fragment_counts(counter: &IpFragmentCounter) -> [u64; NUM_FRAGMENT_KINDS] {
+    [
+        counter.ipv4_first,
+        counter.ipv4_middle,
+        counter.ipv4_last,
+        counter.ipv6,
+    ]
+}
+
+fn get_autoscale_axis_bound(max_val: f64) -> f64 {
+    let mut axis_val = 1.0;
+    let mut val = max_val;
+    while val >= 10.0 {
+        val /= 10.0;
+        axis_val *= 10.0;
+    }
+    axis_val * f64::ceil(val)
+}
+
+impl TsndtContext for DiagnosticsContext {
+    fn get_context_name(&self) -> String {
+        String::from(CONTEXT_NAME)
+    }
+
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String> {
+        vec![
+            keymap.help(&[
+                (
+                    &[Action::SelectPrevious, Action::SelectNext],
+                    "Select interface",
+                ),
+                (
+                    &[Action::ResizeUp, Action::ResizeDown],
+                    "Change plot heights",
+                ),
+            ]),
+            keymap.help(&[
+                (&[Action::PauseDisplay], "Pause/resume display"),
+                (&[Action::ScrollBack, Action::ScrollForward], "Scroll time"),
+                (&[Action::ZoomTimeIn, Action::ZoomTimeOut], "Time axis zoom"),
+            ]),
+        ]
+    }
+
+    fn handle_tick(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        self.model.on_tick(source)
+    }
+
+    fn handle_action(&mut self, action: Action, _source: &mut dyn DataSource) -> Result<()> {
+        if self
+            .model
+            .time_window
+            .handle_action(action, self.model.tick_count)
+        {
+            return Ok(());
+        }
+
+        match action {
+            Action::ResizeUp => {
+                // Change the height of the plots
+                if self.view.hop_limit_height_percentage < 100 {
+                    self.view.hop_limit_height_percentage += 1;
+                }
+            }
+            Action::SelectPrevious => {
+                // Move the selected item in the interface list up
+                let selected = self.view.interfaces_state.selected().unwrap_or(0);
+                let candidate = if selected > 0 { selected - 1 } else { 0 };
+                self.view.interfaces_state.select(Some(candidate));
+            }
+            Action::ResizeDown => {
+                // Change the height of the plots
+                if self.view.hop_limit_height_percentage > 0 {
+                    self.view.hop_limit_height_percentage -= 1;
+                }
+            }
+            Action::SelectNext => {
+                // Move the selected item in the interface list down
+                let selected = self.view.interfaces_state.selected().unwrap_or(0);
+                let candidate = selected + 1;
+                if candidate < self.model.interfaces.len() {
+                    self.view.interfaces_state.select(Some(candidate));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame, context_area: Rect) {
+        self.view.draw(frame, &self.model, context_area);
+    }
+}
+
+impl DiagnosticsContext {
+    pub(crate) fn new(source: &dyn DataSource, settings: &Settings) -> Self {
+        let interfaces = source.interfaces().to_vec();
+        let interfaces_state = ListState::default().with_selected(Some(0));
+        let time_window = TimeWindow::new(settings);
+
+        let mut cumul_fragment_counts = HashMap::new();
+        let mut tick_fragment_count_data = HashMap::new();
+        let mut cumul_hop_limit_counts = HashMap::new();
+        let mut hop_limit_snapshots = HashMap::new();
+        for interface in &interfaces {
+            cumul_fragment_counts.insert(interface.index, [0; NUM_FRAGMENT_KINDS]);
+            tick_fragment_count_data.insert(
+                interface.index,
+                std::array::from_fn(|_| {
+                    let mut series = Series::new(time_window.series_capacity());
+                    series.push(0.0, 0.0);
+                    series
+                }),
+            );
+            cumul_hop_limit_counts.insert(interface.index, [0; NUM_HOP_LIMIT_VALUES]);
+            hop_limit_snapshots.insert(interface.index, VecDeque::new());
+        }
+
+        Self {
+            model: DiagnosticsModel {
+                interfaces,
+                cumul_fragment_counts,
+                tick_fragment_count_data,
+                cumul_hop_limit_counts,
+                hop_limit_snapshots,
+                tick_count: 0.0,
+                tick_rate_ms: settings.tick_rate_ms,
+                time_window,
+            },
+            view: DiagnosticsView {
+                interfaces_state,
+                hop_limit_height_percentage: settings.plots.hop_limit_height_percentage,
+                colors: settings.colors.clone(),
+                aliases: settings.aliases.clone(),
+            },
+        }
+    }
+}
+
+impl DiagnosticsModel {
+    fn on_tick(&mut self, source: &dyn DataSource) -> Result<()> {
+        self.tick_count += 1.0;
+
+        // Sum up the hop limit buckets across all CPUs
+        let mut hop_limit_counts: HashMap<u32, HopLimitCounts> = HashMap::new();
+        source.hop_limit_counts(&mut |key, values| {
+            let counts = hop_limit_counts
+                .entry(key.if_index)
+                .or_insert([0; NUM_HOP_LIMIT_VALUES]);
+            for cpu_count in values {
+                counts[key.hop_limit as usize % NUM_HOP_LIMIT_VALUES] += cpu_count;
+            }
+        })?;
+
+        for interface in &self.interfaces {
+            // Interfaces which have not received a fragment yet have no counters
+            let mut across_cpus_fragment_counts = [0u64; NUM_FRAGMENT_KINDS];
+            source.fragment_counters(interface.index, &mut |cpu_counters| {
+                for cpu_counter in cpu_counters {
+                    for (total, count) in across_cpus_fragment_counts
+                        .iter_mut()
+                        .zip(get_fragment_counts(cpu_counter))
+                    {
+                        *total += count;
+                    }
+                }
+            })?;
+
+            let prev_fragment_counts = self
+                .cumul_fragment_counts
+                .insert(interface.index, across_cpus_fragment_counts)
+                .unwrap_or([0; NUM_FRAGMENT_KINDS]);
+            let fragment_counts_windows = self
+                .tick_fragment_count_data
+                .get_mut(&interface.index)
+                .unwrap();
+            for (kind, fragment_counts_window) in fragment_counts_windows.iter_mut().enumerate() {
+                fragment_counts_window.push(
+                    self.tick_count,
+                    across_cpus_fragment_counts[kind].saturating_sub(prev_fragment_counts[kind])
+                        as f64,
+                );
+            }
+
+            let cumul_hop_limit_counts = hop_limit_counts
+                .get(&interface.index)
+                .copied()
+                .unwrap_or([0; NUM_HOP_LIMIT_VALUES]);
+            self.cumul_hop_limit_counts
+                .insert(interface.index, cumul_hop_limit_counts);
+            let snapshots = self.hop_limit_snapshots.get_mut(&interface.index).unwrap();
+            if snapshots.len() as f64 > self.time_window.history_size() {
+                snapshots.pop_front();
+            }
+            snapshots.push_back((self.tick_count, cumul_hop_limit_counts));
+        }
+
+        Ok(())
+    }
+
+    /// The first and last tick shown on the time axis
+    fn window(&self) -> [f64; 2] {
+        self.time_window.bounds(self.tick_count)
+    }
+
+    /// Hop limit histogram of the packets received during the current window
+    fn window_hop_limit_counts(&self, interface_index: u32) -> HopLimitCounts {
+        let mut counts = [0; NUM_HOP_LIMIT_VALUES];
+        let [start, end] = self.window();
+        if let Some(snapshots) = self.hop_limit_snapshots.get(&interface_index) {
+            let oldest = snapshots.iter().find(|snapshot| snapshot.0 >= start);
+            let latest = snapshots.iter().rev().find(|snapshot| snapshot.0 <= end);
+            if let (Some((_, oldest)), Some((_, latest))) = (oldest, latest) {
+                for (hop_limit, count) in counts.iter_mut().enumerate() {
+                    // The eBPF map is an LRU, so buckets can restart from zero if evicted
+                    *count = latest[hop_limit].saturating_sub(oldest[hop_limit]);
+                }
+            }
+        }
+        counts
+    }
+}
+
+impl DiagnosticsView {
+    fn draw(&mut self, frame: &mut Frame, model: &DiagnosticsModel, context_area: Rect) {
+        let [iface_list, plots] =
+            Layout::horizontal([Constraint::Percentage(15), Constraint::Fill(1)])
+                .areas(context_area);
+        let [fragment_counts, hop_limit_histogram] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Percentage(self.hop_limit_height_percentage),
+        ])
+        .areas(plots);
+
+        let selected = self.interfaces_state.selected().unwrap_or(0);
+        let interface = model.interfaces.get(selected);
+
+        self.render_list(frame, iface_list, model);
+        self.render_fragment_time_series(frame, fragment_counts, model, interface);
+        self.render_hop_limit_histogram(frame, hop_limit_histogram, model, interface);
+    }
+
+    fn render_fragment_time_series(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+        model: &DiagnosticsModel,
+        interface: Option<&NetworkInterface>,
+    ) {
+        let window = model.window();
+        let x_labels = vec![
+            Span::styled(
+                format!("{}", window[0]),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!("{}", (window[0] + window[1]) / 2.0)),
+            Span::styled(
+                format!("{}", window[1]),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+        ];
+
+        // Initialize max_val to 1.0 to avoid a quirk in the time series plot with autoscaling.
+        // If all values are 0 in the plot, and autoscaling starts at 0, then no points get plotted.
+        let mut max_val = 1.0f64;
+        // The points are gathered before the datasets, which borrow them
+        let data: Vec<Vec<(f64, f64)>> = interface
+            .and_then(|interface| model.tick_fragment_count_data.get(&interface.index))
+            .map(|data| {
+                data.iter()
+                    .map(|kind_data| kind_data.points(window))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut datasets = Vec::with_capacity(NUM_FRAGMENT_KINDS);
+        for (kind, kind_data) in data.iter().enumerate() {
+            let kind_max_val = kind_data.iter().map(|datum| datum.1).fold(0.0, f64::max);
+            max_val = max_val.max(kind_max_val);
+            let dataset = Dataset::default()
+                .name(FRAGMENT_KIND_NAMES[kind])
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::Indexed(kind as u8 + 1)))
+                .data(kind_data);
+            datasets.push(dataset);
+        }
+
+        let y_bound = get_autoscale_axis_bound(max_val);
+        let y_labels = [
+            "0".into(),
+            (y_bound / 2.0).to_string().bold(),
+            y_bound.to_string().bold(),
+        ];
+
+        let title = match interface {
+            Some(interface) => format!(
+                "Fragments per {} ms on {}",
+                model.tick_rate_ms,
+                self.aliases.interface_or_name(&interface.name)
+            ),
+            None => format!("Fragments per {} ms", model.tick_rate_ms),
+        };
+
+        let chart = Chart::new(datasets)
+            .block(Block::bordered().title(title))
+            .x_axis(
+                Axis::default()
+                    .title(model.time_window.axis_title())
+                    .style(Style::default().fg(self.colors.disabled))
+                    .labels(x_labels)
+                    .bounds(window),
+            )
+            .y_axis(
+                Axis::default()
+                    .title("Fragments")
+                    .style(Style::default().fg(self.colors.disabled))
+                    .labels(y_labels)
+                    .bounds([0.0, y_bound]),
+            )
+            .hidden_legend_constraints((Constraint::Min(0), Constraint::Min(0)))
+            .legend_position(Some(LegendPosition::TopLeft));
+
+        frame.render_widget(chart, area);
+    }
+
+    fn render_hop_limit_histogram(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+        model: &DiagnosticsModel,
+        interface: Option<&NetworkInterface>,
+    ) {
+        let counts = interface
+            .map(|interface| model.window_hop_limit_counts(interface.index))
+            .unwrap_or([0; NUM_HOP_LIMIT_VALUES]);
+
+        // Only show the TTL values which were actually observed, since a full 256 bucket
+        // histogram would not fit in a terminal
+        let labels: Vec<String> = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(hop_limit, _)| hop_limit.to_string())
+            .collect();
+        let data: Vec<(&str, u64)> = labels
+            .iter()
+            .zip(counts.iter().filter(|count| **count > 0))
+            .map(|(label, count)| (label.as_str(), *count))
+            .collect();
+
+        let title = match interface {
+            Some(interface) => format!(
+                "TTL/hop limit histogram for window on {}",
+                self.aliases.interface_or_name(&interface.name)
+            ),
+            None => String::from("TTL/hop limit histogram for window"),
+        };
+
+        let bar_chart = BarChart::default()
+            .block(Block::bordered().title(title))
+            .bar_width(5)
+            .data(&data);
+
+        frame.render_widget(bar_chart, area);
+    }
+
+    fn render_list(&mut self, frame: &mut Frame, list_area: Rect, model: &DiagnosticsModel) {
+        let ifaces: Vec<ListItem> = model
+            .interfaces
+            .iter()
+            .map(|iface| {
+                let fragment_count: u64 = model
+                    .cumul_fragment_counts
+                    .get(&iface.index)
+                    .map(|counts| counts.iter().sum())
+                    .unwrap_or(0);
+                ListItem::new(format!(
+                    "{}: {} ({} frags)",
+                    iface.index,
+                    self.aliases.interface_or_name(&iface.name),
+                    fragment_count
+                ))
+            })
+            .collect();
+
+        let list = List::new(ifaces)
+            .block(Block::bordered().title("Interface List"))
+            .style(Style::new().white())
+            .highlight_style(Style::new().italic())
+            .highlight_symbol(">")
+            .repeat_highlight_symbol(true)
+            .direction(ListDirection::TopToBottom);
+
+        frame.render_stateful_widget(list, list_area, &mut self.interfaces_state);
+    }
+}
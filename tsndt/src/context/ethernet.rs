@@ -1,8 +1,6 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
-use aya::maps::MapData;
 use color_eyre::eyre::Result;
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
     layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style, Stylize},
@@ -16,17 +14,23 @@ use ratatui::{
 };
 use tsndt_common::Counter;
 
-use super::TsndtContext;
-use crate::app::TICK_RATE_MS;
+use super::{
+    rollup::{Rollups, TimeRange},
+    series::{CpuCounts, Series},
+    time_window::TimeWindow,
+    TsndtContext,
+};
+use crate::{
+    export::SeriesPoint,
+    keymap::{Action, Keymap},
+    settings::{Aliases, ColorSettings, Settings},
+    source::DataSource,
+};
 
-const DISABLED_COLOR: Color = Color::Rgb(100, 100, 100);
-const ZOOM_CONTEXT_COLOR: Color = Color::LightBlue;
-const DEFAULT_HISTOGRAM_WIDTH_PERCENTAGE: u16 = 25;
-const DEFAULT_BYTE_COUNTERS_HEIGHT_PERCENTAGE: u16 = 50;
 const CONTEXT_NAME: &str = "Ethernet";
-const IDLE_MAC_ADDR_TIMEOUT_SEC: u64 = 300;
-const IDLE_MAC_ADDR_TIMEOUT_NUM_TICKS: f64 =
-    IDLE_MAC_ADDR_TIMEOUT_SEC as f64 * (1000.0 / TICK_RATE_MS as f64);
+// Source MAC addresses which keep a history of their counts, besides the displayed ones. The
+// history takes far more memory than the rest of an entry, so only so many get one.
+const MAX_MACS_WITH_HISTORY: usize = 256;
 
 #[derive(Clone, Eq, PartialEq, Hash)]
 enum ZoomContext {
@@ -34,7 +38,23 @@ enum ZoomContext {
     Byte,
 }
 
-pub(crate) struct EthernetContext {
+/// The set of plots shown next to the source MAC address list
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum PlotsMode {
+    Counters,
+    PerCpu,
+}
+
+impl PlotsMode {
+    fn next(self) -> Self {
+        match self {
+            PlotsMode::Counters => PlotsMode::PerCpu,
+            PlotsMode::PerCpu => PlotsMode::Counters,
+        }
+    }
+}
+
+pub struct EthernetContext {
     pub(crate) model: EthernetModel,
     pub(crate) view: EthernetView,
 }
@@ -47,22 +67,58 @@ pub(crate) struct EthernetView {
     byte_counter_height_percentage: u16,
     zoom_context: ZoomContext,
     autoscaling: HashMap<ZoomContext, bool>,
+    plots_mode: PlotsMode,
+    time_range: TimeRange,
+    colors: ColorSettings,
+    aliases: Aliases,
+}
+
+/// The counts of the selected source MAC address on each CPU, from the tick after it was
+/// selected. Only one address is broken down, as a series per CPU for each would take more
+/// memory than all the rest with many addresses and CPUs.
+struct CpuBreakdown {
+    src_mac: [u8; 6],
+    // Indexed by CPU
+    cpus: Vec<CpuCounts>,
+}
+
+/// The per-tick counts of a source MAC address, for the time series plots and exports
+struct MacHistory {
+    tick_packet_counts: Series,
+    tick_byte_counts: Series,
+    // Per-tick counts rolled up over longer periods than the history
+    packet_count_rollups: Rollups,
+    byte_count_rollups: Rollups,
+}
+
+/// Everything kept about a source MAC address, together so that a tick looks it up only once
+struct MacEntry {
+    cumul_packet_count: u32,
+    cumul_byte_count: u64,
+    // The latest tick on which the address was read, with the packets and bytes received during it
+    latest_tick_counts: (f64, f64, f64),
+    // For the first addresses tracked, up to MAX_MACS_WITH_HISTORY, and for the displayed ones
+    // from the tick after they are first displayed
+    history: Option<Box<MacHistory>>,
+    last_active_tick: f64,
 }
 
 pub(crate) struct EthernetModel {
+    // Tracked source MAC addresses, in the order they are listed
     src_macs: Vec<[u8; 6]>,
-    last_active_tick: HashMap<[u8; 6], f64>,
-    cumul_packet_counts: HashMap<[u8; 6], u32>,
-    tick_packet_count_data: HashMap<[u8; 6], Vec<(f64, f64)>>,
-    cumul_byte_counts: HashMap<[u8; 6], u64>,
-    tick_byte_count_data: HashMap<[u8; 6], Vec<(f64, f64)>>,
+    macs: HashMap<[u8; 6], MacEntry>,
+    cpu_breakdown: Option<CpuBreakdown>,
+    num_macs_with_history: usize,
     tick_count: f64,
     displaying: HashSet<[u8; 6]>,
-    window_size: f64,
-    window: [f64; 2],
+    tick_rate_ms: u64,
+    ticks_per_sec: f64,
+    // MAC addresses which received nothing for this many ticks are removed
+    idle_timeout_ticks: f64,
+    time_window: TimeWindow,
 }
 
-fn get_mac_string(mac: &[u8; 6]) -> String {
+pub(crate) fn get_mac_string(mac: &[u8]) -> String {
     let hex_strings: Vec<String> = mac.iter().map(|octet| format!("{octet:02x?}")).collect();
     hex_strings.join(":")
 }
@@ -82,84 +138,136 @@ impl TsndtContext for EthernetContext {
         String::from(CONTEXT_NAME)
     }
 
-    fn get_command_help(&self) -> Vec<String> {
-        vec![String::from(
-            "(↑/↓) Select address, (t) Toggle address monitoring, (s) Sort address values",
-        )]
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String> {
+        vec![
+            keymap.help(&[
+                (
+                    &[Action::SelectPrevious, Action::SelectNext],
+                    "Select address",
+                ),
+                (&[Action::ToggleSelected], "Toggle address monitoring"),
+                (&[Action::SortSelection], "Sort address values"),
+            ]),
+            keymap.help(&[
+                (
+                    &[Action::ZoomBytes, Action::ZoomPackets],
+                    "Select plot zoom context",
+                ),
+                (&[Action::ToggleAutoscaling], "Toggle autoscaling"),
+                (&[Action::ZoomIn, Action::ZoomOut], "Y axis zoom"),
+            ]),
+            keymap.help(&[
+                (
+                    &[Action::ResizeLeft, Action::ResizeRight],
+                    "Change plot widths",
+                ),
+                (
+                    &[Action::ResizeUp, Action::ResizeDown],
+                    "Change plot heights",
+                ),
+                (
+                    &[Action::CyclePlots],
+                    "Cycle plot views (counters, per-CPU)",
+                ),
+            ]),
+            keymap.help(&[
+                (
+                    &[Action::CycleTimeRange],
+                    "Cycle time range (window, 10 min, 6 h)",
+                ),
+                (&[Action::PauseDisplay], "Pause/resume display"),
+                (&[Action::ScrollBack, Action::ScrollForward], "Scroll time"),
+                (&[Action::ZoomTimeIn, Action::ZoomTimeOut], "Time axis zoom"),
+            ]),
+        ]
     }
 
-    fn handle_tick(&mut self, bpf: &mut aya::Ebpf) -> Result<()> {
-        self.model.on_tick(bpf)
+    fn handle_tick(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        let selected = self
+            .view
+            .src_macs_state
+            .selected()
+            .and_then(|selected| self.model.src_macs.get(selected))
+            .copied();
+        self.model.on_tick(source, selected)
     }
 
-    fn handle_key_event(&mut self, key: KeyEvent, _bpf: &mut aya::Ebpf) -> Result<()> {
-        match key.code {
-            KeyCode::Char('b') => {
+    fn handle_action(&mut self, action: Action, _source: &mut dyn DataSource) -> Result<()> {
+        if self
+            .model
+            .time_window
+            .handle_action(action, self.model.tick_count)
+        {
+            return Ok(());
+        }
+
+        match action {
+            Action::ZoomBytes => {
                 self.view.zoom_context = ZoomContext::Byte;
             }
-            KeyCode::Char('p') => {
+            Action::ZoomPackets => {
                 self.view.zoom_context = ZoomContext::Packet;
             }
-            KeyCode::Char('a') => {
+            Action::ToggleAutoscaling => {
                 let val = !self.view.autoscaling[&self.view.zoom_context];
                 self.view
                     .autoscaling
                     .insert(self.view.zoom_context.clone(), val);
             }
-            KeyCode::Char('s') => {
+            Action::SortSelection => {
                 self.model.src_macs.sort();
             }
-            KeyCode::Char('-') => match self.view.zoom_context {
+            Action::CyclePlots => {
+                self.view.plots_mode = self.view.plots_mode.next();
+            }
+            Action::CycleTimeRange => {
+                self.view.time_range = self.view.time_range.next();
+            }
+            Action::ZoomOut => match self.view.zoom_context {
                 ZoomContext::Packet => self.view.packet_count_y_bounds[1] *= 2.0,
                 ZoomContext::Byte => self.view.byte_count_y_bounds[1] *= 2.0,
             },
-            KeyCode::Char('+') => match self.view.zoom_context {
+            Action::ZoomIn => match self.view.zoom_context {
                 ZoomContext::Packet => self.view.packet_count_y_bounds[1] /= 2.0,
                 ZoomContext::Byte => self.view.byte_count_y_bounds[1] /= 2.0,
             },
-            KeyCode::Up => {
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    // Change the height of the plots
-                    if self.view.byte_counter_height_percentage < 100 {
-                        self.view.byte_counter_height_percentage += 1;
-                    }
-                } else {
-                    // Move the selected item in the interface list up
-                    let selected = self.view.src_macs_state.selected().unwrap_or(0);
-                    let candidate = if selected > 0 { selected - 1 } else { 0 };
-                    self.view.src_macs_state.select(Some(candidate));
+            Action::ResizeUp => {
+                // Change the height of the plots
+                if self.view.byte_counter_height_percentage < 100 {
+                    self.view.byte_counter_height_percentage += 1;
                 }
             }
-            KeyCode::Down => {
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    // Change the height of the plots
-                    if self.view.byte_counter_height_percentage > 0 {
-                        self.view.byte_counter_height_percentage -= 1;
-                    }
-                } else {
-                    // Move the selected item in the interface list down
-                    let selected = self.view.src_macs_state.selected().unwrap_or(0);
-                    let candidate = selected + 1;
-                    if candidate < self.model.src_macs.len() {
-                        self.view.src_macs_state.select(Some(candidate));
-                    }
+            Action::SelectPrevious => {
+                // Move the selected item in the interface list up
+                let selected = self.view.src_macs_state.selected().unwrap_or(0);
+                let candidate = if selected > 0 { selected - 1 } else { 0 };
+                self.view.src_macs_state.select(Some(candidate));
+            }
+            Action::ResizeDown => {
+                // Change the height of the plots
+                if self.view.byte_counter_height_percentage > 0 {
+                    self.view.byte_counter_height_percentage -= 1;
                 }
             }
-            KeyCode::Right => {
-                if key.modifiers.contains(KeyModifiers::CONTROL)
-                    && self.view.histogram_width_percentage > 0
-                {
+            Action::SelectNext => {
+                // Move the selected item in the interface list down
+                let selected = self.view.src_macs_state.selected().unwrap_or(0);
+                let candidate = selected + 1;
+                if candidate < self.model.src_macs.len() {
+                    self.view.src_macs_state.select(Some(candidate));
+                }
+            }
+            Action::ResizeRight => {
+                if self.view.histogram_width_percentage > 0 {
                     self.view.histogram_width_percentage -= 1;
                 }
             }
-            KeyCode::Left => {
-                if key.modifiers.contains(KeyModifiers::CONTROL)
-                    && self.view.histogram_width_percentage < 100
-                {
+            Action::ResizeLeft => {
+                if self.view.histogram_width_percentage < 100 {
                     self.view.histogram_width_percentage += 1;
                 }
             }
-            KeyCode::Char('t') => {
+            Action::ToggleSelected => {
                 let selected = self.view.src_macs_state.selected().unwrap_or(0);
                 let src_mac = self.model.src_macs.get(selected).cloned();
                 if let Some(src_mac) = src_mac {
@@ -179,149 +287,313 @@ impl TsndtContext for EthernetContext {
     fn draw(&mut self, frame: &mut Frame, context_area: Rect) {
         self.view.draw(frame, &self.model, context_area);
     }
+
+    fn export_series(&self) -> Option<Vec<SeriesPoint>> {
+        Some(self.model.series())
+    }
 }
 
 impl EthernetContext {
-    pub(crate) fn new() -> Self {
+    pub fn new(source: &dyn DataSource, settings: &Settings) -> Self {
         let src_macs_state = ListState::default().with_selected(Some(0));
 
-        // Turn on autoscaling by default
-        let autoscaling = HashMap::from([(ZoomContext::Byte, true), (ZoomContext::Packet, true)]);
-
+        // Start from the counts that the source already holds, which are not 0 for counters
+        // pinned by an earlier run, so that they do not show up as traffic on the first tick
+        let time_window = TimeWindow::new(settings);
+        let ticks_per_sec = settings.ticks(1);
+        let mut src_macs = Vec::new();
+        let mut macs = HashMap::new();
+        let _ = source.mac_counters(&mut |src_mac, values| {
+            let history = (src_macs.len() < MAX_MACS_WITH_HISTORY)
+                .then(|| MacHistory::new(time_window.series_capacity(), ticks_per_sec));
+            let mut entry = MacEntry::new(0.0, history);
+            entry.cumul_packet_count = values.iter().map(|counter| counter.packets).sum();
+            entry.cumul_byte_count = values.iter().map(|counter| counter.bytes).sum();
+            src_macs.push(*src_mac);
+            macs.insert(*src_mac, entry);
+        });
+
+        let autoscaling = HashMap::from([
+            (ZoomContext::Byte, settings.plots.autoscaling),
+            (ZoomContext::Packet, settings.plots.autoscaling),
+        ]);
+
+        let num_macs_with_history = src_macs.len().min(MAX_MACS_WITH_HISTORY);
         Self {
             model: EthernetModel {
-                src_macs: Vec::new(),
-                window_size: 50.0,
-                window: [0.0, 50.0],
+                src_macs,
+                macs,
+                cpu_breakdown: None,
+                num_macs_with_history,
+                tick_rate_ms: settings.tick_rate_ms,
+                ticks_per_sec,
+                idle_timeout_ticks: settings.ticks(settings.mac_idle_timeout_sec),
+                time_window,
                 tick_count: 0.0,
-                last_active_tick: HashMap::new(),
-                tick_packet_count_data: HashMap::new(),
-                cumul_packet_counts: HashMap::new(),
-                tick_byte_count_data: HashMap::new(),
-                cumul_byte_counts: HashMap::new(),
                 displaying: HashSet::new(),
             },
             view: EthernetView {
-                packet_count_y_bounds: [0.0, 40.0],
-                byte_count_y_bounds: [0.0, 50000.0],
-                histogram_width_percentage: DEFAULT_HISTOGRAM_WIDTH_PERCENTAGE,
+                packet_count_y_bounds: [0.0, settings.plots.packet_axis_max],
+                byte_count_y_bounds: [0.0, settings.plots.byte_axis_max],
+                histogram_width_percentage: settings.plots.histogram_width_percentage,
                 zoom_context: ZoomContext::Packet,
-                byte_counter_height_percentage: DEFAULT_BYTE_COUNTERS_HEIGHT_PERCENTAGE,
+                byte_counter_height_percentage: settings.plots.byte_counters_height_percentage,
                 autoscaling,
                 src_macs_state,
+                plots_mode: PlotsMode::Counters,
+                time_range: TimeRange::Window,
+                colors: settings.colors.clone(),
+                aliases: settings.aliases.clone(),
             },
         }
     }
 }
 
-impl EthernetModel {
-    fn on_tick(&mut self, bpf: &mut aya::Ebpf) -> Result<()> {
-        self.tick_count += 1.0;
+impl CpuBreakdown {
+    fn new(src_mac: [u8; 6], values: &[Counter], series_capacity: usize) -> Self {
+        Self {
+            src_mac,
+            cpus: values
+                .iter()
+                .map(|counter| CpuCounts::new(counter, series_capacity))
+                .collect(),
+        }
+    }
 
-        let src_mac_rx_counters: aya::maps::PerCpuHashMap<&MapData, [u8; 6], Counter> =
-            aya::maps::PerCpuHashMap::try_from(bpf.map("SMAC_RX_COUNT").unwrap())?;
+    /// Adds the counts received since the previous tick, given the cumulative counts of each CPU
+    fn on_tick(&mut self, tick_count: f64, values: &[Counter], series_capacity: usize) {
+        CpuCounts::push_all(&mut self.cpus, tick_count, values, series_capacity);
+    }
+}
+
+impl MacHistory {
+    fn new(series_capacity: usize, ticks_per_sec: f64) -> Box<Self> {
+        Box::new(Self {
+            tick_packet_counts: Series::new(series_capacity),
+            tick_byte_counts: Series::new(series_capacity),
+            packet_count_rollups: Rollups::new(ticks_per_sec),
+            byte_count_rollups: Rollups::new(ticks_per_sec),
+        })
+    }
+}
 
-        let num_cpus =
-            aya::util::nr_cpus().unwrap_or_else(|_| panic!("Could not get number of CPUs"));
+impl MacEntry {
+    fn new(last_active_tick: f64, history: Option<Box<MacHistory>>) -> Self {
+        Self {
+            cumul_packet_count: 0,
+            cumul_byte_count: 0,
+            latest_tick_counts: (0.0, 0.0, 0.0),
+            history,
+            last_active_tick,
+        }
+    }
 
-        for src_mac_packet_counter_entry in src_mac_rx_counters.iter() {
-            let (src_mac, values) = src_mac_packet_counter_entry?;
+    /// Adds the counts received since the previous tick, given the cumulative counts of each CPU
+    fn on_tick(&mut self, tick_count: f64, values: &[Counter]) {
+        let mut across_cpus_packet_count: u32 = 0;
+        let mut across_cpus_byte_count: u64 = 0;
+        for cpu_counter in values {
+            across_cpus_packet_count += cpu_counter.packets;
+            across_cpus_byte_count += cpu_counter.bytes;
+        }
 
-            // Add the source MAC to the list if it was not being tracked with an active tick and
-            // initialize the counts to 0.
-            if !self.last_active_tick.contains_key(&src_mac) {
-                self.src_macs.push(src_mac);
-                self.cumul_byte_counts.insert(src_mac, 0);
-                self.cumul_packet_counts.insert(src_mac, 0);
-                self.tick_byte_count_data.insert(src_mac, Vec::new());
-                self.tick_packet_count_data.insert(src_mac, Vec::new());
-            }
+        // If new data arrived for this MAC address, then it is active as of the current tick
+        if across_cpus_packet_count > self.cumul_packet_count {
+            self.last_active_tick = tick_count;
+        }
 
-            let packet_counts_window = self.tick_packet_count_data.get_mut(&src_mac).unwrap();
-            let byte_counts_window = self.tick_byte_count_data.get_mut(&src_mac).unwrap();
+        let packet_count = across_cpus_packet_count.saturating_sub(self.cumul_packet_count) as f64;
+        self.cumul_packet_count = across_cpus_packet_count;
+        let byte_count = across_cpus_byte_count.saturating_sub(self.cumul_byte_count) as f64;
+        self.cumul_byte_count = across_cpus_byte_count;
+        self.latest_tick_counts = (tick_count, packet_count, byte_count);
+
+        if let Some(history) = &mut self.history {
+            history.tick_packet_counts.push(tick_count, packet_count);
+            history.packet_count_rollups.push(tick_count, packet_count);
+            history.tick_byte_counts.push(tick_count, byte_count);
+            history.byte_count_rollups.push(tick_count, byte_count);
+        }
+    }
+}
 
-            let prev_packet_count_val = *self.cumul_packet_counts.get(&src_mac).unwrap();
-            let prev_byte_count_val = self.cumul_byte_counts.get(&src_mac).unwrap();
+impl EthernetModel {
+    /// Packets and bytes received during the latest tick from each source MAC address which
+    /// received any, busiest first
+    pub(crate) fn latest_tick_counts(&self) -> Vec<([u8; 6], f64, f64)> {
+        let mut counts: Vec<([u8; 6], f64, f64)> = self
+            .src_macs
+            .iter()
+            .filter_map(|src_mac| {
+                let (tick, packet_count, byte_count) = self.macs.get(src_mac)?.latest_tick_counts;
+                (tick == self.tick_count && packet_count > 0.0).then_some((
+                    *src_mac,
+                    packet_count,
+                    byte_count,
+                ))
+            })
+            .collect();
+        counts.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+        counts
+    }
 
-            if packet_counts_window.len() as f64 > self.window_size {
-                packet_counts_window.remove(0);
+    /// The packet and byte counts of each tick in the history from each source MAC address which
+    /// keeps one
+    fn series(&self) -> Vec<SeriesPoint> {
+        let mut points = Vec::new();
+        for src_mac in &self.src_macs {
+            let Some(history) = self
+                .macs
+                .get(src_mac)
+                .and_then(|entry| entry.history.as_ref())
+            else {
+                continue;
+            };
+            // Both series get a point at each tick, so they line up
+            for (packet_datum, byte_datum) in history
+                .tick_packet_counts
+                .iter()
+                .zip(history.tick_byte_counts.iter())
+            {
+                points.push(SeriesPoint {
+                    tick: packet_datum.0 as u64,
+                    entity: get_mac_string(src_mac),
+                    packets: packet_datum.1 as u64,
+                    bytes: byte_datum.1 as u64,
+                });
             }
+        }
+        points
+    }
 
-            let mut across_cpus_packet_count: u32 = 0;
-            let mut across_cpus_byte_count: u64 = 0;
-            for cpu_id in 0..num_cpus {
-                if let Some(cpu_counter) = values.get(cpu_id) {
-                    across_cpus_packet_count += cpu_counter.packets;
-                    across_cpus_byte_count += cpu_counter.bytes;
-                }
-            }
+    /// Adds the counts received since the previous tick, breaking down those of `selected` by
+    /// CPU
+    fn on_tick(&mut self, source: &mut dyn DataSource, selected: Option<[u8; 6]>) -> Result<()> {
+        self.tick_count += 1.0;
+        if self
+            .cpu_breakdown
+            .as_ref()
+            .map(|breakdown| breakdown.src_mac)
+            != selected
+        {
+            self.cpu_breakdown = None;
+        }
 
-            packet_counts_window.push((
-                self.tick_count,
-                (across_cpus_packet_count - prev_packet_count_val) as f64,
-            ));
-            self.cumul_packet_counts
-                .insert(src_mac, across_cpus_packet_count);
-
-            byte_counts_window.push((
-                self.tick_count,
-                (across_cpus_byte_count - prev_byte_count_val) as f64,
-            ));
-            self.cumul_byte_counts
-                .insert(src_mac, across_cpus_byte_count);
-
-            // If new data arrived for this MAC entry, then update its last active tick to the current tick
-            if across_cpus_packet_count > prev_packet_count_val {
-                *self
-                    .last_active_tick
-                    .entry(src_mac)
-                    .or_insert(self.tick_count) = self.tick_count;
+        let series_capacity = self.time_window.series_capacity();
+        source.mac_counters(&mut |src_mac, values| {
+            // Start tracking a new source MAC address from counts of 0
+            let src_mac = *src_mac;
+            let entry = match self.macs.entry(src_mac) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    self.src_macs.push(src_mac);
+                    // An address can still be displayed from before it went idle
+                    let has_history = self.num_macs_with_history < MAX_MACS_WITH_HISTORY
+                        || self.displaying.contains(&src_mac);
+                    let history = has_history.then(|| {
+                        self.num_macs_with_history += 1;
+                        MacHistory::new(series_capacity, self.ticks_per_sec)
+                    });
+                    entry.insert(MacEntry::new(self.tick_count, history))
+                }
+            };
+            entry.on_tick(self.tick_count, values);
+
+            if selected == Some(src_mac) {
+                match &mut self.cpu_breakdown {
+                    Some(breakdown) => breakdown.on_tick(self.tick_count, values, series_capacity),
+                    None => {
+                        self.cpu_breakdown =
+                            Some(CpuBreakdown::new(src_mac, values, series_capacity))
+                    }
+                }
             }
-        }
+        })?;
 
         // Remove MAC addresses which have been inactive for the duration of the timeout period
         let mut to_remove = Vec::new();
-        for (src_mac, last_active_tick) in &self.last_active_tick {
-            // Check if the timeout has occurred
-            if self.tick_count - IDLE_MAC_ADDR_TIMEOUT_NUM_TICKS >= *last_active_tick {
+        for (src_mac, entry) in &self.macs {
+            if self.tick_count - self.idle_timeout_ticks >= entry.last_active_tick {
                 to_remove.push(*src_mac);
             }
         }
 
-        for src_mac in &to_remove {
-            self.cumul_byte_counts.remove(src_mac);
-            self.cumul_packet_counts.remove(src_mac);
-            self.tick_byte_count_data.remove(src_mac);
-            self.tick_packet_count_data.remove(src_mac);
-            self.last_active_tick.remove(src_mac);
-            if let Some(index) = self.src_macs.iter().position(|value| value == src_mac) {
-                self.src_macs.swap_remove(index);
+        if !to_remove.is_empty() {
+            for src_mac in &to_remove {
+                if let Some(entry) = self.macs.remove(src_mac) {
+                    if entry.history.is_some() {
+                        self.num_macs_with_history -= 1;
+                    }
+                }
+                source.remove_mac_counters(src_mac)?;
             }
+            let macs = &self.macs;
+            self.src_macs.retain(|src_mac| macs.contains_key(src_mac));
+        }
 
-            let mut src_mac_rx_counters: aya::maps::PerCpuHashMap<&mut MapData, [u8; 6], u32> =
-                aya::maps::PerCpuHashMap::try_from(bpf.map_mut("SMAC_RX_COUNT").unwrap())?;
+        Ok(())
+    }
 
-            src_mac_rx_counters.remove(src_mac)?;
-        }
+    /// The tracked source MAC addresses which are plotted, in list order
+    fn displayed_macs(&self) -> impl Iterator<Item = (&[u8; 6], &MacEntry)> {
+        self.src_macs
+            .iter()
+            .filter(|src_mac| self.displaying.contains(*src_mac))
+            .filter_map(|src_mac| Some((src_mac, self.macs.get(src_mac)?)))
+    }
 
-        if self.tick_count > self.window_size {
-            self.window[0] += 1.0;
-            self.window[1] += 1.0;
+    /// Per-CPU packet and byte totals for the selected source MAC address over the current
+    /// window, since it was selected, ordered by CPU
+    fn window_cpu_counts(&self, src_mac: &[u8; 6]) -> Vec<(usize, u64, u64)> {
+        let window = self.window();
+        let Some(breakdown) = &self.cpu_breakdown else {
+            return Vec::new();
+        };
+        if breakdown.src_mac != *src_mac {
+            return Vec::new();
         }
+        CpuCounts::sums(&breakdown.cpus, window)
+    }
 
-        Ok(())
+    /// The first and last tick shown on the time axis
+    fn window(&self) -> [f64; 2] {
+        self.time_window.bounds(self.tick_count)
+    }
+
+    /// The first and last tick of a time range plotted from the rollups
+    fn range_bounds(&self, time_range: TimeRange) -> Option<[f64; 2]> {
+        time_range.bounds(self.ticks_per_sec, self.tick_count)
     }
 
+    /// Displays the source MAC address or stops displaying it. Displayed addresses get a history
+    /// if they have none yet.
     fn toggle_display(&mut self, src_mac: &[u8; 6]) {
         if self.displaying.contains(src_mac) {
             self.displaying.remove(src_mac);
-        } else {
-            self.displaying.insert(*src_mac);
+            return;
+        }
+        self.displaying.insert(*src_mac);
+        if let Some(entry) = self.macs.get_mut(src_mac) {
+            if entry.history.is_none() {
+                entry.history = Some(MacHistory::new(
+                    self.time_window.series_capacity(),
+                    self.ticks_per_sec,
+                ));
+                self.num_macs_with_history += 1;
+            }
         }
     }
 }
 
 impl EthernetView {
+    /// The alias of a MAC address, or the address itself if it has none
+    fn mac_name(&self, mac: &[u8; 6]) -> String {
+        match self.aliases.mac(mac) {
+            Some(alias) => alias.to_string(),
+            None => get_mac_string(mac),
+        }
+    }
+
     fn draw(&mut self, frame: &mut Frame, model: &EthernetModel, context_area: Rect) {
         let [observed_mac_list, plots] =
             Layout::horizontal([Constraint::Percentage(15), Constraint::Fill(1)])
@@ -343,49 +615,152 @@ impl EthernetView {
         .areas(byte_counts);
 
         self.render_list(frame, observed_mac_list, model);
-        self.render_packet_time_series(frame, packet_time_series, model);
-        self.render_packet_cumul_histogram(frame, packet_cumul_histogram, model);
-        self.render_byte_time_series(frame, byte_time_series, model);
-        self.render_byte_cumul_histogram(frame, byte_cumul_histogram, model);
+        match self.plots_mode {
+            PlotsMode::Counters => {
+                self.render_packet_time_series(frame, packet_time_series, model);
+                self.render_packet_cumul_histogram(frame, packet_cumul_histogram, model);
+                self.render_byte_time_series(frame, byte_time_series, model);
+                self.render_byte_cumul_histogram(frame, byte_cumul_histogram, model);
+            }
+            PlotsMode::PerCpu => {
+                self.render_per_cpu_histograms(frame, packet_counts, byte_counts, model);
+            }
+        }
     }
 
-    fn render_packet_time_series(&mut self, frame: &mut Frame, area: Rect, model: &EthernetModel) {
+    fn render_per_cpu_histograms(
+        &mut self,
+        frame: &mut Frame,
+        packet_area: Rect,
+        byte_area: Rect,
+        model: &EthernetModel,
+    ) {
+        let selected = self.src_macs_state.selected().unwrap_or(0);
+        let src_mac = model.src_macs.get(selected);
+        let cpu_counts = src_mac
+            .map(|src_mac| model.window_cpu_counts(src_mac))
+            .unwrap_or_default();
+        let src_mac_str = src_mac
+            .map(|src_mac| self.mac_name(src_mac))
+            .unwrap_or_default();
+
+        let total_packet_count: u64 = cpu_counts.iter().map(|count| count.1).sum();
+        let total_byte_count: u64 = cpu_counts.iter().map(|count| count.2).sum();
+
+        // Label each bar with its share of the address traffic so that an imbalance stands
+        // out even when the bars are too narrow to compare
+        let packet_labels: Vec<String> = cpu_counts
+            .iter()
+            .map(|(cpu_id, packet_count, _)| {
+                let share = 100.0 * *packet_count as f64 / total_packet_count.max(1) as f64;
+                format!("cpu{cpu_id} {share:.0}%")
+            })
+            .collect();
+        let byte_labels: Vec<String> = cpu_counts
+            .iter()
+            .map(|(cpu_id, _, byte_count)| {
+                let share = 100.0 * *byte_count as f64 / total_byte_count.max(1) as f64;
+                format!("cpu{cpu_id} {share:.0}%")
+            })
+            .collect();
+
+        let packet_data: Vec<(&str, u64)> = packet_labels
+            .iter()
+            .zip(&cpu_counts)
+            .map(|(label, count)| (label.as_str(), count.1))
+            .collect();
+        let byte_data: Vec<(&str, u64)> = byte_labels
+            .iter()
+            .zip(&cpu_counts)
+            .map(|(label, count)| (label.as_str(), count.2))
+            .collect();
+
+        let packet_bar_chart = BarChart::default()
+            .block(Block::bordered().title(format!(
+                "Packets per CPU from {src_mac_str} over the window, since selecting it"
+            )))
+            .bar_width(10)
+            .data(&packet_data);
+        let byte_bar_chart = BarChart::default()
+            .block(Block::bordered().title(format!(
+                "Bytes per CPU from {src_mac_str} over the window, since selecting it"
+            )))
+            .bar_width(10)
+            .data(&byte_data);
+
+        frame.render_widget(packet_bar_chart, packet_area);
+        frame.render_widget(byte_bar_chart, byte_area);
+    }
+
+    /// Bounds, labels and title of the time axis of the time series plots, which follow either
+    /// the window or the selected time range
+    fn time_axis(&self, model: &EthernetModel) -> ([f64; 2], Vec<Span<'static>>, &'static str) {
+        let window = model
+            .range_bounds(self.time_range)
+            .unwrap_or(model.window());
+        let [start, middle, end] = self.time_range.axis_labels().unwrap_or([
+            format!("{}", window[0]),
+            format!("{}", (window[0] + window[1]) / 2.0),
+            format!("{}", window[1]),
+        ]);
         let x_labels = vec![
-            Span::styled(
-                format!("{}", model.window[0]),
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
-            Span::raw(format!("{}", (model.window[0] + model.window[1]) / 2.0)),
-            Span::styled(
-                format!("{}", model.window[1]),
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
+            Span::styled(start, Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(middle),
+            Span::styled(end, Style::default().add_modifier(Modifier::BOLD)),
         ];
+        let x_title = self
+            .time_range
+            .axis_title()
+            .unwrap_or(model.time_window.axis_title());
+        (window, x_labels, x_title)
+    }
+
+    fn render_packet_time_series(&mut self, frame: &mut Frame, area: Rect, model: &EthernetModel) {
+        let (window, x_labels, x_title) = self.time_axis(model);
 
         // Initialize max_val to 1.0 to avoid a quirk in the time series plot with autoscaling.
         // If all values are 0 in the plot, and autoscaling starts at 0, then no points get plotted.
         let mut max_val = 1.0f64;
-        let mut datasets = Vec::with_capacity(model.src_macs.len());
-        let mut color_index = 1u8;
-        for src_mac in &model.src_macs {
-            if model.displaying.contains(src_mac) {
-                let data = model.tick_packet_count_data.get(src_mac).unwrap();
-                let src_mac_max_val = data.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap().1;
-                max_val = if max_val.total_cmp(&src_mac_max_val).is_ge() {
-                    max_val
-                } else {
-                    src_mac_max_val
-                };
-                let dataset = Dataset::default()
-                    .name(get_mac_string(src_mac))
-                    .marker(symbols::Marker::Dot)
-                    .style(Style::default().fg(Color::Indexed(color_index)))
-                    .data(data);
-                datasets.push(dataset);
-                color_index += 1;
+        // The points are gathered before the datasets, which borrow them. Only one of the two is
+        // filled, depending on whether the rollups are plotted.
+        let range_bounds = model.range_bounds(self.time_range);
+        let mut rollup_series = Vec::new();
+        let mut points = Vec::new();
+        for (src_mac, entry) in model.displayed_macs() {
+            let Some(history) = &entry.history else {
+                continue;
+            };
+            match range_bounds {
+                Some(bounds) => {
+                    rollup_series.push((src_mac, history.packet_count_rollups.series(bounds)))
+                }
+                None => points.push((src_mac, history.tick_packet_counts.points(window))),
             }
         }
 
+        let mut datasets = Vec::with_capacity(rollup_series.len() * 3 + points.len());
+        let mut color_index = 1u8;
+        for (src_mac, series) in &rollup_series {
+            max_val = max_val.max(series.max_value());
+            datasets.extend(series.datasets(&self.mac_name(src_mac), Color::Indexed(color_index)));
+            color_index += 1;
+        }
+        for (src_mac, data) in &points {
+            let src_mac_max_val = data.iter().map(|datum| datum.1).fold(0.0, f64::max);
+            max_val = if max_val.total_cmp(&src_mac_max_val).is_ge() {
+                max_val
+            } else {
+                src_mac_max_val
+            };
+            let dataset = Dataset::default()
+                .name(self.mac_name(src_mac))
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::Indexed(color_index)))
+                .data(data);
+            datasets.push(dataset);
+            color_index += 1;
+        }
+
         if self.autoscaling[&ZoomContext::Packet] {
             let upper_bound = get_autoscale_axis_bound(max_val);
             self.packet_count_y_bounds[1] = upper_bound;
@@ -398,7 +773,7 @@ impl EthernetView {
         ];
 
         let border_style = match self.zoom_context {
-            ZoomContext::Packet => Style::default().fg(ZOOM_CONTEXT_COLOR),
+            ZoomContext::Packet => Style::default().fg(self.colors.zoom_context),
             ZoomContext::Byte => Style::default(),
         };
 
@@ -412,19 +787,19 @@ impl EthernetView {
             .block(
                 Block::bordered()
                     .border_style(border_style)
-                    .title(format!("Packet count per {TICK_RATE_MS} ms")),
+                    .title(format!("Packet count per {} ms", model.tick_rate_ms)),
             )
             .x_axis(
                 Axis::default()
-                    .title("Time")
-                    .style(Style::default().fg(DISABLED_COLOR))
+                    .title(x_title)
+                    .style(Style::default().fg(self.colors.disabled))
                     .labels(x_labels)
-                    .bounds(model.window),
+                    .bounds(window),
             )
             .y_axis(
                 Axis::default()
                     .title(y_axis_title)
-                    .style(Style::default().fg(DISABLED_COLOR))
+                    .style(Style::default().fg(self.colors.disabled))
                     .labels(y_labels)
                     .bounds(self.packet_count_y_bounds),
             )
@@ -440,22 +815,16 @@ impl EthernetView {
         area: Rect,
         model: &EthernetModel,
     ) {
-        let mut target_src_macs: Vec<&[u8; 6]> = Vec::with_capacity(model.src_macs.len());
-        for src_mac in &model.src_macs {
-            if model.displaying.contains(src_mac) {
-                target_src_macs.push(src_mac);
-            }
-        }
+        let target_src_macs: Vec<(&[u8; 6], &MacEntry)> = model.displayed_macs().collect();
 
         let mut mac_strs: Vec<String> = Vec::with_capacity(target_src_macs.len());
-        for src_mac in &target_src_macs {
-            mac_strs.push(get_mac_string(src_mac));
+        for (src_mac, _) in &target_src_macs {
+            mac_strs.push(self.mac_name(src_mac));
         }
 
         let mut data: Vec<(&str, u64)> = Vec::with_capacity(target_src_macs.len());
-        for (i, src_mac) in target_src_macs.iter().enumerate() {
-            let val = model.cumul_packet_counts.get(*src_mac).unwrap();
-            data.push((mac_strs.get(i).unwrap(), *val as u64));
+        for (i, (_, entry)) in target_src_macs.iter().enumerate() {
+            data.push((mac_strs.get(i).unwrap(), entry.cumul_packet_count as u64));
         }
 
         data.sort_by_key(|datum| std::cmp::Reverse(datum.1));
@@ -469,42 +838,51 @@ impl EthernetView {
     }
 
     fn render_byte_time_series(&mut self, frame: &mut Frame, area: Rect, model: &EthernetModel) {
-        let x_labels = vec![
-            Span::styled(
-                format!("{}", model.window[0]),
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
-            Span::raw(format!("{}", (model.window[0] + model.window[1]) / 2.0)),
-            Span::styled(
-                format!("{}", model.window[1]),
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
-        ];
+        let (window, x_labels, x_title) = self.time_axis(model);
 
         // Initialize max_val to 1.0 to avoid a quirk in the time series plot with autoscaling.
         // If all values are 0 in the plot, and autoscaling starts at 0, then no points get plotted.
         let mut max_val = 1.0f64;
-        let mut datasets = Vec::with_capacity(model.src_macs.len());
-        let mut color_index = 1;
-        for src_mac in &model.src_macs {
-            if model.displaying.contains(src_mac) {
-                let data = model.tick_byte_count_data.get(src_mac).unwrap();
-                let src_mac_max_val = data.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap().1;
-                max_val = if max_val.total_cmp(&src_mac_max_val).is_ge() {
-                    max_val
-                } else {
-                    src_mac_max_val
-                };
-                let dataset = Dataset::default()
-                    .name(get_mac_string(src_mac))
-                    .marker(symbols::Marker::Dot)
-                    .style(Style::default().fg(Color::Indexed(color_index)))
-                    .data(data);
-                datasets.push(dataset);
-                color_index += 1;
+        // The points are gathered before the datasets, which borrow them. Only one of the two is
+        // filled, depending on whether the rollups are plotted.
+        let range_bounds = model.range_bounds(self.time_range);
+        let mut rollup_series = Vec::new();
+        let mut points = Vec::new();
+        for (src_mac, entry) in model.displayed_macs() {
+            let Some(history) = &entry.history else {
+                continue;
+            };
+            match range_bounds {
+                Some(bounds) => {
+                    rollup_series.push((src_mac, history.byte_count_rollups.series(bounds)))
+                }
+                None => points.push((src_mac, history.tick_byte_counts.points(window))),
             }
         }
 
+        let mut datasets = Vec::with_capacity(rollup_series.len() * 3 + points.len());
+        let mut color_index = 1;
+        for (src_mac, series) in &rollup_series {
+            max_val = max_val.max(series.max_value());
+            datasets.extend(series.datasets(&self.mac_name(src_mac), Color::Indexed(color_index)));
+            color_index += 1;
+        }
+        for (src_mac, data) in &points {
+            let src_mac_max_val = data.iter().map(|datum| datum.1).fold(0.0, f64::max);
+            max_val = if max_val.total_cmp(&src_mac_max_val).is_ge() {
+                max_val
+            } else {
+                src_mac_max_val
+            };
+            let dataset = Dataset::default()
+                .name(self.mac_name(src_mac))
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::Indexed(color_index)))
+                .data(data);
+            datasets.push(dataset);
+            color_index += 1;
+        }
+
         if self.autoscaling[&ZoomContext::Byte] {
             let upper_bound = get_autoscale_axis_bound(max_val);
             self.byte_count_y_bounds[1] = upper_bound;
@@ -518,7 +896,7 @@ impl EthernetView {
 
         let border_style = match self.zoom_context {
             ZoomContext::Packet => Style::default(),
-            ZoomContext::Byte => Style::default().fg(ZOOM_CONTEXT_COLOR),
+            ZoomContext::Byte => Style::default().fg(self.colors.zoom_context),
         };
 
         let y_axis_title = if self.autoscaling[&ZoomContext::Byte] {
@@ -531,19 +909,19 @@ impl EthernetView {
             .block(
                 Block::bordered()
                     .border_style(border_style)
-                    .title(format!("Byte count per {TICK_RATE_MS} ms")),
+                    .title(format!("Byte count per {} ms", model.tick_rate_ms)),
             )
             .x_axis(
                 Axis::default()
-                    .title("Time")
-                    .style(Style::default().fg(DISABLED_COLOR))
+                    .title(x_title)
+                    .style(Style::default().fg(self.colors.disabled))
                     .labels(x_labels)
-                    .bounds(model.window),
+                    .bounds(window),
             )
             .y_axis(
                 Axis::default()
                     .title(y_axis_title)
-                    .style(Style::default().fg(DISABLED_COLOR))
+                    .style(Style::default().fg(self.colors.disabled))
                     .labels(y_labels)
                     .bounds(self.byte_count_y_bounds),
             )
@@ -559,22 +937,16 @@ impl EthernetView {
         area: Rect,
         model: &EthernetModel,
     ) {
-        let mut target_src_macs: Vec<&[u8; 6]> = Vec::with_capacity(model.src_macs.len());
-        for src_mac in &model.src_macs {
-            if model.displaying.contains(src_mac) {
-                target_src_macs.push(src_mac);
-            }
-        }
+        let target_src_macs: Vec<(&[u8; 6], &MacEntry)> = model.displayed_macs().collect();
 
         let mut mac_strs: Vec<String> = Vec::with_capacity(target_src_macs.len());
-        for src_mac in &target_src_macs {
-            mac_strs.push(get_mac_string(src_mac));
+        for (src_mac, _) in &target_src_macs {
+            mac_strs.push(self.mac_name(src_mac));
         }
 
         let mut data: Vec<(&str, u64)> = Vec::with_capacity(target_src_macs.len());
-        for (i, src_mac) in target_src_macs.iter().enumerate() {
-            let val = model.cumul_byte_counts.get(*src_mac).unwrap();
-            data.push((mac_strs.get(i).unwrap(), *val));
+        for (i, (_, entry)) in target_src_macs.iter().enumerate() {
+            data.push((mac_strs.get(i).unwrap(), entry.cumul_byte_count));
         }
 
         data.sort_by_key(|datum| std::cmp::Reverse(datum.1));
@@ -595,9 +967,13 @@ impl EthernetView {
                 let color = if model.displaying.contains(src_mac) {
                     Color::default()
                 } else {
-                    DISABLED_COLOR
+                    self.colors.disabled
+                };
+                let label = match self.aliases.mac(src_mac) {
+                    Some(alias) => format!("{} ({})", alias, get_mac_string(src_mac)),
+                    None => get_mac_string(src_mac),
                 };
-                let li = ListItem::new(get_mac_string(src_mac)).style(Style::default().fg(color));
+                let li = ListItem::new(label).style(Style::default().fg(color));
                 li
             })
             .collect();
@@ -620,3 +996,171 @@ impl EthernetView {
         frame.render_stateful_widget(list, list_area, &mut self.src_macs_state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::memory::MemorySource;
+
+    const SRC_MAC_A: [u8; 6] = [0x02, 0, 0, 0, 0, 0xa];
+    const SRC_MAC_B: [u8; 6] = [0x02, 0, 0, 0, 0, 0xb];
+
+    fn receive(source: &mut MemorySource, src_mac: [u8; 6], packet_len: u32) {
+        let mut frame = [0; 64];
+        frame[6..12].copy_from_slice(&src_mac);
+        source.engine.count_frame(1, 0, 0, &frame, packet_len);
+    }
+
+    /// Packets and bytes of each source MAC address during the latest tick
+    fn latest_counts(context: &EthernetContext) -> Vec<([u8; 6], f64, f64)> {
+        let mut counts = context.model.latest_tick_counts();
+        counts.sort_by_key(|count| count.0);
+        counts
+    }
+
+    fn tracked_macs(context: &EthernetContext) -> Vec<[u8; 6]> {
+        let mut src_macs = context.model.src_macs.clone();
+        src_macs.sort();
+        src_macs
+    }
+
+    #[test]
+    fn counts_frames_received_since_the_previous_tick() {
+        let mut source = MemorySource::with_test_interfaces(1);
+        let mut context = EthernetContext::new(&source, &Settings::from_args(&[]));
+
+        receive(&mut source, SRC_MAC_A, 100);
+        receive(&mut source, SRC_MAC_A, 200);
+        receive(&mut source, SRC_MAC_B, 60);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(
+            latest_counts(&context),
+            [(SRC_MAC_A, 2.0, 300.0), (SRC_MAC_B, 1.0, 60.0)]
+        );
+
+        receive(&mut source, SRC_MAC_B, 1500);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(latest_counts(&context), [(SRC_MAC_B, 1.0, 1500.0)]);
+
+        context.handle_tick(&mut source).unwrap();
+        assert!(latest_counts(&context).is_empty());
+        assert_eq!(tracked_macs(&context), [SRC_MAC_A, SRC_MAC_B]);
+    }
+
+    #[test]
+    fn starts_from_the_counts_already_held_by_the_source() {
+        let mut source = MemorySource::with_test_interfaces(1);
+        receive(&mut source, SRC_MAC_A, 100);
+        let mut context = EthernetContext::new(&source, &Settings::from_args(&[]));
+
+        context.handle_tick(&mut source).unwrap();
+        assert!(latest_counts(&context).is_empty());
+
+        receive(&mut source, SRC_MAC_A, 100);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(latest_counts(&context), [(SRC_MAC_A, 1.0, 100.0)]);
+    }
+
+    #[test]
+    fn exports_series_oldest_first() {
+        let mut source = MemorySource::with_test_interfaces(1);
+        let mut context = EthernetContext::new(&source, &Settings::from_args(&[]));
+
+        for packet_len in [100, 200, 300] {
+            receive(&mut source, SRC_MAC_A, packet_len);
+            context.handle_tick(&mut source).unwrap();
+        }
+        let points: Vec<(u64, u64)> = context
+            .model
+            .series()
+            .iter()
+            .map(|point| (point.tick, point.bytes))
+            .collect();
+        assert_eq!(points, [(1, 100), (2, 200), (3, 300)]);
+    }
+
+    #[test]
+    fn keeps_history_for_so_many_addresses_and_the_displayed_ones() {
+        let mut source = MemorySource::with_test_interfaces(1);
+        let mut context = EthernetContext::new(&source, &Settings::from_args(&[]));
+        let src_macs: Vec<[u8; 6]> = (0..=MAX_MACS_WITH_HISTORY as u16)
+            .map(|index| {
+                let [a, b] = index.to_be_bytes();
+                [0x02, 0, 0, 0, a, b]
+            })
+            .collect();
+
+        for src_mac in &src_macs {
+            receive(&mut source, *src_mac, 100);
+        }
+        context.handle_tick(&mut source).unwrap();
+        // The source reads the addresses in no particular order, so any one of them is left out
+        let without_history: Vec<[u8; 6]> = src_macs
+            .iter()
+            .filter(|src_mac| context.model.macs[*src_mac].history.is_none())
+            .copied()
+            .collect();
+        assert_eq!(without_history.len(), 1);
+
+        context.model.toggle_display(&without_history[0]);
+        assert!(context.model.macs[&without_history[0]].history.is_some());
+        assert_eq!(latest_counts(&context).len(), src_macs.len());
+    }
+
+    #[test]
+    fn breaks_down_only_the_selected_address_by_cpu() {
+        let mut source = MemorySource::with_test_interfaces(1);
+        let mut context = EthernetContext::new(&source, &Settings::from_args(&[]));
+        receive(&mut source, SRC_MAC_A, 100);
+        receive(&mut source, SRC_MAC_B, 100);
+        context.handle_tick(&mut source).unwrap();
+        context
+            .handle_action(Action::SortSelection, &mut source)
+            .unwrap();
+
+        // The breakdown starts from the counts at the tick on which A is first selected
+        context.handle_tick(&mut source).unwrap();
+        receive(&mut source, SRC_MAC_A, 200);
+        receive(&mut source, SRC_MAC_B, 200);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(context.model.window_cpu_counts(&SRC_MAC_A), [(0, 1, 200)]);
+        assert!(context.model.window_cpu_counts(&SRC_MAC_B).is_empty());
+
+        context
+            .handle_action(Action::SelectNext, &mut source)
+            .unwrap();
+        context.handle_tick(&mut source).unwrap();
+        receive(&mut source, SRC_MAC_B, 60);
+        context.handle_tick(&mut source).unwrap();
+        assert!(context.model.window_cpu_counts(&SRC_MAC_A).is_empty());
+        assert_eq!(context.model.window_cpu_counts(&SRC_MAC_B), [(0, 1, 60)]);
+    }
+
+    #[test]
+    fn removes_idle_mac_addresses() {
+        let mut source = MemorySource::with_test_interfaces(1);
+        // Ticks of a second, so that the timeout is 2 ticks
+        let settings = Settings::from_args(&["--tick-rate", "1000", "--mac-idle-timeout", "2"]);
+        let mut context = EthernetContext::new(&source, &settings);
+
+        receive(&mut source, SRC_MAC_A, 100);
+        receive(&mut source, SRC_MAC_B, 100);
+        context.handle_tick(&mut source).unwrap();
+
+        receive(&mut source, SRC_MAC_B, 100);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(tracked_macs(&context), [SRC_MAC_A, SRC_MAC_B]);
+
+        // A has been idle for 2 ticks, while B received a frame on the tick before
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(tracked_macs(&context), [SRC_MAC_B]);
+        assert!(!context.model.macs.contains_key(&SRC_MAC_A));
+        assert!(!source.engine.mac_counters.contains_key(&SRC_MAC_A));
+
+        // An address which comes back is tracked again from the frames received since
+        receive(&mut source, SRC_MAC_A, 60);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(tracked_macs(&context), [SRC_MAC_A]);
+        assert_eq!(latest_counts(&context), [(SRC_MAC_A, 1.0, 60.0)]);
+    }
+}
@@ -0,0 +1,436 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+};
+
+use color_eyre::eyre::Result;
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    widgets::{Block, Row, Table, TableState},
+    Frame,
+};
+use tsndt_common::{
+    MulticastGroupKey, MulticastMembership, MULTICAST_MEMBERSHIP_JOINED, MULTICAST_REPORT_IGMP_V1,
+    MULTICAST_REPORT_IGMP_V2, MULTICAST_REPORT_IGMP_V3, MULTICAST_REPORT_MLD_V1,
+    MULTICAST_REPORT_MLD_V2,
+};
+
+use super::TsndtContext;
+use crate::{
+    keymap::{Action, Keymap},
+    settings::{ColorSettings, Settings},
+    source::DataSource,
+};
+
+const CONTEXT_NAME: &str = "Multicast";
+// Matches the default IGMP group membership interval (RFC 2236 section 8.4) and MLD multicast
+// address listening interval (RFC 2710 section 7.4)
+const IDLE_GROUP_TIMEOUT_SEC: u64 = 260;
+
+type GroupId = (u32, [u8; 16]);
+
+pub(crate) struct MulticastContext {
+    pub(crate) model: MulticastModel,
+    pub(crate) view: MulticastView,
+}
+
+pub(crate) struct MulticastView {
+    groups_state: TableState,
+    groups_height_percentage: u16,
+    colors: ColorSettings,
+}
+
+struct MulticastMember {
+    reporter: [u8; 16],
+    membership: MulticastMembership,
+}
+
+pub(crate) struct MulticastModel {
+    interface_names: HashMap<u32, String>,
+    groups: Vec<GroupId>,
+    members: HashMap<GroupId, Vec<MulticastMember>>,
+    last_active_tick: HashMap<GroupId, f64>,
+    cumul_packet_counts: HashMap<GroupId, u32>,
+    cumul_byte_counts: HashMap<GroupId, u64>,
+    packet_rates: HashMap<GroupId, f64>,
+    byte_rates: HashMap<GroupId, f64>,
+    tick_count: f64,
+    ticks_per_sec: f64,
+    idle_group_timeout_ticks: f64,
+    // Data source time at the latest tick, which report ages are measured against
+    now_ns: u64,
+}
+
+fn get_ip_string(addr: &[u8; 16]) -> String {
+    let addr = Ipv6Addr::from(*addr);
+    let addr = match addr.to_ipv4_mapped() {
+        Some(v4_addr) => IpAddr::V4(v4_addr),
+        None => IpAddr::V6(addr),
+    };
+    addr.to_string()
+}
+
+fn get_report_kind_string(report_kind: u32) -> &'static str {
+    match report_kind {
+        MULTICAST_REPORT_IGMP_V1 => "IGMPv1",
+        MULTICAST_REPORT_IGMP_V2 => "IGMPv2",
+        MULTICAST_REPORT_IGMP_V3 => "IGMPv3",
+        MULTICAST_REPORT_MLD_V1 => "MLDv1",
+        MULTICAST_REPORT_MLD_V2 => "MLDv2",
+        _ => "unknown",
+    }
+}
+
+impl TsndtContext for MulticastContext {
+    fn get_context_name(&self) -> String {
+        String::from(CONTEXT_NAME)
+    }
+
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String> {
+        vec![keymap.help(&[
+            (
+                &[Action::SelectPrevious, Action::SelectNext],
+                "Select group",
+            ),
+            (
+                &[Action::ResizeUp, Action::ResizeDown],
+                "Change table heights",
+            ),
+        ])]
+    }
+
+    fn handle_tick(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        self.model.on_tick(source)
+    }
+
+    fn handle_action(&mut self, action: Action, _source: &mut dyn DataSource) -> Result<()> {
+        match action {
+            Action::ResizeUp => {
+                if self.view.groups_height_percentage < 100 {
+                    self.view.groups_height_percentage += 1;
+                }
+            }
+            Action::SelectPrevious => {
+                let selected = self.view.groups_state.selected().unwrap_or(0);
+                let candidate = if selected > 0 { selected - 1 } else { 0 };
+                self.view.groups_state.select(Some(candidate));
+            }
+            Action::ResizeDown => {
+                if self.view.groups_height_percentage > 0 {
+                    self.view.groups_height_percentage -= 1;
+                }
+            }
+            Action::SelectNext => {
+                let selected = self.view.groups_state.selected().unwrap_or(0);
+                let candidate = selected + 1;
+                if candidate < self.model.groups.len() {
+                    self.view.groups_state.select(Some(candidate));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame, context_area: Rect) {
+        self.view.draw(frame, &self.model, context_area);
+    }
+}
+
+impl MulticastContext {
+    pub(crate) fn new(source: &dyn DataSource, settings: &Settings) -> Self {
+        // Group traffic is keyed by interface index, so keep the names around for display
+        let interface_names = source
+            .interfaces()
+            .iter()
+            .map(|interface| {
+                let name = settings.aliases.interface_or_name(&interface.name);
+                (interface.index, name.to_string())
+            })
+            .collect();
+
+        Self {
+            model: MulticastModel {
+                interface_names,
+                groups: Vec::new(),
+                members: HashMap::new(),
+                last_active_tick: HashMap::new(),
+                cumul_packet_counts: HashMap::new(),
+                cumul_byte_counts: HashMap::new(),
+                packet_rates: HashMap::new(),
+                byte_rates: HashMap::new(),
+                tick_count: 0.0,
+                ticks_per_sec: settings.ticks(1),
+                idle_group_timeout_ticks: settings.ticks(IDLE_GROUP_TIMEOUT_SEC),
+                now_ns: 0,
+            },
+            view: MulticastView {
+                groups_state: TableState::default().with_selected(Some(0)),
+                groups_height_percentage: settings.plots.multicast_groups_height_percentage,
+                colors: settings.colors.clone(),
+            },
+        }
+    }
+}
+
+impl MulticastModel {
+    fn on_tick(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        self.tick_count += 1.0;
+        let ticks_per_sec = self.ticks_per_sec;
+
+        source.multicast_group_counters(&mut |key, values| {
+            let group_id = (key.if_index, key.group);
+            self.track_group(group_id);
+
+            let mut across_cpus_packet_count: u32 = 0;
+            let mut across_cpus_byte_count: u64 = 0;
+            for cpu_counter in values {
+                across_cpus_packet_count += cpu_counter.packets;
+                across_cpus_byte_count += cpu_counter.bytes;
+            }
+
+            let prev_packet_count_val = self
+                .cumul_packet_counts
+                .insert(group_id, across_cpus_packet_count)
+                .unwrap_or(0);
+            let prev_byte_count_val = self
+                .cumul_byte_counts
+                .insert(group_id, across_cpus_byte_count)
+                .unwrap_or(0);
+
+            // The eBPF map is an LRU, so counts can restart from zero if an entry was evicted
+            let packet_delta = across_cpus_packet_count.saturating_sub(prev_packet_count_val);
+            let byte_delta = across_cpus_byte_count.saturating_sub(prev_byte_count_val);
+            self.packet_rates
+                .insert(group_id, packet_delta as f64 * ticks_per_sec);
+            self.byte_rates
+                .insert(group_id, byte_delta as f64 * ticks_per_sec);
+
+            if packet_delta > 0 {
+                self.last_active_tick.insert(group_id, self.tick_count);
+            }
+        })?;
+
+        let now_ns = source.now_ns();
+        self.now_ns = now_ns;
+        let member_timeout_ns = IDLE_GROUP_TIMEOUT_SEC * 1_000_000_000;
+        let mut expired_members = Vec::new();
+        self.members.clear();
+        source.multicast_members(&mut |key, membership| {
+            if now_ns.saturating_sub(membership.last_report_ns) >= member_timeout_ns {
+                expired_members.push(*key);
+                return;
+            }
+
+            let group_id = (key.if_index, key.group);
+            self.track_group(group_id);
+            if membership.state == MULTICAST_MEMBERSHIP_JOINED {
+                self.last_active_tick.insert(group_id, self.tick_count);
+            }
+            self.members
+                .entry(group_id)
+                .or_default()
+                .push(MulticastMember {
+                    reporter: key.reporter,
+                    membership: *membership,
+                });
+        })?;
+
+        for key in &expired_members {
+            source.remove_multicast_member(key)?;
+        }
+
+        // Remove groups without members or traffic for the duration of the timeout period
+        let mut to_remove = Vec::new();
+        for (group_id, last_active_tick) in &self.last_active_tick {
+            if self.tick_count - self.idle_group_timeout_ticks >= *last_active_tick {
+                to_remove.push(*group_id);
+            }
+        }
+
+        for group_id in &to_remove {
+            self.last_active_tick.remove(group_id);
+            self.cumul_packet_counts.remove(group_id);
+            self.cumul_byte_counts.remove(group_id);
+            self.packet_rates.remove(group_id);
+            self.byte_rates.remove(group_id);
+            self.members.remove(group_id);
+            if let Some(index) = self.groups.iter().position(|value| value == group_id) {
+                self.groups.remove(index);
+            }
+
+            // The entry may already have been evicted from the LRU map
+            let _ = source.remove_multicast_group_counters(&MulticastGroupKey {
+                if_index: group_id.0,
+                group: group_id.1,
+            });
+        }
+
+        for members in self.members.values_mut() {
+            members.sort_by_key(|member| member.reporter);
+        }
+
+        Ok(())
+    }
+
+    fn track_group(&mut self, group_id: GroupId) {
+        if !self.last_active_tick.contains_key(&group_id) {
+            self.last_active_tick.insert(group_id, self.tick_count);
+            self.groups.push(group_id);
+            self.groups.sort();
+        }
+    }
+
+    fn interface_name(&self, if_index: u32) -> String {
+        self.interface_names
+            .get(&if_index)
+            .cloned()
+            .unwrap_or_else(|| if_index.to_string())
+    }
+}
+
+impl MulticastView {
+    fn draw(&mut self, frame: &mut Frame, model: &MulticastModel, context_area: Rect) {
+        let [groups_area, members_area] = Layout::vertical([
+            Constraint::Percentage(self.groups_height_percentage),
+            Constraint::Fill(1),
+        ])
+        .areas(context_area);
+
+        self.render_groups(frame, groups_area, model);
+        self.render_members(frame, members_area, model);
+    }
+
+    fn render_groups(&mut self, frame: &mut Frame, area: Rect, model: &MulticastModel) {
+        let rows: Vec<Row> = model
+            .groups
+            .iter()
+            .map(|group_id| {
+                let members = model.members.get(group_id);
+                let joined_count = members
+                    .map(|members| {
+                        members
+                            .iter()
+                            .filter(|member| member.membership.state == MULTICAST_MEMBERSHIP_JOINED)
+                            .count()
+                    })
+                    .unwrap_or(0);
+                let color = if joined_count > 0 {
+                    Color::default()
+                } else {
+                    self.colors.disabled
+                };
+
+                Row::new(vec![
+                    model.interface_name(group_id.0),
+                    get_ip_string(&group_id.1),
+                    joined_count.to_string(),
+                    format!("{:.1}", model.packet_rates.get(group_id).unwrap_or(&0.0)),
+                    format!("{:.1}", model.byte_rates.get(group_id).unwrap_or(&0.0)),
+                ])
+                .style(Style::default().fg(color))
+            })
+            .collect();
+
+        // If the table was empty, then the selected index may be set to none.
+        // Once the table has entries in it, default to selecting index 0 if
+        // it was none.
+        if self.groups_state.selected().is_none() && !rows.is_empty() {
+            self.groups_state.select(Some(0));
+        }
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(15),
+                Constraint::Percentage(40),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+            ],
+        )
+        .header(
+            Row::new(vec![
+                "Interface",
+                "Group",
+                "Members",
+                "Packets/s",
+                "Bytes/s",
+            ])
+            .bold(),
+        )
+        .block(Block::bordered().title("Multicast Groups"))
+        .row_highlight_style(Style::new().italic())
+        .highlight_symbol(">");
+
+        frame.render_stateful_widget(table, area, &mut self.groups_state);
+    }
+
+    fn render_members(&mut self, frame: &mut Frame, area: Rect, model: &MulticastModel) {
+        let selected = self.groups_state.selected().unwrap_or(0);
+        let group_id = model.groups.get(selected);
+        let now_ns = model.now_ns;
+
+        let rows: Vec<Row> = group_id
+            .and_then(|group_id| model.members.get(group_id))
+            .map(|members| {
+                members
+                    .iter()
+                    .map(|member| {
+                        let joined = member.membership.state == MULTICAST_MEMBERSHIP_JOINED;
+                        let last_report_age_sec =
+                            now_ns.saturating_sub(member.membership.last_report_ns) as f64
+                                / 1_000_000_000.0;
+                        Row::new(vec![
+                            get_ip_string(&member.reporter),
+                            String::from(get_report_kind_string(member.membership.report_kind)),
+                            String::from(if joined { "joined" } else { "left" }),
+                            member.membership.report_count.to_string(),
+                            format!("{last_report_age_sec:.1}s"),
+                        ])
+                        .style(Style::default().fg(if joined {
+                            Color::default()
+                        } else {
+                            self.colors.disabled
+                        }))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let title = match group_id {
+            Some(group_id) => format!(
+                "Reporters for {} on {}",
+                get_ip_string(&group_id.1),
+                model.interface_name(group_id.0)
+            ),
+            None => String::from("Reporters"),
+        };
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(40),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+            ],
+        )
+        .header(
+            Row::new(vec![
+                "Reporter",
+                "Protocol",
+                "State",
+                "Reports",
+                "Last report",
+            ])
+            .bold(),
+        )
+        .block(Block::bordered().title(title));
+
+        frame.render_widget(table, area);
+    }
+}
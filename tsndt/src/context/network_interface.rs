@@ -1,13 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
-use aya::{
-    maps::{MapData, PerCpuValues},
-    programs::{xdp::XdpLinkId, Xdp, XdpFlags},
-};
-use aya_log::EbpfLogger;
-use color_eyre::eyre::{eyre, Context, Result};
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use color_eyre::eyre::{eyre, Result};
+use network_interface::NetworkInterface;
 use ratatui::{
     layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style, Stylize},
@@ -19,23 +13,51 @@ use ratatui::{
     },
     Frame,
 };
-use tsndt_common::Counter;
+use tsndt_common::{Counter, INTER_ARRIVAL_NUM_BUCKETS};
 
-use super::TsndtContext;
-use crate::app::TICK_RATE_MS;
+use super::{
+    rollup::{Rollups, TimeRange},
+    series::{CpuCounts, Series},
+    time_window::TimeWindow,
+    TsndtContext,
+};
+use crate::{
+    export::SeriesPoint,
+    keymap::{Action, Keymap},
+    settings::{Aliases, ColorSettings, Settings},
+    source::DataSource,
+};
 
-const DISABLED_COLOR: Color = Color::Rgb(100, 100, 100);
-const ZOOM_CONTEXT_COLOR: Color = Color::LightBlue;
-const DEFAULT_HISTOGRAM_WIDTH_PERCENTAGE: u16 = 25;
-const DEFAULT_BYTE_COUNTERS_HEIGHT_PERCENTAGE: u16 = 50;
 const CONTEXT_NAME: &str = "Network Interfaces";
 
+type InterArrivalCounts = [u64; INTER_ARRIVAL_NUM_BUCKETS];
+
 #[derive(Clone, Eq, PartialEq, Hash)]
 enum ZoomContext {
     Packet,
     Byte,
 }
 
+/// The set of plots shown next to the interface list
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum PlotsMode {
+    Counters,
+    RxQueues,
+    PerCpu,
+    InterArrival,
+}
+
+impl PlotsMode {
+    fn next(self) -> Self {
+        match self {
+            PlotsMode::Counters => PlotsMode::RxQueues,
+            PlotsMode::RxQueues => PlotsMode::PerCpu,
+            PlotsMode::PerCpu => PlotsMode::InterArrival,
+            PlotsMode::InterArrival => PlotsMode::Counters,
+        }
+    }
+}
+
 pub(crate) struct NetworkInterfaceContext {
     pub(crate) model: NetworkInterfaceModel,
     pub(crate) view: NetworkInterfaceView,
@@ -49,19 +71,71 @@ pub(crate) struct NetworkInterfaceView {
     byte_counter_height_percentage: u16,
     zoom_context: ZoomContext,
     autoscaling: HashMap<ZoomContext, bool>,
+    plots_mode: PlotsMode,
+    time_range: TimeRange,
+    showing_peaks: bool,
+    colors: ColorSettings,
+    aliases: Aliases,
+}
+
+/// Everything kept about an interface, together so that a tick looks it up only once
+struct InterfaceEntry {
+    interface: NetworkInterface,
+    collecting: bool,
+    // Which backend of the data source is collecting on the interface, if it has several
+    backend: Option<String>,
+    cumul_packet_count: u32,
+    tick_packet_counts: Series,
+    cumul_byte_count: u64,
+    tick_byte_counts: Series,
+    // Peak microburst counts on the busiest CPU, scaled up from the burst bucket width to a full
+    // tick so that they can be plotted alongside the per-tick counts
+    tick_peak_packet_counts: Series,
+    tick_peak_byte_counts: Series,
+    // Per-tick counts rolled up over longer periods than the history
+    packet_count_rollups: Rollups,
+    byte_count_rollups: Rollups,
+    // Indexed by CPU
+    cpus: Vec<CpuCounts>,
+    // Cumulative inter-arrival histograms at each tick of the history, oldest first, of which
+    // those from the start and end of the window are differenced to get the histogram for the
+    // window
+    inter_arrival_snapshots: VecDeque<(f64, InterArrivalCounts)>,
 }
 
 pub(crate) struct NetworkInterfaceModel {
-    interfaces: Vec<NetworkInterface>,
-    cumul_packet_counts: HashMap<u32, u32>,
-    tick_packet_count_data: HashMap<u32, Vec<(f64, f64)>>,
-    cumul_byte_counts: HashMap<u32, u64>,
-    tick_byte_count_data: HashMap<u32, Vec<(f64, f64)>>,
+    burst_bucket_ns: u64,
+    // Listed in the order of the data source
+    interfaces: Vec<InterfaceEntry>,
+    burst_epoch: u64,
+    cumul_rx_queue_packet_counts: HashMap<(u32, u32), u64>,
+    tick_rx_queue_packet_count_data: HashMap<(u32, u32), Series>,
+    cumul_rx_queue_byte_counts: HashMap<(u32, u32), u64>,
+    tick_rx_queue_byte_count_data: HashMap<(u32, u32), Series>,
     tick_count: f64,
-    collecting: HashMap<u32, bool>,
-    xdp_link_ids: HashMap<u32, XdpLinkId>,
-    window_size: f64,
-    window: [f64; 2],
+    tick_rate_ms: u64,
+    ticks_per_sec: f64,
+    time_window: TimeWindow,
+}
+
+/// Formats a power of two nanosecond duration compactly enough for a bar label
+fn format_duration_ns(duration_ns: u64) -> String {
+    if duration_ns >= 1_000_000_000 {
+        format!("{:.1}s", duration_ns as f64 / 1e9)
+    } else if duration_ns >= 1_000_000 {
+        format!("{}ms", duration_ns / 1_000_000)
+    } else if duration_ns >= 1_000 {
+        format!("{}µs", duration_ns / 1_000)
+    } else {
+        format!("{duration_ns}ns")
+    }
+}
+
+/// A series starting from a count of 0 at tick 0, so that an interface is plotted from the start
+fn zeroed_series(series_capacity: usize) -> Series {
+    let mut series = Series::new(series_capacity);
+    series.push(0.0, 0.0);
+    series
 }
 
 fn get_autoscale_axis_bound(max_val: f64) -> f64 {
@@ -74,138 +148,160 @@ fn get_autoscale_axis_bound(max_val: f64) -> f64 {
     axis_val * f64::ceil(val)
 }
 
-fn init_ebpf_programs(
-    interfaces: &Vec<NetworkInterface>,
-    bpf: &mut aya::Ebpf,
-) -> Result<HashMap<u32, XdpLinkId>> {
-    EbpfLogger::init(bpf).unwrap();
-
-    let mut xdp_link_ids = HashMap::new();
-
-    let program: &mut Xdp = bpf.program_mut("xdp_tsndt").unwrap().try_into().unwrap();
-    program.load().unwrap();
-
-    let num_cpus =
-        aya::util::nr_cpus().unwrap_or_else(|_| panic!("Unable to obtain the number of CPUs"));
-
-    for interface in interfaces {
-        let link_id = program.attach(&interface.name, XdpFlags::default())
-            .context("failed to attach the XDP program with default flags - try changing XdpFlags::default() to XdpFlags::SKB_MODE").unwrap();
-        xdp_link_ids.insert(interface.index, link_id);
-    }
-
-    let mut ebpf_interface_rx_counters: aya::maps::PerCpuHashMap<&mut MapData, u32, Counter> =
-        aya::maps::PerCpuHashMap::try_from(bpf.map_mut("IF_RX_COUNT").unwrap()).unwrap();
-
-    for interface in interfaces {
-        if ebpf_interface_rx_counters.get(&interface.index, 0).is_err() {
-            ebpf_interface_rx_counters.insert(
-                interface.index,
-                PerCpuValues::try_from(vec![
-                    Counter {
-                        bytes: 0,
-                        packets: 0
-                    };
-                    num_cpus
-                ])?,
-                0,
-            )?;
-        }
-    }
-
-    Ok(xdp_link_ids)
-}
-
 impl TsndtContext for NetworkInterfaceContext {
     fn get_context_name(&self) -> String {
         String::from(CONTEXT_NAME)
     }
 
-    fn get_command_help(&self) -> Vec<String> {
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String> {
         vec![
-            String::from("(↑/↓) Select interface, (t) Toggle interface monitoring"),
-            String::from(
-                "(b/p) Select plot zoom context, (a) Toggle autoscaling, (+/-) Y axis zoom",
-            ),
-            String::from("(Ctrl + ←/→): Change plot widths, (Ctrl + ↑/↓): Change plot heights"),
+            keymap.help(&[
+                (
+                    &[Action::SelectPrevious, Action::SelectNext],
+                    "Select interface",
+                ),
+                (&[Action::ToggleSelected], "Toggle interface monitoring"),
+                (&[Action::CycleXdpMode], "Cycle XDP mode"),
+            ]),
+            keymap.help(&[
+                (
+                    &[Action::ZoomBytes, Action::ZoomPackets],
+                    "Select plot zoom context",
+                ),
+                (&[Action::ToggleAutoscaling], "Toggle autoscaling"),
+                (&[Action::ZoomIn, Action::ZoomOut], "Y axis zoom"),
+            ]),
+            keymap.help(&[
+                (
+                    &[Action::ResizeLeft, Action::ResizeRight],
+                    "Change plot widths",
+                ),
+                (
+                    &[Action::ResizeUp, Action::ResizeDown],
+                    "Change plot heights",
+                ),
+            ]),
+            keymap.help(&[
+                (
+                    &[Action::CyclePlots],
+                    "Cycle plot views (counters, RX queues, per-CPU, inter-arrival times)",
+                ),
+                (&[Action::TogglePeaks], "Toggle microburst peaks"),
+            ]),
+            keymap.help(&[
+                (
+                    &[Action::CycleTimeRange],
+                    "Cycle time range (window, 10 min, 6 h)",
+                ),
+                (&[Action::PauseDisplay], "Pause/resume display"),
+                (&[Action::ScrollBack, Action::ScrollForward], "Scroll time"),
+                (&[Action::ZoomTimeIn, Action::ZoomTimeOut], "Time axis zoom"),
+            ]),
         ]
     }
 
-    fn handle_tick(&mut self, bpf: &mut aya::Ebpf) -> Result<()> {
-        self.model.on_tick(bpf)
+    fn handle_tick(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        self.model.on_tick(source)
     }
 
-    fn handle_key_event(&mut self, key: KeyEvent, bpf: &mut aya::Ebpf) -> Result<()> {
-        match key.code {
-            KeyCode::Char('b') => {
+    fn handle_action(&mut self, action: Action, source: &mut dyn DataSource) -> Result<()> {
+        if self
+            .model
+            .time_window
+            .handle_action(action, self.model.tick_count)
+        {
+            return Ok(());
+        }
+
+        match action {
+            Action::ZoomBytes => {
                 self.view.zoom_context = ZoomContext::Byte;
             }
-            KeyCode::Char('p') => {
+            Action::ZoomPackets => {
                 self.view.zoom_context = ZoomContext::Packet;
             }
-            KeyCode::Char('a') => {
+            Action::ToggleAutoscaling => {
                 let val = !self.view.autoscaling[&self.view.zoom_context];
                 self.view
                     .autoscaling
                     .insert(self.view.zoom_context.clone(), val);
             }
-            KeyCode::Char('-') => match self.view.zoom_context {
+            Action::CyclePlots => {
+                self.view.plots_mode = self.view.plots_mode.next();
+            }
+            Action::CycleTimeRange => {
+                self.view.time_range = self.view.time_range.next();
+            }
+            Action::TogglePeaks => {
+                self.view.showing_peaks = !self.view.showing_peaks;
+            }
+            Action::ZoomOut => match self.view.zoom_context {
                 ZoomContext::Packet => self.view.packet_count_y_bounds[1] *= 2.0,
                 ZoomContext::Byte => self.view.byte_count_y_bounds[1] *= 2.0,
             },
-            KeyCode::Char('+') => match self.view.zoom_context {
+            Action::ZoomIn => match self.view.zoom_context {
                 ZoomContext::Packet => self.view.packet_count_y_bounds[1] /= 2.0,
                 ZoomContext::Byte => self.view.byte_count_y_bounds[1] /= 2.0,
             },
-            KeyCode::Up => {
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    // Change the height of the plots
-                    if self.view.byte_counter_height_percentage < 100 {
-                        self.view.byte_counter_height_percentage += 1;
-                    }
-                } else {
-                    // Move the selected item in the interface list up
-                    let selected = self.view.interfaces_state.selected().unwrap_or(0);
-                    let candidate = if selected > 0 { selected - 1 } else { 0 };
-                    self.view.interfaces_state.select(Some(candidate));
+            Action::ResizeUp => {
+                // Change the height of the plots
+                if self.view.byte_counter_height_percentage < 100 {
+                    self.view.byte_counter_height_percentage += 1;
                 }
             }
-            KeyCode::Down => {
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    // Change the height of the plots
-                    if self.view.byte_counter_height_percentage > 0 {
-                        self.view.byte_counter_height_percentage -= 1;
-                    }
-                } else {
-                    // Move the selected item in the interface list down
-                    let selected = self.view.interfaces_state.selected().unwrap_or(0);
-                    let candidate = selected + 1;
-                    if candidate < self.model.interfaces.len() {
-                        self.view.interfaces_state.select(Some(candidate));
-                    }
+            Action::SelectPrevious => {
+                // Move the selected item in the interface list up
+                let selected = self.view.interfaces_state.selected().unwrap_or(0);
+                let candidate = if selected > 0 { selected - 1 } else { 0 };
+                self.view.interfaces_state.select(Some(candidate));
+            }
+            Action::ResizeDown => {
+                // Change the height of the plots
+                if self.view.byte_counter_height_percentage > 0 {
+                    self.view.byte_counter_height_percentage -= 1;
+                }
+            }
+            Action::SelectNext => {
+                // Move the selected item in the interface list down
+                let selected = self.view.interfaces_state.selected().unwrap_or(0);
+                let candidate = selected + 1;
+                if candidate < self.model.interfaces.len() {
+                    self.view.interfaces_state.select(Some(candidate));
                 }
             }
-            KeyCode::Right => {
-                if key.modifiers.contains(KeyModifiers::CONTROL)
-                    && self.view.histogram_width_percentage > 0
-                {
+            Action::ResizeRight => {
+                if self.view.histogram_width_percentage > 0 {
                     self.view.histogram_width_percentage -= 1;
                 }
             }
-            KeyCode::Left => {
-                if key.modifiers.contains(KeyModifiers::CONTROL)
-                    && self.view.histogram_width_percentage < 100
-                {
+            Action::ResizeLeft => {
+                if self.view.histogram_width_percentage < 100 {
                     self.view.histogram_width_percentage += 1;
                 }
             }
-            KeyCode::Char('t') => {
+            Action::CycleXdpMode => {
                 let selected = self.view.interfaces_state.selected().unwrap_or(0);
-                let interface = self.model.interfaces.get(selected);
+                if let Some(interface) = self.model.interface(selected) {
+                    let result = match source.xdp_mode(interface) {
+                        Some(xdp_mode) => source.set_xdp_mode(interface, xdp_mode.next()),
+                        None => Ok(()),
+                    };
+                    if let Err(report) = result {
+                        tracing::warn!(
+                            "Failed to change the XDP mode of interface {}: {}",
+                            interface.name,
+                            report
+                        );
+                    }
+                }
+            }
+            Action::ToggleSelected => {
+                let selected = self.view.interfaces_state.selected().unwrap_or(0);
+                let interface = self.model.interface(selected);
                 if let Some(interface) = interface {
                     let interface_index = interface.index;
                     let interface_name = interface.name.clone();
-                    let result = self.model.toggle_ebpf_program(interface_index, bpf);
+                    let result = self.model.toggle_collection(interface_index, source);
                     if let Err(report) = result {
                         tracing::warn!("Failed to toggle interface {}: {}", interface_name, report);
                     }
@@ -225,240 +321,387 @@ impl TsndtContext for NetworkInterfaceContext {
     fn draw(&mut self, frame: &mut Frame, context_area: Rect) {
         self.view.draw(frame, &self.model, context_area);
     }
+
+    fn export_series(&self) -> Option<Vec<SeriesPoint>> {
+        Some(self.model.series())
+    }
 }
 
 impl NetworkInterfaceContext {
-    pub(crate) fn new(bpf: &mut aya::Ebpf) -> Self {
-        // Initialize the interfaces list to include all known interfaces on the host system
-        let mut interfaces = NetworkInterface::show().unwrap();
-        interfaces.sort_by(|a, b| a.index.partial_cmp(&b.index).unwrap());
+    pub(crate) fn new(source: &dyn DataSource, settings: &Settings) -> Self {
+        // Initialize the interfaces list to include all interfaces known to the data source
+        let interfaces = source.interfaces().to_vec();
         let interfaces_state = ListState::default().with_selected(Some(0));
+        let time_window = TimeWindow::new(settings);
+        let series_capacity = time_window.series_capacity();
+        let ticks_per_sec = settings.ticks(1);
+
+        // Start from the counts that the source already holds, which are not 0 for counters
+        // pinned by an earlier run, so that they do not show up as traffic on the first tick.
+        // The data source starts out collecting on all interfaces.
+        let interfaces = interfaces
+            .into_iter()
+            .map(|interface| {
+                let mut entry = InterfaceEntry::new(interface, series_capacity, ticks_per_sec);
+                let _ = source.interface_counters(entry.interface.index, &mut |counters| {
+                    entry.cumul_packet_count = counters.iter().map(|counter| counter.packets).sum();
+                    entry.cumul_byte_count = counters.iter().map(|counter| counter.bytes).sum();
+                    entry.cpus = counters
+                        .iter()
+                        .map(|counter| CpuCounts::new(counter, series_capacity))
+                        .collect();
+                });
+                entry
+            })
+            .collect();
 
-        // Initialize packet counts to 0
-        let mut cur_packet_counts = HashMap::new();
-        let mut cumul_packet_counts = HashMap::new();
-        let mut tick_packet_count_data: HashMap<u32, Vec<(f64, f64)>> = HashMap::new();
-        for interface in &interfaces {
-            tick_packet_count_data.insert(interface.index, vec![(0.0, 0.0); 1]);
-            cur_packet_counts.insert(interface.index, 0);
-            cumul_packet_counts.insert(interface.index, 0);
-        }
-
-        // Initialize byte counts to 0
-        let mut cur_byte_counts = HashMap::new();
-        let mut cumul_byte_counts = HashMap::new();
-        let mut tick_byte_count_data: HashMap<u32, Vec<(f64, f64)>> = HashMap::new();
-        for interface in &interfaces {
-            tick_byte_count_data.insert(interface.index, vec![(0.0, 0.0); 1]);
-            cur_byte_counts.insert(interface.index, 0);
-            cumul_byte_counts.insert(interface.index, 0);
-        }
-
-        // Enable collection on all interfaces
-        let mut collecting = HashMap::new();
-        for interface in &interfaces {
-            collecting.insert(interface.index, true);
-        }
-
-        // Load the eBPF programs
-        let xdp_link_ids = init_ebpf_programs(&interfaces, bpf).unwrap();
-
-        // Turn on autoscaling by default
-        let autoscaling = HashMap::from([(ZoomContext::Byte, true), (ZoomContext::Packet, true)]);
+        let autoscaling = HashMap::from([
+            (ZoomContext::Byte, settings.plots.autoscaling),
+            (ZoomContext::Packet, settings.plots.autoscaling),
+        ]);
 
         Self {
             model: NetworkInterfaceModel {
-                window_size: 50.0,
-                window: [0.0, 50.0],
+                burst_bucket_ns: settings.burst_bucket_ns,
+                tick_rate_ms: settings.tick_rate_ms,
+                ticks_per_sec,
+                time_window,
                 tick_count: 0.0,
                 interfaces,
-                tick_packet_count_data,
-                cumul_packet_counts,
-                tick_byte_count_data,
-                cumul_byte_counts,
-                collecting,
-                xdp_link_ids,
+                burst_epoch: 0,
+                cumul_rx_queue_packet_counts: HashMap::new(),
+                tick_rx_queue_packet_count_data: HashMap::new(),
+                cumul_rx_queue_byte_counts: HashMap::new(),
+                tick_rx_queue_byte_count_data: HashMap::new(),
             },
             view: NetworkInterfaceView {
-                packet_count_y_bounds: [0.0, 40.0],
-                byte_count_y_bounds: [0.0, 50000.0],
-                histogram_width_percentage: DEFAULT_HISTOGRAM_WIDTH_PERCENTAGE,
+                packet_count_y_bounds: [0.0, settings.plots.packet_axis_max],
+                byte_count_y_bounds: [0.0, settings.plots.byte_axis_max],
+                histogram_width_percentage: settings.plots.histogram_width_percentage,
                 zoom_context: ZoomContext::Packet,
-                byte_counter_height_percentage: DEFAULT_BYTE_COUNTERS_HEIGHT_PERCENTAGE,
+                byte_counter_height_percentage: settings.plots.byte_counters_height_percentage,
                 autoscaling,
                 interfaces_state,
+                plots_mode: PlotsMode::Counters,
+                time_range: TimeRange::Window,
+                showing_peaks: true,
+                colors: settings.colors.clone(),
+                aliases: settings.aliases.clone(),
             },
         }
     }
 }
 
+impl InterfaceEntry {
+    fn new(interface: NetworkInterface, series_capacity: usize, ticks_per_sec: f64) -> Self {
+        Self {
+            interface,
+            collecting: true,
+            backend: None,
+            cumul_packet_count: 0,
+            tick_packet_counts: zeroed_series(series_capacity),
+            cumul_byte_count: 0,
+            tick_byte_counts: zeroed_series(series_capacity),
+            tick_peak_packet_counts: zeroed_series(series_capacity),
+            tick_peak_byte_counts: zeroed_series(series_capacity),
+            packet_count_rollups: Rollups::new(ticks_per_sec),
+            byte_count_rollups: Rollups::new(ticks_per_sec),
+            cpus: Vec::new(),
+            inter_arrival_snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Adds the counts received since the previous tick, given the cumulative counts of each CPU
+    fn on_tick(&mut self, tick_count: f64, values: &[Counter], series_capacity: usize) {
+        // Sum up the value across all CPUs, while keeping the per-CPU deltas around so that
+        // uneven softirq load across CPUs can be shown
+        let mut across_cpus_packet_count: u32 = 0;
+        let mut across_cpus_byte_count: u64 = 0;
+        for cpu_counter in values {
+            across_cpus_packet_count += cpu_counter.packets;
+            across_cpus_byte_count += cpu_counter.bytes;
+        }
+        CpuCounts::push_all(&mut self.cpus, tick_count, values, series_capacity);
+
+        let packet_count = across_cpus_packet_count.saturating_sub(self.cumul_packet_count) as f64;
+        self.tick_packet_counts.push(tick_count, packet_count);
+        self.packet_count_rollups.push(tick_count, packet_count);
+        self.cumul_packet_count = across_cpus_packet_count;
+
+        let byte_count = across_cpus_byte_count.saturating_sub(self.cumul_byte_count) as f64;
+        self.tick_byte_counts.push(tick_count, byte_count);
+        self.byte_count_rollups.push(tick_count, byte_count);
+        self.cumul_byte_count = across_cpus_byte_count;
+    }
+
+    /// The per-tick counts, microburst peaks and rollups of packets or bytes
+    fn counts(&self, unit: &ZoomContext) -> (&Series, &Series, &Rollups) {
+        match unit {
+            ZoomContext::Packet => (
+                &self.tick_packet_counts,
+                &self.tick_peak_packet_counts,
+                &self.packet_count_rollups,
+            ),
+            ZoomContext::Byte => (
+                &self.tick_byte_counts,
+                &self.tick_peak_byte_counts,
+                &self.byte_count_rollups,
+            ),
+        }
+    }
+}
+
 impl NetworkInterfaceModel {
-    fn toggle_ebpf_program(&mut self, interface_index: u32, bpf: &mut aya::Ebpf) -> Result<()> {
-        if let Some(is_loaded) = self.collecting.get(&interface_index) {
-            if *is_loaded {
-                let result = self.detach_ebpf_program(interface_index, bpf);
-                if result.is_ok() {
-                    self.collecting.insert(interface_index, false);
-                }
-                result
-            } else {
-                let result = self.attach_ebpf_program(interface_index, bpf);
-                if result.is_ok() {
-                    self.collecting.insert(interface_index, true);
-                }
-                result
-            }
+    fn toggle_collection(
+        &mut self,
+        interface_index: u32,
+        source: &mut dyn DataSource,
+    ) -> Result<()> {
+        let series_capacity = self.time_window.series_capacity();
+        let ticks_per_sec = self.ticks_per_sec;
+        let entry = self
+            .interfaces
+            .iter_mut()
+            .find(|entry| entry.interface.index == interface_index)
+            .ok_or_else(|| {
+                eyre!(
+                    "Could not find an interface with index {} to toggle collection on",
+                    interface_index
+                )
+            })?;
+
+        if entry.collecting {
+            source.detach(&entry.interface)?;
+            entry.collecting = false;
+            entry.tick_packet_counts = zeroed_series(series_capacity);
+            entry.tick_byte_counts = zeroed_series(series_capacity);
+            entry.tick_peak_packet_counts = zeroed_series(series_capacity);
+            entry.tick_peak_byte_counts = zeroed_series(series_capacity);
+            entry.packet_count_rollups = Rollups::new(ticks_per_sec);
+            entry.byte_count_rollups = Rollups::new(ticks_per_sec);
         } else {
-            Err(eyre!(
-                "Could not find an interface with index {} to toggle eBPF program on",
-                interface_index
-            ))
+            source.attach(&entry.interface)?;
+            entry.collecting = true;
         }
+
+        Ok(())
+    }
+
+    /// The interfaces being collected on, in list order
+    fn collected_interfaces(&self) -> impl Iterator<Item = &InterfaceEntry> {
+        self.interfaces.iter().filter(|entry| entry.collecting)
+    }
+
+    /// The interface at a position in the list
+    fn interface(&self, list_index: usize) -> Option<&NetworkInterface> {
+        self.interfaces
+            .get(list_index)
+            .map(|entry| &entry.interface)
     }
 
-    fn find_interface(&self, interface_index: u32) -> Option<NetworkInterface> {
-        let mut target_interface: Option<NetworkInterface> = None;
-        for interface in &self.interfaces {
-            if interface.index == interface_index {
-                target_interface = Some(interface.clone());
-                break;
+    /// Packets and bytes received during the latest tick on each interface being collected on
+    pub(crate) fn latest_tick_counts(&self) -> Vec<(&NetworkInterface, f64, f64)> {
+        let latest_count = |series: &Series| series.latest().map(|datum| datum.1).unwrap_or(0.0);
+        self.collected_interfaces()
+            .map(|entry| {
+                (
+                    &entry.interface,
+                    latest_count(&entry.tick_packet_counts),
+                    latest_count(&entry.tick_byte_counts),
+                )
+            })
+            .collect()
+    }
+
+    /// The packet and byte counts of each tick in the history on each interface being collected
+    /// on
+    fn series(&self) -> Vec<SeriesPoint> {
+        let mut points = Vec::new();
+        for entry in self.collected_interfaces() {
+            let packet_data = entry.tick_packet_counts.iter();
+            for (packet_datum, byte_datum) in packet_data.zip(entry.tick_byte_counts.iter()) {
+                points.push(SeriesPoint {
+                    tick: packet_datum.0 as u64,
+                    entity: entry.interface.name.clone(),
+                    packets: packet_datum.1 as u64,
+                    bytes: byte_datum.1 as u64,
+                });
             }
         }
+        points
+    }
 
-        target_interface
-    }
-
-    fn attach_ebpf_program(&mut self, interface_index: u32, bpf: &mut aya::Ebpf) -> Result<()> {
-        let interface = self.find_interface(interface_index);
-        if let Some(interface) = interface {
-            let program: &mut Xdp = bpf.program_mut("xdp_tsndt").unwrap().try_into()?;
-            let xdp_link_id = program.attach(&interface.name, XdpFlags::default())
-                .context("failed to attach the XDP program with default flags - try changing XdpFlags::default() to XdpFlags::SKB_MODE").unwrap();
-            self.xdp_link_ids.insert(interface_index, xdp_link_id);
-            let num_cpus = aya::util::nr_cpus().unwrap();
-
-            let mut ebpf_interface_rx_counters: aya::maps::PerCpuHashMap<
-                &mut MapData,
-                u32,
-                Counter,
-            > = aya::maps::PerCpuHashMap::try_from(bpf.map_mut("IF_RX_COUNT").unwrap()).unwrap();
-            if ebpf_interface_rx_counters.get(&interface.index, 0).is_err() {
-                ebpf_interface_rx_counters.insert(
-                    interface.index,
-                    PerCpuValues::try_from(vec![
-                        Counter {
-                            bytes: 0,
-                            packets: 0
-                        };
-                        num_cpus
-                    ])?,
-                    0,
-                )?;
-            }
+    /// The first and last tick shown on the time axis
+    fn window(&self) -> [f64; 2] {
+        self.time_window.bounds(self.tick_count)
+    }
 
-            Ok(())
-        } else {
-            Err(eyre!(
-                "Could not find an interface with index {} to attach eBPF program to",
-                interface_index
-            ))
+    /// The first and last tick of a time range plotted from the rollups
+    fn range_bounds(&self, time_range: TimeRange) -> Option<[f64; 2]> {
+        time_range.bounds(self.ticks_per_sec, self.tick_count)
+    }
+
+    /// Per-queue packet and byte totals for an interface over the current window, ordered by
+    /// queue index
+    fn window_rx_queue_counts(&self, interface_index: u32) -> Vec<(u32, u64, u64)> {
+        let window = self.window();
+        let mut rx_queue_counts: Vec<(u32, u64, u64)> = self
+            .tick_rx_queue_packet_count_data
+            .iter()
+            .filter(|((if_index, _), _)| *if_index == interface_index)
+            .map(|(rx_queue_id, packet_data)| {
+                let packet_count = packet_data.sum(window) as u64;
+                let byte_count = self
+                    .tick_rx_queue_byte_count_data
+                    .get(rx_queue_id)
+                    .map(|byte_data| byte_data.sum(window) as u64)
+                    .unwrap_or(0);
+                (rx_queue_id.1, packet_count, byte_count)
+            })
+            .collect();
+        rx_queue_counts.sort_by_key(|rx_queue_count| rx_queue_count.0);
+        rx_queue_counts
+    }
+
+    /// Collects the microburst peaks recorded during the read epoch which just ended, then starts
+    /// a new epoch so that the eBPF program resets the peaks
+    fn on_tick_bursts(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        let peak_scale = (self.tick_rate_ms * 1_000_000) as f64 / self.burst_bucket_ns as f64;
+
+        for entry in &mut self.interfaces {
+            // Each CPU only sees the frames steered to it, so a burst spread across CPUs shows up
+            // as smaller peaks on each of them. Peaks on different CPUs may also come from
+            // different buckets, so they cannot be added up, and the busiest CPU is plotted.
+            let mut max_cpu_peak_packet_count: u64 = 0;
+            let mut max_cpu_peak_byte_count: u64 = 0;
+            source.burst_counters(entry.interface.index, &mut |cpu_bursts| {
+                for cpu_burst in cpu_bursts {
+                    // Stale peaks belong to an earlier epoch with no traffic since
+                    if cpu_burst.epoch == self.burst_epoch {
+                        max_cpu_peak_packet_count =
+                            max_cpu_peak_packet_count.max(cpu_burst.peak_packets);
+                        max_cpu_peak_byte_count = max_cpu_peak_byte_count.max(cpu_burst.peak_bytes);
+                    }
+                }
+            })?;
+
+            entry.tick_peak_packet_counts.push(
+                self.tick_count,
+                max_cpu_peak_packet_count as f64 * peak_scale,
+            );
+            entry
+                .tick_peak_byte_counts
+                .push(self.tick_count, max_cpu_peak_byte_count as f64 * peak_scale);
         }
+
+        self.burst_epoch += 1;
+        source.set_burst_epoch(self.burst_epoch)?;
+
+        Ok(())
     }
 
-    fn detach_ebpf_program(&mut self, interface_index: u32, bpf: &mut aya::Ebpf) -> Result<()> {
-        let xdp_link_id = self.xdp_link_ids.remove(&interface_index);
-        let num_cpus = aya::util::nr_cpus().unwrap();
-        if let Some(xdp_link_id) = xdp_link_id {
-            let program: &mut Xdp = bpf.program_mut("xdp_tsndt").unwrap().try_into()?;
-            program.detach(xdp_link_id)
-            .context("failed to attach the XDP program with default flags - try changing XdpFlags::default() to XdpFlags::SKB_MODE").unwrap();
-            let mut ebpf_interface_rx_counters: aya::maps::PerCpuHashMap<
-                &mut MapData,
-                u32,
-                Counter,
-            > = aya::maps::PerCpuHashMap::try_from(bpf.map_mut("IF_RX_COUNT").unwrap()).unwrap();
-            if ebpf_interface_rx_counters.get(&interface_index, 0).is_err() {
-                ebpf_interface_rx_counters.insert(
-                    interface_index,
-                    PerCpuValues::try_from(vec![
-                        Counter {
-                            bytes: 0,
-                            packets: 0
-                        };
-                        num_cpus
-                    ])?,
-                    0,
-                )?;
+    /// Snapshots the cumulative inter-arrival histogram of each interface, summed across CPUs
+    fn on_tick_inter_arrival(&mut self, source: &dyn DataSource) -> Result<()> {
+        for entry in &mut self.interfaces {
+            let mut across_cpus_counts = [0; INTER_ARRIVAL_NUM_BUCKETS];
+            source.inter_arrival_histograms(entry.interface.index, &mut |cpu_histograms| {
+                for cpu_histogram in cpu_histograms {
+                    for (bucket, count) in across_cpus_counts.iter_mut().enumerate() {
+                        *count += cpu_histogram.buckets[bucket];
+                    }
+                }
+            })?;
+
+            let snapshots = &mut entry.inter_arrival_snapshots;
+            if snapshots.len() as f64 > self.time_window.history_size() {
+                snapshots.pop_front();
             }
-            self.tick_packet_count_data
-                .insert(interface_index, vec![(0.0, 0.0); 1]);
-            self.tick_byte_count_data
-                .insert(interface_index, vec![(0.0, 0.0); 1]);
-            Ok(())
-        } else {
-            Err(eyre!(
-                "Could not find an interface with index {} to detach eBPF program from",
-                interface_index
-            ))
+            snapshots.push_back((self.tick_count, across_cpus_counts));
         }
+
+        Ok(())
     }
 
-    fn on_tick(&mut self, bpf: &aya::Ebpf) -> Result<()> {
-        self.tick_count += 1.0;
+    /// Per-CPU packet and byte totals for an interface over the current window, ordered by CPU
+    fn window_cpu_counts(&self, entry: &InterfaceEntry) -> Vec<(usize, u64, u64)> {
+        CpuCounts::sums(&entry.cpus, self.window())
+    }
 
-        let ebpf_interface_rx_counters: aya::maps::PerCpuHashMap<&MapData, u32, Counter> =
-            aya::maps::PerCpuHashMap::try_from(bpf.map("IF_RX_COUNT").unwrap())?;
+    /// Inter-arrival histogram of the frames received on an interface during the current window
+    fn window_inter_arrival_counts(&self, entry: &InterfaceEntry) -> InterArrivalCounts {
+        let mut counts = [0; INTER_ARRIVAL_NUM_BUCKETS];
+        let [start, end] = self.window();
+        let snapshots = &entry.inter_arrival_snapshots;
+        let oldest = snapshots.iter().find(|snapshot| snapshot.0 >= start);
+        let latest = snapshots.iter().rev().find(|snapshot| snapshot.0 <= end);
+        if let (Some((_, oldest)), Some((_, latest))) = (oldest, latest) {
+            for (bucket, count) in counts.iter_mut().enumerate() {
+                *count = latest[bucket].saturating_sub(oldest[bucket]);
+            }
+        }
+        counts
+    }
 
-        let num_cpus =
-            aya::util::nr_cpus().unwrap_or_else(|_| panic!("Could not get number of CPUs"));
+    fn on_tick(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        self.tick_count += 1.0;
+        let series_capacity = self.time_window.series_capacity();
 
-        for interface in &self.interfaces {
-            let result_val = ebpf_interface_rx_counters.get(&interface.index, 0)?;
-            let packet_counts_window = self
-                .tick_packet_count_data
-                .get_mut(&interface.index)
-                .unwrap();
-            let byte_counts_window = self.tick_byte_count_data.get_mut(&interface.index).unwrap();
-            let prev_packet_count_val = self.cumul_packet_counts.get(&interface.index).unwrap();
-            let prev_byte_count_val = self.cumul_byte_counts.get(&interface.index).unwrap();
-
-            if packet_counts_window.len() as f64 > self.window_size {
-                packet_counts_window.remove(0);
+        for entry in &mut self.interfaces {
+            let if_index = entry.interface.index;
+            if let Some(backend) = source.collection_backend(if_index) {
+                entry.backend = Some(backend);
             }
+            source.interface_counters(if_index, &mut |values| {
+                entry.on_tick(self.tick_count, values, series_capacity)
+            })?;
+        }
 
-            if byte_counts_window.len() as f64 > self.window_size {
-                byte_counts_window.remove(0);
-            }
+        self.on_tick_bursts(source)?;
+        self.on_tick_inter_arrival(source)?;
 
-            // Sum up the value across all CPUs
-            let mut across_cpus_packet_count: u32 = 0;
+        source.rx_queue_counters(&mut |rx_queue_key, values| {
+            let rx_queue_id = (rx_queue_key.if_index, rx_queue_key.rx_queue);
+
+            let packet_counts_window = self
+                .tick_rx_queue_packet_count_data
+                .entry(rx_queue_id)
+                .or_insert_with(|| Series::new(series_capacity));
+            let byte_counts_window = self
+                .tick_rx_queue_byte_count_data
+                .entry(rx_queue_id)
+                .or_insert_with(|| Series::new(series_capacity));
+            let prev_packet_count_val = *self
+                .cumul_rx_queue_packet_counts
+                .get(&rx_queue_id)
+                .unwrap_or(&0);
+            let prev_byte_count_val = *self
+                .cumul_rx_queue_byte_counts
+                .get(&rx_queue_id)
+                .unwrap_or(&0);
+
+            // Sum up the value across all CPUs. A queue whose counters went backwards, after an
+            // eviction or a change of backend, counts nothing for this tick.
+            let mut across_cpus_packet_count: u64 = 0;
             let mut across_cpus_byte_count: u64 = 0;
-            for cpu_id in 0..num_cpus {
-                if let Some(cpu_counter) = result_val.get(cpu_id) {
-                    across_cpus_packet_count += cpu_counter.packets;
-                    across_cpus_byte_count += cpu_counter.bytes;
-                }
+            for cpu_counter in values {
+                across_cpus_packet_count += cpu_counter.packets as u64;
+                across_cpus_byte_count += cpu_counter.bytes;
             }
 
-            packet_counts_window.push((
+            packet_counts_window.push(
                 self.tick_count,
-                (across_cpus_packet_count - prev_packet_count_val) as f64,
-            ));
-            self.cumul_packet_counts
-                .insert(interface.index, across_cpus_packet_count);
+                across_cpus_packet_count.saturating_sub(prev_packet_count_val) as f64,
+            );
+            self.cumul_rx_queue_packet_counts
+                .insert(rx_queue_id, across_cpus_packet_count);
 
-            byte_counts_window.push((
+            byte_counts_window.push(
                 self.tick_count,
-                (across_cpus_byte_count - prev_byte_count_val) as f64,
-            ));
-            self.cumul_byte_counts
-                .insert(interface.index, across_cpus_byte_count);
-        }
-
-        if self.tick_count > self.window_size {
-            self.window[0] += 1.0;
-            self.window[1] += 1.0;
-        }
+                across_cpus_byte_count.saturating_sub(prev_byte_count_val) as f64,
+            );
+            self.cumul_rx_queue_byte_counts
+                .insert(rx_queue_id, across_cpus_byte_count);
+        })?;
 
         Ok(())
     }
@@ -486,235 +729,343 @@ impl NetworkInterfaceView {
         .areas(byte_counts);
 
         self.render_list(frame, iface_list, model);
-        self.render_packet_time_series(frame, packet_time_series, model);
-        self.render_packet_cumul_histogram(frame, packet_cumul_histogram, model);
-        self.render_byte_time_series(frame, byte_time_series, model);
-        self.render_byte_cumul_histogram(frame, byte_cumul_histogram, model);
+        match self.plots_mode {
+            PlotsMode::Counters => {
+                self.render_time_series(frame, packet_time_series, model, ZoomContext::Packet);
+                self.render_packet_cumul_histogram(frame, packet_cumul_histogram, model);
+                self.render_time_series(frame, byte_time_series, model, ZoomContext::Byte);
+                self.render_byte_cumul_histogram(frame, byte_cumul_histogram, model);
+            }
+            PlotsMode::RxQueues => {
+                self.render_rx_queue_histograms(frame, packet_counts, byte_counts, model);
+            }
+            PlotsMode::PerCpu => {
+                self.render_per_cpu_histograms(frame, packet_counts, byte_counts, model);
+            }
+            PlotsMode::InterArrival => {
+                self.render_inter_arrival_histogram(frame, plots, model);
+            }
+        }
     }
 
-    fn render_packet_time_series(
+    fn render_inter_arrival_histogram(
         &mut self,
         frame: &mut Frame,
         area: Rect,
         model: &NetworkInterfaceModel,
     ) {
-        let x_labels = vec![
-            Span::styled(
-                format!("{}", model.window[0]),
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
-            Span::raw(format!("{}", (model.window[0] + model.window[1]) / 2.0)),
-            Span::styled(
-                format!("{}", model.window[1]),
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
-        ];
-
-        // Initialize max_val to 1.0 to avoid a quirk in the time series plot with autoscaling.
-        // If all values are 0 in the plot, and autoscaling starts at 0, then no points get plotted.
-        let mut max_val = 1.0f64;
-        let mut datasets = Vec::with_capacity(model.interfaces.len());
-        let mut color_index = 1u8;
-        for interface in &model.interfaces {
-            let collecting = model.collecting.get(&interface.index);
-            if let Some(collecting) = collecting {
-                if *collecting {
-                    let data = model.tick_packet_count_data.get(&interface.index).unwrap();
-                    let iface_max_val = data.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap().1;
-                    max_val = if max_val.total_cmp(&iface_max_val).is_ge() {
-                        max_val
-                    } else {
-                        iface_max_val
-                    };
-                    let dataset = Dataset::default()
-                        .name(interface.name.clone())
-                        .marker(symbols::Marker::Dot)
-                        .style(Style::default().fg(Color::Indexed(color_index)))
-                        .data(data);
-                    color_index += 1;
-                    datasets.push(dataset);
+        let selected = self.interfaces_state.selected().unwrap_or(0);
+        let entry = model.interfaces.get(selected);
+        let interface = entry.map(|entry| &entry.interface);
+        let counts = entry
+            .map(|entry| model.window_inter_arrival_counts(entry))
+            .unwrap_or([0; INTER_ARRIVAL_NUM_BUCKETS]);
+
+        // Trim the empty buckets at either end, but keep the empty ones in between so that the
+        // shape of the distribution (e.g. bimodal pacing) is preserved
+        let first_bucket = counts.iter().position(|count| *count > 0).unwrap_or(0);
+        let last_bucket = counts.iter().rposition(|count| *count > 0).unwrap_or(0);
+
+        // Each bar is labelled with the upper bound of its bucket
+        let labels: Vec<String> = (first_bucket..=last_bucket)
+            .map(|bucket| {
+                if bucket == INTER_ARRIVAL_NUM_BUCKETS - 1 {
+                    format!(">{}", format_duration_ns(1 << (bucket - 1)))
+                } else {
+                    format!("<{}", format_duration_ns(1 << bucket))
                 }
-            } else {
-                tracing::warn!(
-                    "Could not find if interface with index {} was enabled or not",
-                    interface.index
-                );
-            }
-        }
+            })
+            .collect();
+        let data: Vec<(&str, u64)> = labels
+            .iter()
+            .zip(&counts[first_bucket..=last_bucket])
+            .map(|(label, count)| (label.as_str(), *count))
+            .collect();
 
-        if self.autoscaling[&ZoomContext::Packet] {
-            let upper_bound = get_autoscale_axis_bound(max_val);
-            self.packet_count_y_bounds[1] = upper_bound;
+        // Each CPU times the gaps between the frames it handles, which are then added up for the
+        // interface, so with RSS these are longer than the gaps on the wire
+        let title = match interface {
+            Some(interface) => format!(
+                "Packet inter-arrival times for window on {} (gaps measured per CPU)",
+                self.aliases.interface_or_name(&interface.name)
+            ),
+            None => String::from("Packet inter-arrival times for window (gaps measured per CPU)"),
         };
 
-        let y_labels = [
-            "0".into(),
-            (self.packet_count_y_bounds[1] / 2.0).to_string().bold(),
-            self.packet_count_y_bounds[1].to_string().bold(),
-        ];
-
-        let border_style = match self.zoom_context {
-            ZoomContext::Packet => Style::default().fg(ZOOM_CONTEXT_COLOR),
-            ZoomContext::Byte => Style::default(),
-        };
+        let bar_chart = BarChart::default()
+            .block(Block::bordered().title(title))
+            .bar_width(7)
+            .data(&data);
 
-        let y_axis_title = if self.autoscaling[&ZoomContext::Packet] {
-            "Packets (autoscaled)"
-        } else {
-            "Packets (manual zoom)"
-        };
+        frame.render_widget(bar_chart, area);
+    }
 
-        let chart = Chart::new(datasets)
-            .block(
-                Block::bordered()
-                    .border_style(border_style)
-                    .title(format!("Packet count per {TICK_RATE_MS} ms")),
-            )
-            .x_axis(
-                Axis::default()
-                    .title("Time")
-                    .style(Style::default().fg(DISABLED_COLOR))
-                    .labels(x_labels)
-                    .bounds(model.window),
-            )
-            .y_axis(
-                Axis::default()
-                    .title(y_axis_title)
-                    .style(Style::default().fg(DISABLED_COLOR))
-                    .labels(y_labels)
-                    .bounds(self.packet_count_y_bounds),
-            )
-            .hidden_legend_constraints((Constraint::Min(0), Constraint::Min(0)))
-            .legend_position(Some(LegendPosition::TopLeft));
+    fn render_rx_queue_histograms(
+        &mut self,
+        frame: &mut Frame,
+        packet_area: Rect,
+        byte_area: Rect,
+        model: &NetworkInterfaceModel,
+    ) {
+        let selected = self.interfaces_state.selected().unwrap_or(0);
+        let interface = model.interface(selected);
+        let rx_queue_counts = interface
+            .map(|interface| model.window_rx_queue_counts(interface.index))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(rx_queue, packet_count, byte_count)| {
+                (format!("q{rx_queue}"), packet_count, byte_count)
+            })
+            .collect();
 
-        frame.render_widget(chart, area);
+        self.render_share_histograms(
+            frame,
+            packet_area,
+            byte_area,
+            interface,
+            "RX queue",
+            rx_queue_counts,
+        );
     }
 
-    fn render_packet_cumul_histogram(
+    fn render_per_cpu_histograms(
         &mut self,
         frame: &mut Frame,
-        area: Rect,
+        packet_area: Rect,
+        byte_area: Rect,
         model: &NetworkInterfaceModel,
     ) {
-        let mut target_interfaces: Vec<&NetworkInterface> =
-            Vec::with_capacity(model.interfaces.len());
-        for interface in &model.interfaces {
-            let collecting = model.collecting.get(&interface.index);
-            if let Some(collecting) = collecting {
-                if *collecting {
-                    target_interfaces.push(interface);
-                }
-            }
-        }
+        let selected = self.interfaces_state.selected().unwrap_or(0);
+        let entry = model.interfaces.get(selected);
+        let interface = entry.map(|entry| &entry.interface);
+        let cpu_counts = entry
+            .map(|entry| model.window_cpu_counts(entry))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(cpu_id, packet_count, byte_count)| {
+                (format!("cpu{cpu_id}"), packet_count, byte_count)
+            })
+            .collect();
 
-        let mut data: Vec<(&str, u64)> = Vec::with_capacity(target_interfaces.len());
+        self.render_share_histograms(frame, packet_area, byte_area, interface, "CPU", cpu_counts);
+    }
 
-        for interface in target_interfaces {
-            let val = model.cumul_packet_counts.get(&interface.index).unwrap();
-            data.push((&interface.name, *val as u64));
-        }
+    /// Renders packet and byte bar charts showing how the traffic of an interface over the
+    /// current window is spread across some resource, such as RX queues or CPUs
+    fn render_share_histograms(
+        &mut self,
+        frame: &mut Frame,
+        packet_area: Rect,
+        byte_area: Rect,
+        interface: Option<&NetworkInterface>,
+        resource_name: &str,
+        counts: Vec<(String, u64, u64)>,
+    ) {
+        let interface_name = interface
+            .map(|interface| self.aliases.interface_or_name(&interface.name).to_string())
+            .unwrap_or_default();
 
-        data.sort_by_key(|datum| std::cmp::Reverse(datum.1));
+        let total_packet_count: u64 = counts.iter().map(|count| count.1).sum();
+        let total_byte_count: u64 = counts.iter().map(|count| count.2).sum();
 
-        let bar_chart = BarChart::default()
-            .block(Block::bordered().title("Cumulative packet count"))
+        // Label each bar with its share of the interface traffic so that an imbalance stands
+        // out even when the bars are too narrow to compare
+        let packet_labels: Vec<String> = counts
+            .iter()
+            .map(|(label, packet_count, _)| {
+                let share = 100.0 * *packet_count as f64 / total_packet_count.max(1) as f64;
+                format!("{label} {share:.0}%")
+            })
+            .collect();
+        let byte_labels: Vec<String> = counts
+            .iter()
+            .map(|(label, _, byte_count)| {
+                let share = 100.0 * *byte_count as f64 / total_byte_count.max(1) as f64;
+                format!("{label} {share:.0}%")
+            })
+            .collect();
+
+        let packet_data: Vec<(&str, u64)> = packet_labels
+            .iter()
+            .zip(&counts)
+            .map(|(label, count)| (label.as_str(), count.1))
+            .collect();
+        let byte_data: Vec<(&str, u64)> = byte_labels
+            .iter()
+            .zip(&counts)
+            .map(|(label, count)| (label.as_str(), count.2))
+            .collect();
+
+        let packet_bar_chart = BarChart::default()
+            .block(Block::bordered().title(format!(
+                "Packets per {resource_name} on {interface_name} over the window"
+            )))
             .bar_width(10)
-            .data(&data);
+            .data(&packet_data);
+        let byte_bar_chart = BarChart::default()
+            .block(Block::bordered().title(format!(
+                "Bytes per {resource_name} on {interface_name} over the window"
+            )))
+            .bar_width(10)
+            .data(&byte_data);
 
-        frame.render_widget(bar_chart, area);
+        frame.render_widget(packet_bar_chart, packet_area);
+        frame.render_widget(byte_bar_chart, byte_area);
+    }
+
+    /// Bounds, labels and title of the time axis of the time series plots, which follow either
+    /// the window or the selected time range
+    fn time_axis(
+        &self,
+        model: &NetworkInterfaceModel,
+    ) -> ([f64; 2], Vec<Span<'static>>, &'static str) {
+        let window = model
+            .range_bounds(self.time_range)
+            .unwrap_or(model.window());
+        let [start, middle, end] = self.time_range.axis_labels().unwrap_or([
+            format!("{}", window[0]),
+            format!("{}", (window[0] + window[1]) / 2.0),
+            format!("{}", window[1]),
+        ]);
+        let x_labels = vec![
+            Span::styled(start, Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(middle),
+            Span::styled(end, Style::default().add_modifier(Modifier::BOLD)),
+        ];
+        let x_title = self
+            .time_range
+            .axis_title()
+            .unwrap_or(model.time_window.axis_title());
+        (window, x_labels, x_title)
     }
 
-    fn render_byte_time_series(
+    fn time_series_title(&self, model: &NetworkInterfaceModel, unit_name: &str) -> String {
+        let tick_rate_ms = model.tick_rate_ms;
+        if self.showing_peaks {
+            format!(
+                "{unit_name} count per {tick_rate_ms} ms (busiest CPU peaks from {} µs buckets, \
+                 scaled)",
+                model.burst_bucket_ns / 1000
+            )
+        } else {
+            format!("{unit_name} count per {tick_rate_ms} ms")
+        }
+    }
+
+    /// Renders the packet or byte counts of each interface over time
+    fn render_time_series(
         &mut self,
         frame: &mut Frame,
         area: Rect,
         model: &NetworkInterfaceModel,
+        unit: ZoomContext,
     ) {
-        let x_labels = vec![
-            Span::styled(
-                format!("{}", model.window[0]),
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
-            Span::raw(format!("{}", (model.window[0] + model.window[1]) / 2.0)),
-            Span::styled(
-                format!("{}", model.window[1]),
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
-        ];
+        let (window, x_labels, x_title) = self.time_axis(model);
 
         // Initialize max_val to 1.0 to avoid a quirk in the time series plot with autoscaling.
         // If all values are 0 in the plot, and autoscaling starts at 0, then no points get plotted.
         let mut max_val = 1.0f64;
-        let mut datasets = Vec::with_capacity(model.interfaces.len());
+        // The points are gathered before the datasets, which borrow them. Only one of the two is
+        // filled, depending on whether the rollups are plotted.
+        let range_bounds = model.range_bounds(self.time_range);
+        let mut rollup_series = Vec::new();
+        let mut points = Vec::new();
+        for entry in model.collected_interfaces() {
+            let (tick_counts, tick_peak_counts, rollups) = entry.counts(&unit);
+            match range_bounds {
+                Some(bounds) => rollup_series.push((entry, rollups.series(bounds))),
+                None => points.push((
+                    entry,
+                    tick_counts.points(window),
+                    tick_peak_counts.points(window),
+                )),
+            }
+        }
+
+        let mut datasets = Vec::with_capacity(rollup_series.len() * 3 + points.len() * 2);
         let mut color_index = 1u8;
-        for interface in &model.interfaces {
-            let collecting = model.collecting.get(&interface.index);
-            if let Some(collecting) = collecting {
-                if *collecting {
-                    let data = model.tick_byte_count_data.get(&interface.index).unwrap();
-                    let iface_max_val = data.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap().1;
-                    max_val = if max_val.total_cmp(&iface_max_val).is_ge() {
-                        max_val
-                    } else {
-                        iface_max_val
-                    };
-                    let dataset = Dataset::default()
-                        .name(interface.name.clone())
-                        .marker(symbols::Marker::Dot)
-                        .style(Style::default().fg(Color::Indexed(color_index)))
-                        .data(data);
-                    color_index += 1;
-                    datasets.push(dataset);
-                }
+        for (entry, series) in &rollup_series {
+            max_val = max_val.max(series.max_value());
+            datasets.extend(series.datasets(
+                self.aliases.interface_or_name(&entry.interface.name),
+                Color::Indexed(color_index),
+            ));
+            color_index += 1;
+        }
+        for (entry, data, peak_data) in &points {
+            let iface_max_val = data.iter().map(|datum| datum.1).fold(0.0, f64::max);
+            max_val = if max_val.total_cmp(&iface_max_val).is_ge() {
+                max_val
             } else {
-                tracing::warn!(
-                    "Could not find if interface with index {} was enabled or not",
-                    interface.index
-                );
+                iface_max_val
+            };
+            let name = self.aliases.interface_or_name(&entry.interface.name);
+            let dataset = Dataset::default()
+                .name(name.to_string())
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::Indexed(color_index)))
+                .data(data);
+            datasets.push(dataset);
+            if self.showing_peaks {
+                let iface_max_peak_val = peak_data.iter().map(|datum| datum.1).fold(0.0, f64::max);
+                max_val = max_val.max(iface_max_peak_val);
+                let peak_dataset = Dataset::default()
+                    .name(format!("{name} CPU peak"))
+                    .marker(symbols::Marker::Braille)
+                    .style(Style::default().fg(Color::Indexed(color_index)))
+                    .data(peak_data);
+                datasets.push(peak_dataset);
             }
+            color_index += 1;
         }
 
-        if self.autoscaling[&ZoomContext::Byte] {
-            let upper_bound = get_autoscale_axis_bound(max_val);
-            self.byte_count_y_bounds[1] = upper_bound;
+        let autoscaling = self.autoscaling[&unit];
+        let (y_bounds, unit_name) = match unit {
+            ZoomContext::Packet => (&mut self.packet_count_y_bounds, "Packet"),
+            ZoomContext::Byte => (&mut self.byte_count_y_bounds, "Byte"),
+        };
+        if autoscaling {
+            y_bounds[1] = get_autoscale_axis_bound(max_val);
         };
+        let y_bounds = *y_bounds;
 
         let y_labels = [
             "0".into(),
-            (self.byte_count_y_bounds[1] / 2.0).to_string().bold(),
-            self.byte_count_y_bounds[1].to_string().bold(),
+            (y_bounds[1] / 2.0).to_string().bold(),
+            y_bounds[1].to_string().bold(),
         ];
 
-        let border_style = match self.zoom_context {
-            ZoomContext::Packet => Style::default(),
-            ZoomContext::Byte => Style::default().fg(ZOOM_CONTEXT_COLOR),
+        let border_style = if self.zoom_context == unit {
+            Style::default().fg(self.colors.zoom_context)
+        } else {
+            Style::default()
         };
 
-        let y_axis_title = if self.autoscaling[&ZoomContext::Byte] {
-            "Bytes (autoscaled)"
+        let y_axis_title = if autoscaling {
+            format!("{unit_name}s (autoscaled)")
         } else {
-            "Bytes (manual zoom)"
+            format!("{unit_name}s (manual zoom)")
         };
 
         let chart = Chart::new(datasets)
             .block(
                 Block::bordered()
                     .border_style(border_style)
-                    .title(format!("Byte count per {TICK_RATE_MS} ms")),
+                    .title(self.time_series_title(model, unit_name)),
             )
             .x_axis(
                 Axis::default()
-                    .title("Time")
-                    .style(Style::default().fg(DISABLED_COLOR))
+                    .title(x_title)
+                    .style(Style::default().fg(self.colors.disabled))
                     .labels(x_labels)
-                    .bounds(model.window),
+                    .bounds(window),
             )
             .y_axis(
                 Axis::default()
                     .title(y_axis_title)
-                    .style(Style::default().fg(DISABLED_COLOR))
+                    .style(Style::default().fg(self.colors.disabled))
                     .labels(y_labels)
-                    .bounds(self.byte_count_y_bounds),
+                    .bounds(y_bounds),
             )
             .hidden_legend_constraints((Constraint::Min(0), Constraint::Min(0)))
             .legend_position(Some(LegendPosition::TopLeft));
@@ -722,30 +1073,46 @@ impl NetworkInterfaceView {
         frame.render_widget(chart, area);
     }
 
-    fn render_byte_cumul_histogram(
+    fn render_packet_cumul_histogram(
         &mut self,
         frame: &mut Frame,
         area: Rect,
         model: &NetworkInterfaceModel,
     ) {
-        let mut target_interfaces: Vec<&NetworkInterface> =
-            Vec::with_capacity(model.interfaces.len());
-        for interface in &model.interfaces {
-            let collecting = model.collecting.get(&interface.index);
-            if let Some(collecting) = collecting {
-                if *collecting {
-                    target_interfaces.push(interface);
-                }
-            }
-        }
+        let mut data: Vec<(&str, u64)> = model
+            .collected_interfaces()
+            .map(|entry| {
+                (
+                    self.aliases.interface_or_name(&entry.interface.name),
+                    entry.cumul_packet_count as u64,
+                )
+            })
+            .collect();
+        data.sort_by_key(|datum| std::cmp::Reverse(datum.1));
 
-        let mut data: Vec<(&str, u64)> = Vec::with_capacity(target_interfaces.len());
+        let bar_chart = BarChart::default()
+            .block(Block::bordered().title("Cumulative packet count"))
+            .bar_width(10)
+            .data(&data);
 
-        for interface in target_interfaces {
-            let val = model.cumul_byte_counts.get(&interface.index).unwrap();
-            data.push((&interface.name, *val));
-        }
+        frame.render_widget(bar_chart, area);
+    }
 
+    fn render_byte_cumul_histogram(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+        model: &NetworkInterfaceModel,
+    ) {
+        let mut data: Vec<(&str, u64)> = model
+            .collected_interfaces()
+            .map(|entry| {
+                (
+                    self.aliases.interface_or_name(&entry.interface.name),
+                    entry.cumul_byte_count,
+                )
+            })
+            .collect();
         data.sort_by_key(|datum| std::cmp::Reverse(datum.1));
 
         let bar_chart = BarChart::default()
@@ -760,20 +1127,23 @@ impl NetworkInterfaceView {
         let ifaces: Vec<ListItem> = model
             .interfaces
             .iter()
-            .map(|iface| {
-                let collecting = model.collecting.get(&iface.index);
-                let color = if let Some(collecting) = collecting {
-                    if *collecting {
-                        Color::default()
-                    } else {
-                        DISABLED_COLOR
-                    }
+            .map(|entry| {
+                let iface = &entry.interface;
+                let color = if entry.collecting {
+                    Color::default()
                 } else {
-                    DISABLED_COLOR
+                    self.colors.disabled
                 };
 
-                let li = ListItem::new(format!("{}: {}", iface.index, iface.name.clone()))
-                    .style(Style::default().fg(color));
+                let name = match self.aliases.interface(&iface.name) {
+                    Some(alias) => format!("{} ({})", alias, iface.name),
+                    None => iface.name.clone(),
+                };
+                let label = match &entry.backend {
+                    Some(backend) => format!("{}: {} [{}]", iface.index, name, backend),
+                    None => format!("{}: {}", iface.index, name),
+                };
+                let li = ListItem::new(label).style(Style::default().fg(color));
                 li
             })
             .collect();
@@ -789,3 +1159,82 @@ impl NetworkInterfaceView {
         frame.render_stateful_widget(list, list_area, &mut self.interfaces_state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tsndt_common::Counter;
+
+    use super::*;
+    use crate::source::memory::MemorySource;
+
+    fn receive(source: &mut MemorySource, if_index: u32, rx_queue: u32, packet_len: u32) {
+        source
+            .engine
+            .count_frame(if_index, rx_queue, 0, &[0; 64], packet_len);
+    }
+
+    /// Packets and bytes received on each interface during the latest tick
+    fn latest_counts(context: &NetworkInterfaceContext) -> Vec<(u32, f64, f64)> {
+        context
+            .model
+            .latest_tick_counts()
+            .into_iter()
+            .map(|(interface, packet_count, byte_count)| {
+                (interface.index, packet_count, byte_count)
+            })
+            .collect()
+    }
+
+    fn latest_rx_queue_count(context: &NetworkInterfaceContext, rx_queue_id: (u32, u32)) -> f64 {
+        context.model.tick_rx_queue_packet_count_data[&rx_queue_id]
+            .latest()
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn counts_frames_received_since_the_previous_tick() {
+        let mut source = MemorySource::with_test_interfaces(2);
+        let mut context = NetworkInterfaceContext::new(&source, &Settings::from_args(&[]));
+
+        receive(&mut source, 1, 0, 100);
+        receive(&mut source, 1, 1, 200);
+        receive(&mut source, 2, 0, 60);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(latest_counts(&context), [(1, 2.0, 300.0), (2, 1.0, 60.0)]);
+        assert_eq!(latest_rx_queue_count(&context, (1, 0)), 1.0);
+        assert_eq!(latest_rx_queue_count(&context, (1, 1)), 1.0);
+
+        receive(&mut source, 2, 0, 1500);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(latest_counts(&context), [(1, 0.0, 0.0), (2, 1.0, 1500.0)]);
+        assert_eq!(latest_rx_queue_count(&context, (1, 0)), 0.0);
+    }
+
+    #[test]
+    fn counts_nothing_when_rx_queue_counters_go_backwards() {
+        let mut source = MemorySource::with_test_interfaces(1);
+        let mut context = NetworkInterfaceContext::new(&source, &Settings::from_args(&[]));
+
+        for _ in 0..3 {
+            receive(&mut source, 1, 0, 100);
+        }
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(latest_rx_queue_count(&context, (1, 0)), 3.0);
+
+        // As after the entry was evicted and counted again from 0
+        source.engine.rx_queue_counters.insert(
+            (1, 0),
+            Counter {
+                bytes: 100,
+                packets: 1,
+            },
+        );
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(latest_rx_queue_count(&context, (1, 0)), 0.0);
+
+        receive(&mut source, 1, 0, 100);
+        context.handle_tick(&mut source).unwrap();
+        assert_eq!(latest_rx_queue_count(&context, (1, 0)), 1.0);
+    }
+}
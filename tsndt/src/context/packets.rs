@@ -0,0 +1,442 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{Ipv4Addr, Ipv6Addr},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::Result;
+use network_interface::NetworkInterface;
+use ratatui::{
+    layout::Rect,
+    style::{Style, Stylize},
+    widgets::{Block, List, ListDirection, ListItem, ListState},
+    Frame,
+};
+
+use super::{ethernet::get_mac_string, TsndtContext};
+use crate::{
+    keymap::{Action, Keymap},
+    pcapng::PcapngWriter,
+    source::{ebpf::clock_now_ns, DataSource},
+};
+
+const CONTEXT_NAME: &str = "Packets";
+const MAX_NUM_SUMMARIES: usize = 1000;
+
+const ETH_HDR_LEN: usize = 14;
+const VLAN_HDR_LEN: usize = 4;
+const ETH_P_IPV4: u16 = 0x0800;
+const ETH_P_ARP: u16 = 0x0806;
+const ETH_P_8021Q: u16 = 0x8100;
+const ETH_P_8021AD: u16 = 0x88a8;
+const ETH_P_IPV6: u16 = 0x86dd;
+const ETH_P_LLDP: u16 = 0x88cc;
+const ETH_P_PTP: u16 = 0x88f7;
+const ETH_P_AVTP: u16 = 0x22f0;
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_IGMP: u8 = 2;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMPV6: u8 = 58;
+const IPV6_HDR_LEN: usize = 40;
+
+pub(crate) struct PacketsContext {
+    pub(crate) model: PacketsModel,
+    pub(crate) view: PacketsView,
+}
+
+pub(crate) struct PacketsView {
+    summaries_state: ListState,
+    // When set, the selection tracks the most recent sample
+    following: bool,
+    sample_rate: u32,
+}
+
+pub(crate) struct PacketsModel {
+    interfaces: Vec<NetworkInterface>,
+    interface_names: HashMap<u32, String>,
+    pcapng_writer: Option<PcapngWriter>,
+    summaries: VecDeque<String>,
+    num_samples: u64,
+}
+
+/// Formats a wall clock timestamp as local time of day with microseconds, like tcpdump does
+fn get_time_of_day_string(timestamp_ns: u64) -> String {
+    let secs = (timestamp_ns / 1_000_000_000) as libc::time_t;
+    let micros = (timestamp_ns % 1_000_000_000) / 1000;
+    let mut local_time: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut local_time) };
+    format!(
+        "{:02}:{:02}:{:02}.{micros:06}",
+        local_time.tm_hour, local_time.tm_min, local_time.tm_sec
+    )
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn get_tcp_flags_string(flags: u8) -> String {
+    let flag_chars = [
+        (0x02, 'S'),
+        (0x01, 'F'),
+        (0x04, 'R'),
+        (0x08, 'P'),
+        (0x20, 'U'),
+        (0x10, '.'),
+    ];
+    flag_chars
+        .iter()
+        .filter(|(mask, _)| flags & mask != 0)
+        .map(|(_, flag_char)| *flag_char)
+        .collect()
+}
+
+/// Summarizes the transport layer of a packet from its source and destination address strings
+fn summarize_transport(protocol: u8, src: &str, dst: &str, data: &[u8]) -> String {
+    match protocol {
+        IPPROTO_TCP => match (read_u16(data, 0), read_u16(data, 2), data.get(13)) {
+            (Some(src_port), Some(dst_port), Some(flags)) => {
+                let mut summary = format!(
+                    "{src}.{src_port} > {dst}.{dst_port}: TCP [{}]",
+                    get_tcp_flags_string(*flags)
+                );
+                if let Some(seq) = read_u32(data, 4) {
+                    summary.push_str(&format!(" seq {seq}"));
+                }
+                summary
+            }
+            _ => format!("{src} > {dst}: TCP (truncated)"),
+        },
+        IPPROTO_UDP => match (read_u16(data, 0), read_u16(data, 2)) {
+            (Some(src_port), Some(dst_port)) => {
+                format!("{src}.{src_port} > {dst}.{dst_port}: UDP")
+            }
+            _ => format!("{src} > {dst}: UDP (truncated)"),
+        },
+        IPPROTO_ICMP | IPPROTO_ICMPV6 => {
+            let name = if protocol == IPPROTO_ICMP {
+                "ICMP"
+            } else {
+                "ICMP6"
+            };
+            match (data.first(), data.get(1)) {
+                (Some(icmp_type), Some(icmp_code)) => {
+                    format!("{src} > {dst}: {name} type {icmp_type} code {icmp_code}")
+                }
+                _ => format!("{src} > {dst}: {name} (truncated)"),
+            }
+        }
+        IPPROTO_IGMP => format!("{src} > {dst}: IGMP"),
+        _ => format!("{src} > {dst}: ip-proto-{protocol}"),
+    }
+}
+
+fn summarize_ipv4(data: &[u8]) -> String {
+    if data.len() < 20 {
+        return String::from("IPv4 (truncated)");
+    }
+    let header_len = (data[0] & 0x0f) as usize * 4;
+    let ttl = data[8];
+    let protocol = data[9];
+    let src = Ipv4Addr::new(data[12], data[13], data[14], data[15]).to_string();
+    let dst = Ipv4Addr::new(data[16], data[17], data[18], data[19]).to_string();
+
+    // Only the first fragment carries the transport header
+    let fragment_offset = read_u16(data, 6).unwrap_or(0) & 0x1fff;
+    let summary = if fragment_offset != 0 {
+        format!("{src} > {dst}: ip-proto-{protocol} fragment offset {fragment_offset}")
+    } else {
+        summarize_transport(protocol, &src, &dst, data.get(header_len..).unwrap_or(&[]))
+    };
+    format!("IPv4 {summary}, ttl {ttl}")
+}
+
+fn summarize_ipv6(data: &[u8]) -> String {
+    if data.len() < IPV6_HDR_LEN {
+        return String::from("IPv6 (truncated)");
+    }
+    let next_header = data[6];
+    let hop_limit = data[7];
+    let mut src = [0; 16];
+    src.copy_from_slice(&data[8..24]);
+    let mut dst = [0; 16];
+    dst.copy_from_slice(&data[24..40]);
+
+    let summary = summarize_transport(
+        next_header,
+        &Ipv6Addr::from(src).to_string(),
+        &Ipv6Addr::from(dst).to_string(),
+        &data[IPV6_HDR_LEN..],
+    );
+    format!("IPv6 {summary}, hlim {hop_limit}")
+}
+
+fn summarize_arp(data: &[u8]) -> String {
+    // Only Ethernet/IPv4 ARP is summarized in detail
+    if data.len() < 28 || data[4] != 6 || data[5] != 4 {
+        return String::from("ARP");
+    }
+    let sender_ip = Ipv4Addr::new(data[14], data[15], data[16], data[17]);
+    let target_ip = Ipv4Addr::new(data[24], data[25], data[26], data[27]);
+    match read_u16(data, 6) {
+        Some(1) => format!("ARP, Request who-has {target_ip} tell {sender_ip}"),
+        Some(2) => format!(
+            "ARP, Reply {sender_ip} is-at {}",
+            get_mac_string(&data[8..14])
+        ),
+        Some(op) => format!("ARP, op {op}"),
+        None => String::from("ARP"),
+    }
+}
+
+/// Builds a tcpdump-style one-line summary of the captured start of a frame
+fn summarize_frame(data: &[u8]) -> String {
+    if data.len() < ETH_HDR_LEN {
+        return String::from("Ethernet (truncated)");
+    }
+    let dst_mac = get_mac_string(&data[0..6]);
+    let src_mac = get_mac_string(&data[6..12]);
+    let mut ether_type = read_u16(data, 12).unwrap_or(0);
+    let mut offset = ETH_HDR_LEN;
+
+    let mut vlan = String::new();
+    if ether_type == ETH_P_8021Q || ether_type == ETH_P_8021AD {
+        match (read_u16(data, offset), read_u16(data, offset + 2)) {
+            (Some(tci), Some(inner_ether_type)) => {
+                vlan = format!("vlan {} p {}, ", tci & 0x0fff, tci >> 13);
+                ether_type = inner_ether_type;
+                offset += VLAN_HDR_LEN;
+            }
+            _ => return format!("{src_mac} > {dst_mac}, 802.1Q (truncated)"),
+        }
+    }
+
+    let payload = &data[offset..];
+    let network_summary = match ether_type {
+        ETH_P_IPV4 => summarize_ipv4(payload),
+        ETH_P_IPV6 => summarize_ipv6(payload),
+        ETH_P_ARP => summarize_arp(payload),
+        ETH_P_LLDP => String::from("LLDP"),
+        ETH_P_PTP => match payload.first() {
+            Some(message_type) => format!("PTPv2 message type {}", message_type & 0x0f),
+            None => String::from("PTPv2"),
+        },
+        ETH_P_AVTP => match payload.first() {
+            Some(subtype) => format!("AVTP subtype 0x{subtype:02x}"),
+            None => String::from("AVTP"),
+        },
+        _ => format!("ethertype 0x{ether_type:04x}"),
+    };
+    format!("{src_mac} > {dst_mac}, {vlan}{network_summary}")
+}
+
+impl TsndtContext for PacketsContext {
+    fn get_context_name(&self) -> String {
+        String::from(CONTEXT_NAME)
+    }
+
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String> {
+        vec![keymap.help(&[
+            (
+                &[Action::SelectPrevious, Action::SelectNext],
+                "Scroll packets",
+            ),
+            (&[Action::ToggleFollow], "Follow new packets"),
+            (&[Action::ClearPackets], "Clear packets"),
+            (&[Action::TogglePcapng], "Start/stop writing pcapng"),
+        ])]
+    }
+
+    fn handle_tick(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        self.model.on_tick(source)?;
+        if self.view.following && !self.model.summaries.is_empty() {
+            self.view
+                .summaries_state
+                .select(Some(self.model.summaries.len() - 1));
+        }
+        Ok(())
+    }
+
+    fn handle_action(&mut self, action: Action, _source: &mut dyn DataSource) -> Result<()> {
+        match action {
+            Action::SelectPrevious => {
+                self.view.following = false;
+                let selected = self.view.summaries_state.selected().unwrap_or(0);
+                let candidate = if selected > 0 { selected - 1 } else { 0 };
+                self.view.summaries_state.select(Some(candidate));
+            }
+            Action::SelectNext => {
+                let selected = self.view.summaries_state.selected().unwrap_or(0);
+                let candidate = selected + 1;
+                if candidate < self.model.summaries.len() {
+                    self.view.summaries_state.select(Some(candidate));
+                }
+            }
+            Action::ToggleFollow => {
+                self.view.following = !self.view.following;
+            }
+            Action::ClearPackets => {
+                self.model.summaries.clear();
+                self.view.summaries_state.select(None);
+            }
+            Action::TogglePcapng => {
+                if let Err(report) = self.model.toggle_pcapng_writer() {
+                    tracing::warn!("Failed to toggle pcapng writing: {}", report);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame, context_area: Rect) {
+        self.view.draw(frame, &self.model, context_area);
+    }
+}
+
+impl PacketsContext {
+    pub(crate) fn new(
+        interfaces: Vec<NetworkInterface>,
+        pcapng_path: Option<&Path>,
+        sample_rate: u32,
+    ) -> Result<Self> {
+        // Samples are keyed by interface index, so keep the names around for display
+        let interface_names = interfaces
+            .iter()
+            .map(|interface| (interface.index, interface.name.clone()))
+            .collect();
+
+        let pcapng_writer = match pcapng_path {
+            Some(path) => Some(PcapngWriter::create(path, &interfaces)?),
+            None => None,
+        };
+
+        Ok(Self {
+            model: PacketsModel {
+                interfaces,
+                interface_names,
+                pcapng_writer,
+                summaries: VecDeque::new(),
+                num_samples: 0,
+            },
+            view: PacketsView {
+                summaries_state: ListState::default(),
+                following: true,
+                sample_rate,
+            },
+        })
+    }
+}
+
+impl PacketsModel {
+    /// Starts writing samples to a new pcapng file in the working directory, or finishes the
+    /// file currently being written
+    fn toggle_pcapng_writer(&mut self) -> Result<()> {
+        match self.pcapng_writer.take() {
+            Some(pcapng_writer) => pcapng_writer.finish(),
+            None => {
+                let path = PathBuf::from(format!(
+                    "{}-{}.pcapng",
+                    env!("CARGO_PKG_NAME"),
+                    clock_now_ns(libc::CLOCK_REALTIME) / 1_000_000_000
+                ));
+                self.pcapng_writer = Some(PcapngWriter::create(&path, &self.interfaces)?);
+                Ok(())
+            }
+        }
+    }
+
+    fn on_tick(&mut self, source: &mut dyn DataSource) -> Result<()> {
+        // Sample timestamps come from the data source clock, so convert them to wall clock time
+        let realtime_offset_ns = source.realtime_offset_ns();
+
+        source.packet_samples(&mut |sample| {
+            self.num_samples += 1;
+
+            let captured_len = (sample.captured_len as usize).min(sample.data.len());
+            let timestamp_ns = sample.timestamp_ns + realtime_offset_ns;
+
+            if let Some(pcapng_writer) = self.pcapng_writer.as_mut() {
+                let res = pcapng_writer.write_packet(
+                    sample.if_index,
+                    timestamp_ns,
+                    &sample.data[..captured_len],
+                    sample.packet_len,
+                );
+                if let Err(report) = res {
+                    tracing::warn!(
+                        "Stopped writing {}: {}",
+                        pcapng_writer.path().display(),
+                        report
+                    );
+                    self.pcapng_writer = None;
+                }
+            }
+
+            let interface_name = self
+                .interface_names
+                .get(&sample.if_index)
+                .cloned()
+                .unwrap_or_else(|| sample.if_index.to_string());
+            let summary = format!(
+                "{} {interface_name} {}, length {}",
+                get_time_of_day_string(timestamp_ns),
+                summarize_frame(&sample.data[..captured_len]),
+                sample.packet_len
+            );
+
+            if self.summaries.len() >= MAX_NUM_SUMMARIES {
+                self.summaries.pop_front();
+            }
+            self.summaries.push_back(summary);
+        });
+
+        Ok(())
+    }
+}
+
+impl PacketsView {
+    fn draw(&mut self, frame: &mut Frame, model: &PacketsModel, context_area: Rect) {
+        let summaries: Vec<ListItem> = model
+            .summaries
+            .iter()
+            .map(|summary| ListItem::new(summary.as_str()))
+            .collect();
+
+        let sampling = if self.sample_rate == 0 {
+            String::from("sampling disabled, see --sample-rate")
+        } else {
+            format!("1 in {} frames sampled", self.sample_rate)
+        };
+        let following = if self.following { ", following" } else { "" };
+        let writing = match &model.pcapng_writer {
+            Some(pcapng_writer) => format!(
+                ", writing {} ({} written)",
+                pcapng_writer.path().display(),
+                pcapng_writer.num_packets()
+            ),
+            None => String::new(),
+        };
+        let title = format!(
+            "Sampled packets ({sampling}, {} total{following}{writing})",
+            model.num_samples
+        );
+
+        let list = List::new(summaries)
+            .block(Block::bordered().title(title))
+            .style(Style::new().white())
+            .highlight_style(Style::new().italic())
+            .highlight_symbol(">")
+            .direction(ListDirection::TopToBottom);
+
+        frame.render_stateful_widget(list, context_area, &mut self.summaries_state);
+    }
+}
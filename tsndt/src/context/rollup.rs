@@ -0,0 +1,215 @@
+// Rollups of a per-tick time series into 1 second, 1 minute and 10 minute buckets, each holding
+// the minimum, maximum and mean of the ticks which fell into it. Each resolution keeps a fixed
+// number of buckets, so that hours of a series can be plotted in bounded memory.
+
+use std::collections::VecDeque;
+
+use ratatui::{
+    style::{Color, Style},
+    symbols,
+    widgets::Dataset,
+};
+
+/// Duration and number of buckets kept of each resolution, finest first
+const LEVELS: [(u64, usize); 3] = [(1, 600), (60, 60), (600, 36)];
+
+/// How far back the time series plots reach
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub(crate) enum TimeRange {
+    /// The window of ticks at full resolution, which can be paused and scrolled
+    Window,
+    TenMinutes,
+    SixHours,
+}
+
+impl TimeRange {
+    pub(crate) fn next(self) -> Self {
+        match self {
+            TimeRange::Window => TimeRange::TenMinutes,
+            TimeRange::TenMinutes => TimeRange::SixHours,
+            TimeRange::SixHours => TimeRange::Window,
+        }
+    }
+
+    /// Duration of the range in seconds, if it is plotted from the rollups
+    fn duration_sec(self) -> Option<u64> {
+        match self {
+            TimeRange::Window => None,
+            TimeRange::TenMinutes => Some(600),
+            TimeRange::SixHours => Some(6 * 3600),
+        }
+    }
+
+    /// The first and last tick of the range, if it is plotted from the rollups
+    pub(crate) fn bounds(self, ticks_per_sec: f64, tick_count: f64) -> Option<[f64; 2]> {
+        let duration_ticks = self.duration_sec()? as f64 * ticks_per_sec;
+        let end = tick_count.max(duration_ticks);
+        Some([end - duration_ticks, end])
+    }
+
+    /// Labels of the start, middle and end of the time axis, relative to now
+    pub(crate) fn axis_labels(self) -> Option<[String; 3]> {
+        let duration_sec = self.duration_sec()?;
+        Some([
+            format!("-{}", format_duration_sec(duration_sec)),
+            format!("-{}", format_duration_sec(duration_sec / 2)),
+            String::from("now"),
+        ])
+    }
+
+    /// Title of the time axis, naming the range and the finest rollups plotted
+    pub(crate) fn axis_title(self) -> Option<&'static str> {
+        match self {
+            TimeRange::Window => None,
+            TimeRange::TenMinutes => Some("Time (last 10 min, 1 s min/mean/max)"),
+            TimeRange::SixHours => Some("Time (last 6 h, 1 s to 10 min min/mean/max)"),
+        }
+    }
+}
+
+fn format_duration_sec(duration_sec: u64) -> String {
+    if duration_sec.is_multiple_of(3600) {
+        format!("{}h", duration_sec / 3600)
+    } else if duration_sec.is_multiple_of(60) {
+        format!("{}m", duration_sec / 60)
+    } else {
+        format!("{duration_sec}s")
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    id: u64,
+    num_ticks: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+struct Level {
+    bucket_ticks: f64,
+    capacity: usize,
+    // Oldest first, up to the latest bucket
+    buckets: VecDeque<Bucket>,
+    // The latest bucket, which may still be filling up. It is kept out of the buffer so that
+    // adding a tick touches no memory beyond the rollups themselves.
+    latest: Option<Bucket>,
+}
+
+/// The minimum, mean and maximum per-tick values of each bucket in a range, oldest first, with
+/// each bucket plotted at its first tick
+#[derive(Default)]
+pub(crate) struct RollupSeries {
+    pub(crate) min: Vec<(f64, f64)>,
+    pub(crate) mean: Vec<(f64, f64)>,
+    pub(crate) max: Vec<(f64, f64)>,
+}
+
+impl RollupSeries {
+    /// The largest value of any bucket in the series
+    pub(crate) fn max_value(&self) -> f64 {
+        self.max.iter().map(|datum| datum.1).fold(0.0, f64::max)
+    }
+
+    /// Datasets plotting the means as dots, with the minimums and maximums around them in braille
+    pub(crate) fn datasets(&self, name: &str, color: Color) -> [Dataset<'_>; 3] {
+        [
+            Dataset::default()
+                .name(name.to_string())
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(color))
+                .data(&self.mean),
+            Dataset::default()
+                .name(format!("{name} min"))
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(color))
+                .data(&self.min),
+            Dataset::default()
+                .name(format!("{name} max"))
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(color))
+                .data(&self.max),
+        ]
+    }
+}
+
+pub(crate) struct Rollups {
+    levels: [Level; LEVELS.len()],
+}
+
+impl Rollups {
+    pub(crate) fn new(ticks_per_sec: f64) -> Self {
+        Self {
+            levels: LEVELS.map(|(duration_sec, capacity)| Level {
+                bucket_ticks: duration_sec as f64 * ticks_per_sec,
+                capacity,
+                buckets: VecDeque::new(),
+                latest: None,
+            }),
+        }
+    }
+
+    /// Adds the value of a tick, counting ticks from 1
+    pub(crate) fn push(&mut self, tick: f64, value: f64) {
+        for level in &mut self.levels {
+            let id = ((tick - 1.0) / level.bucket_ticks).floor() as u64;
+            match &mut level.latest {
+                Some(bucket) if bucket.id == id => {
+                    bucket.num_ticks += 1;
+                    bucket.sum += value;
+                    bucket.min = bucket.min.min(value);
+                    bucket.max = bucket.max.max(value);
+                }
+                latest => {
+                    let bucket = Bucket {
+                        id,
+                        num_ticks: 1,
+                        sum: value,
+                        min: value,
+                        max: value,
+                    };
+                    if let Some(complete) = latest.replace(bucket) {
+                        // The latest bucket counts towards the capacity
+                        if level.buckets.len() + 1 >= level.capacity {
+                            level.buckets.pop_front();
+                        }
+                        level.buckets.push_back(complete);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The buckets starting within the bounds, taking each span of time from the finest
+    /// resolution which still covers it
+    pub(crate) fn series(&self, bounds: [f64; 2]) -> RollupSeries {
+        // Coarser levels fill in what came before the oldest bucket of the finer ones
+        let mut buckets: Vec<(f64, Bucket)> = Vec::new();
+        let mut covered_from = f64::INFINITY;
+        for level in &self.levels {
+            let mut level_covered_from = covered_from;
+            for bucket in level.latest.iter().chain(level.buckets.iter().rev()) {
+                let first_tick = bucket.id as f64 * level.bucket_ticks + 1.0;
+                if first_tick < bounds[0] {
+                    break;
+                }
+                level_covered_from = first_tick;
+                if first_tick < covered_from && first_tick <= bounds[1] {
+                    buckets.push((first_tick, *bucket));
+                }
+            }
+            covered_from = covered_from.min(level_covered_from);
+        }
+        buckets.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut series = RollupSeries::default();
+        for (first_tick, bucket) in buckets {
+            series.min.push((first_tick, bucket.min));
+            series
+                .mean
+                .push((first_tick, bucket.sum / bucket.num_ticks as f64));
+            series.max.push((first_tick, bucket.max));
+        }
+        series
+    }
+}
@@ -0,0 +1,152 @@
+// A per-tick time series held in a ring buffer of the values alone, since its ticks follow on
+// from one another. Once the buffer has grown to the history it is never reallocated, so adding
+// the value of a tick costs the same however long the series has been collected for.
+
+use std::collections::VecDeque;
+
+use tsndt_common::Counter;
+
+pub(crate) struct Series {
+    values: VecDeque<f64>,
+    capacity: usize,
+    // Tick of the latest value, with the values before it on the ticks right before it
+    latest_tick: f64,
+}
+
+impl Series {
+    /// Creates an empty series which keeps the values of up to `capacity` ticks
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            values: VecDeque::new(),
+            capacity: capacity.max(1),
+            latest_tick: 0.0,
+        }
+    }
+
+    /// Adds the value of a tick after the latest one, dropping the oldest value if the series is
+    /// full. Ticks skipped in between get a value of 0.
+    pub(crate) fn push(&mut self, tick: f64, value: f64) {
+        if !self.values.is_empty() {
+            let skipped = (tick - self.latest_tick - 1.0).clamp(0.0, self.capacity as f64);
+            for _ in 0..skipped as usize {
+                self.push_value(0.0);
+            }
+        }
+        self.push_value(value);
+        self.latest_tick = tick;
+    }
+
+    fn push_value(&mut self, value: f64) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    fn first_tick(&self) -> f64 {
+        self.latest_tick + 1.0 - self.values.len() as f64
+    }
+
+    /// The latest tick and its value, if there is any
+    pub(crate) fn latest(&self) -> Option<(f64, f64)> {
+        self.values.back().map(|value| (self.latest_tick, *value))
+    }
+
+    /// Every tick kept and its value, oldest first
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = (f64, f64)> + '_ {
+        let first_tick = self.first_tick();
+        self.values
+            .iter()
+            .enumerate()
+            .map(move |(index, value)| (first_tick + index as f64, *value))
+    }
+
+    /// The ticks and values within the bounds of a window, oldest first
+    pub(crate) fn within(&self, bounds: [f64; 2]) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let first_tick = self.first_tick();
+        let len = self.values.len() as f64;
+        let start = (bounds[0].ceil() - first_tick).clamp(0.0, len) as usize;
+        let end = (bounds[1].floor() - first_tick + 1.0).clamp(0.0, len) as usize;
+        self.values
+            .range(start..end.max(start))
+            .enumerate()
+            .map(move |(index, value)| (first_tick + (start + index) as f64, *value))
+    }
+
+    /// The points within the bounds of a window, to be plotted
+    pub(crate) fn points(&self, bounds: [f64; 2]) -> Vec<(f64, f64)> {
+        self.within(bounds).collect()
+    }
+
+    /// Sum of the values within the bounds of a window
+    pub(crate) fn sum(&self, bounds: [f64; 2]) -> f64 {
+        self.within(bounds).map(|datum| datum.1).sum()
+    }
+}
+
+/// The counts of a single CPU, to show how the traffic of an interface or a source MAC address is
+/// spread across CPUs
+pub(crate) struct CpuCounts {
+    cumul_packet_count: u32,
+    tick_packet_counts: Series,
+    cumul_byte_count: u64,
+    tick_byte_counts: Series,
+}
+
+impl CpuCounts {
+    /// Starts from the cumulative counts of the CPU, with an empty history
+    pub(crate) fn new(counter: &Counter, series_capacity: usize) -> Self {
+        Self {
+            cumul_packet_count: counter.packets,
+            tick_packet_counts: Series::new(series_capacity),
+            cumul_byte_count: counter.bytes,
+            tick_byte_counts: Series::new(series_capacity),
+        }
+    }
+
+    /// Adds the counts of each CPU received since the previous tick, given their cumulative
+    /// counts. CPUs seen for the first time are counted from 0.
+    pub(crate) fn push_all(
+        cpus: &mut Vec<CpuCounts>,
+        tick_count: f64,
+        values: &[Counter],
+        series_capacity: usize,
+    ) {
+        for (cpu_id, cpu_counter) in values.iter().enumerate() {
+            if cpu_id == cpus.len() {
+                cpus.push(CpuCounts::new(
+                    &Counter {
+                        packets: 0,
+                        bytes: 0,
+                    },
+                    series_capacity,
+                ));
+            }
+            let cpu = &mut cpus[cpu_id];
+            cpu.tick_packet_counts.push(
+                tick_count,
+                cpu_counter.packets.saturating_sub(cpu.cumul_packet_count) as f64,
+            );
+            cpu.cumul_packet_count = cpu_counter.packets;
+            cpu.tick_byte_counts.push(
+                tick_count,
+                cpu_counter.bytes.saturating_sub(cpu.cumul_byte_count) as f64,
+            );
+            cpu.cumul_byte_count = cpu_counter.bytes;
+        }
+    }
+
+    /// Packet and byte totals of each CPU within the bounds of a window, ordered by CPU
+    pub(crate) fn sums(cpus: &[CpuCounts], bounds: [f64; 2]) -> Vec<(usize, u64, u64)> {
+        cpus.iter()
+            .enumerate()
+            .map(|(cpu_id, cpu)| {
+                (
+                    cpu_id,
+                    cpu.tick_packet_counts.sum(bounds) as u64,
+                    cpu.tick_byte_counts.sum(bounds) as u64,
+                )
+            })
+            .collect()
+    }
+}
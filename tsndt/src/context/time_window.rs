@@ -0,0 +1,104 @@
+// The span of ticks shown on the time axis of the time series plots. It follows the latest tick
+// until the display is paused, after which it stays put while collection goes on, and can be
+// scrolled and zoomed over the history which the contexts keep.
+
+use crate::{keymap::Action, settings::Settings};
+
+// The time axis is never zoomed in further than this, unless the window is already smaller
+const MIN_SIZE: f64 = 10.0;
+
+pub(crate) struct TimeWindow {
+    size: f64,
+    default_size: f64,
+    history_size: f64,
+    // The tick at the end of the window while paused
+    paused_end: Option<f64>,
+}
+
+impl TimeWindow {
+    pub(crate) fn new(settings: &Settings) -> Self {
+        Self {
+            size: settings.window_ticks as f64,
+            default_size: settings.window_ticks as f64,
+            history_size: settings.history_ticks as f64,
+            paused_end: None,
+        }
+    }
+
+    /// Number of ticks the series are kept for, beyond the tick they start from
+    pub(crate) fn history_size(&self) -> f64 {
+        self.history_size
+    }
+
+    /// Number of ticks kept of each series, counting the tick the history reaches back from
+    pub(crate) fn series_capacity(&self) -> usize {
+        self.history_size as usize + 1
+    }
+
+    /// Title of the time axis, which tells whether the plot still follows the latest ticks
+    pub(crate) fn axis_title(&self) -> &'static str {
+        if self.paused_end.is_some() {
+            "Time (paused)"
+        } else {
+            "Time"
+        }
+    }
+
+    /// The first and last tick of the window, given the latest tick
+    pub(crate) fn bounds(&self, tick_count: f64) -> [f64; 2] {
+        // A window paused for longer than the history is pushed along by the oldest tick kept
+        let end = match self.paused_end {
+            Some(paused_end) => paused_end.max(self.oldest_end(tick_count)),
+            None => tick_count,
+        }
+        .max(self.size);
+        [end - self.size, end]
+    }
+
+    /// The earliest end of the window which still has the history to fill it
+    fn oldest_end(&self, tick_count: f64) -> f64 {
+        (tick_count - self.history_size).max(0.0) + self.size
+    }
+
+    /// Moves the end of a paused window, keeping it within the history
+    fn move_end(&mut self, end: f64, tick_count: f64) {
+        self.paused_end = Some(end.max(self.oldest_end(tick_count)).min(tick_count));
+    }
+
+    /// Pauses, scrolls or zooms the window, returning whether the action was one of those.
+    /// Scrolling and zooming only apply while paused.
+    pub(crate) fn handle_action(&mut self, action: Action, tick_count: f64) -> bool {
+        let Some(paused_end) = self.paused_end else {
+            if action == Action::PauseDisplay {
+                self.paused_end = Some(tick_count);
+            }
+            return action == Action::PauseDisplay;
+        };
+
+        match action {
+            Action::PauseDisplay => {
+                // Resuming jumps back to the latest ticks, as they were shown before pausing
+                self.paused_end = None;
+                self.size = self.default_size;
+            }
+            Action::ScrollBack => {
+                self.move_end(paused_end - (self.size / 4.0).ceil(), tick_count);
+            }
+            Action::ScrollForward => {
+                self.move_end(paused_end + (self.size / 4.0).ceil(), tick_count);
+            }
+            Action::ZoomTimeIn => {
+                self.size = (self.size / 2.0)
+                    .ceil()
+                    .max(MIN_SIZE.min(self.default_size));
+                self.move_end(paused_end, tick_count);
+            }
+            Action::ZoomTimeOut => {
+                self.size = (self.size * 2.0).min(self.history_size);
+                self.move_end(paused_end, tick_count);
+            }
+            _ => return false,
+        }
+        true
+    }
+}
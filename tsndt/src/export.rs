@@ -0,0 +1,198 @@
+// Dumps the time series held by the contexts to files in the data directory, so that what was on
+// screen outlives the session. Each context with per-tick packet and byte counts gets a file of
+// its own, with one row per tick and entity. The contexts without any are reported as left out.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use color_eyre::eyre::{Context, Result};
+use serde::Serialize;
+
+use crate::{app, context::TsndtContext, source::DataSource};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub(crate) enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// The packets and bytes received by an entity, such as an interface or a MAC address, during a
+/// tick
+pub struct SeriesPoint {
+    pub(crate) tick: u64,
+    pub(crate) entity: String,
+    pub(crate) packets: u64,
+    pub(crate) bytes: u64,
+}
+
+#[derive(Serialize)]
+struct SeriesRow<'a> {
+    tick: u64,
+    timestamp_ns: u64,
+    entity: &'a str,
+    packets: u64,
+    bytes: u64,
+}
+
+/// Wall clock time of the recent ticks, for as far back as the contexts keep their series.
+/// Every context ticks together, so that their tick counts agree.
+pub(crate) struct TickTimestamps {
+    // Tick count and realtime timestamp, oldest first, with consecutive tick counts
+    timestamps_ns: VecDeque<(u64, u64)>,
+    capacity: usize,
+}
+
+impl TickTimestamps {
+    /// Starts before tick 1, leaving out the zeros which some series start from at tick 0
+    pub(crate) fn new(history_ticks: u64) -> Self {
+        // The series hold one more point than the history
+        let capacity = history_ticks as usize + 1;
+        Self {
+            timestamps_ns: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Records the time of a new tick, after the contexts have handled it
+    pub(crate) fn on_tick(&mut self, source: &dyn DataSource) {
+        let tick = self.timestamps_ns.back().map_or(1, |(tick, _)| tick + 1);
+        if self.timestamps_ns.len() == self.capacity {
+            self.timestamps_ns.pop_front();
+        }
+        self.timestamps_ns
+            .push_back((tick, source.now_ns() + source.realtime_offset_ns()));
+    }
+
+    fn get(&self, tick: u64) -> Option<u64> {
+        let (first_tick, _) = self.timestamps_ns.front()?;
+        let index = tick.checked_sub(*first_tick)?;
+        self.timestamps_ns
+            .get(index as usize)
+            .map(|(_, timestamp_ns)| *timestamp_ns)
+    }
+}
+
+fn write_csv(path: &Path, rows: &[SeriesRow]) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "tick,timestamp_ns,entity,packets,bytes")?;
+    for row in rows {
+        // Quote entities which would otherwise break the row up
+        let entity = if row.entity.contains([',', '"', '\n']) {
+            format!("\"{}\"", row.entity.replace('"', "\"\""))
+        } else {
+            row.entity.to_string()
+        };
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            row.tick, row.timestamp_ns, entity, row.packets, row.bytes
+        )?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn write_json(path: &Path, rows: &[SeriesRow]) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, rows)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// What exporting the series of the contexts did
+pub(crate) struct Export {
+    /// Files written, one per context with any points
+    pub(crate) paths: Vec<PathBuf>,
+    /// Names of the contexts which keep no packet and byte series, so were left out
+    pub(crate) unexported_contexts: Vec<String>,
+}
+
+impl Export {
+    /// Logs the files written and the contexts left out
+    pub(crate) fn log(&self) {
+        for path in &self.paths {
+            tracing::info!("Exported time series to {}", path.display());
+        }
+        if !self.unexported_contexts.is_empty() {
+            tracing::info!(
+                "Not exported, since they keep no packet and byte time series: {}",
+                self.unexported_contexts.join(", ")
+            );
+        }
+    }
+}
+
+/// Writes the series of every context which has any to a file of its own in the data directory
+pub(crate) fn export_series<'a>(
+    contexts: impl IntoIterator<Item = &'a dyn TsndtContext>,
+    tick_timestamps: &TickTimestamps,
+    format: ExportFormat,
+) -> Result<Export> {
+    let directory = app::get_data_dir();
+    fs::create_dir_all(&directory)?;
+    let exported_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let mut paths = Vec::new();
+    let mut unexported_contexts = Vec::new();
+    for context in contexts {
+        let Some(mut points) = context.export_series() else {
+            unexported_contexts.push(context.get_context_name());
+            continue;
+        };
+        if points.is_empty() {
+            continue;
+        }
+        points.sort_by(|a, b| a.tick.cmp(&b.tick).then_with(|| a.entity.cmp(&b.entity)));
+        let rows: Vec<SeriesRow> = points
+            .iter()
+            .filter_map(|point| {
+                Some(SeriesRow {
+                    tick: point.tick,
+                    timestamp_ns: tick_timestamps.get(point.tick)?,
+                    entity: &point.entity,
+                    packets: point.packets,
+                    bytes: point.bytes,
+                })
+            })
+            .collect();
+
+        let path = directory.join(format!(
+            "{}-{}-{}.{}",
+            env!("CARGO_PKG_NAME"),
+            exported_at,
+            context.get_context_name().to_lowercase().replace(' ', "-"),
+            format.extension()
+        ));
+        match format {
+            ExportFormat::Csv => write_csv(&path, &rows),
+            ExportFormat::Json => write_json(&path, &rows),
+        }
+        .wrap_err_with(|| format!("Failed to export time series to {}", path.display()))?;
+        paths.push(path);
+    }
+
+    Ok(Export {
+        paths,
+        unexported_contexts,
+    })
+}
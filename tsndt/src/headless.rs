@@ -0,0 +1,237 @@
+// Runs without the TUI, printing the interface and top MAC address rates at each tick, either as
+// a table to read or as JSON Lines to pipe into other tools. The same models as the Network
+// Interfaces and Ethernet contexts turn the counters into rates.
+
+use std::{
+    io::{self, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use color_eyre::eyre::Result;
+use serde::Serialize;
+
+use crate::{
+    context::{
+        ethernet::{get_mac_string, EthernetContext},
+        network_interface::NetworkInterfaceContext,
+        TsndtContext,
+    },
+    export::{self, ExportFormat, TickTimestamps},
+    metrics::MetricsExporter,
+    settings::Settings,
+    source::DataSource,
+    teardown,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Human-readable table
+    #[default]
+    Table,
+    /// One JSON object per sample
+    Jsonl,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct HeadlessOptions {
+    pub(crate) format: OutputFormat,
+    /// Stop after this many samples
+    pub(crate) count: Option<u64>,
+    /// Stop once this much time has passed
+    pub(crate) duration: Option<Duration>,
+    /// Number of the busiest source MAC addresses printed in each sample
+    pub(crate) top_macs: usize,
+    /// Format of the time series dumped on exit, if they are
+    pub(crate) export: Option<ExportFormat>,
+}
+
+#[derive(Serialize)]
+struct Sample {
+    sample: u64,
+    timestamp_ns: u64,
+    interfaces: Vec<InterfaceRates>,
+    macs: Vec<MacRates>,
+}
+
+#[derive(Serialize)]
+struct InterfaceRates {
+    index: u32,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+    packets_per_sec: f64,
+    bytes_per_sec: f64,
+}
+
+#[derive(Serialize)]
+struct MacRates {
+    mac: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+    packets_per_sec: f64,
+    bytes_per_sec: f64,
+}
+
+/// Formats a realtime timestamp as a UTC time of day with milliseconds
+fn format_time_of_day(timestamp_ns: u64) -> String {
+    let millis = (timestamp_ns / 1_000_000) % (24 * 3600 * 1000);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        (millis / 60_000) % 60,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+fn write_table_header(out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<12}  {:<4}  {:<24}  {:>12}  {:>12}",
+        "TIME (UTC)", "KIND", "NAME", "PKT/S", "KB/S"
+    )
+}
+
+fn write_table_sample(out: &mut impl Write, sample: &Sample) -> io::Result<()> {
+    let time = format_time_of_day(sample.timestamp_ns);
+    for interface in &sample.interfaces {
+        let name = match &interface.alias {
+            Some(alias) => format!("{} ({})", alias, interface.name),
+            None => interface.name.clone(),
+        };
+        writeln!(
+            out,
+            "{:<12}  {:<4}  {:<24}  {:>12.1}  {:>12.2}",
+            time,
+            "if",
+            name,
+            interface.packets_per_sec,
+            interface.bytes_per_sec / 1000.0
+        )?;
+    }
+    for mac in &sample.macs {
+        let name = match &mac.alias {
+            Some(alias) => format!("{} ({})", alias, mac.mac),
+            None => mac.mac.clone(),
+        };
+        writeln!(
+            out,
+            "{:<12}  {:<4}  {:<24}  {:>12.1}  {:>12.2}",
+            time,
+            "mac",
+            name,
+            mac.packets_per_sec,
+            mac.bytes_per_sec / 1000.0
+        )?;
+    }
+    writeln!(out)
+}
+
+fn write_sample(out: &mut impl Write, format: OutputFormat, sample: &Sample) -> io::Result<()> {
+    match format {
+        OutputFormat::Table => write_table_sample(out, sample)?,
+        OutputFormat::Jsonl => {
+            serde_json::to_writer(&mut *out, sample)?;
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
+
+/// Prints a sample at each tick until the count or duration is reached, or a signal stops it
+pub(crate) fn run(
+    source: &mut dyn DataSource,
+    settings: &Settings,
+    options: &HeadlessOptions,
+    metrics: Option<&MetricsExporter>,
+) -> Result<()> {
+    let mut interfaces_context = NetworkInterfaceContext::new(source, settings);
+    let mut ethernet_context = EthernetContext::new(source, settings);
+    let ticks_per_sec = settings.ticks(1);
+    let mut tick_timestamps = TickTimestamps::new(settings.history_ticks);
+
+    let mut out = io::stdout().lock();
+    if options.format == OutputFormat::Table {
+        write_table_header(&mut out)?;
+    }
+
+    let tick_rate = Duration::from_millis(settings.tick_rate_ms);
+    let started = Instant::now();
+    let mut next_tick = started + tick_rate;
+    let mut samples = 0;
+    loop {
+        if options.count.is_some_and(|count| samples >= count)
+            || options
+                .duration
+                .is_some_and(|duration| started.elapsed() >= duration)
+        {
+            break;
+        }
+
+        thread::sleep(next_tick.saturating_duration_since(Instant::now()));
+        next_tick += tick_rate;
+        if teardown::shutdown_requested() {
+            break;
+        }
+
+        // A paused data source holds the samples still
+        if !source.on_tick()? {
+            continue;
+        }
+        interfaces_context.handle_tick(source)?;
+        ethernet_context.handle_tick(source)?;
+        tick_timestamps.on_tick(source);
+        if let Some(metrics) = metrics {
+            metrics.update(source)?;
+        }
+        samples += 1;
+
+        let sample = Sample {
+            sample: samples,
+            timestamp_ns: source.now_ns() + source.realtime_offset_ns(),
+            interfaces: interfaces_context
+                .model
+                .latest_tick_counts()
+                .into_iter()
+                .map(|(interface, packet_count, byte_count)| InterfaceRates {
+                    index: interface.index,
+                    name: interface.name.clone(),
+                    alias: settings
+                        .aliases
+                        .interface(&interface.name)
+                        .map(String::from),
+                    packets_per_sec: packet_count * ticks_per_sec,
+                    bytes_per_sec: byte_count * ticks_per_sec,
+                })
+                .collect(),
+            macs: ethernet_context
+                .model
+                .latest_tick_counts()
+                .into_iter()
+                .take(options.top_macs)
+                .map(|(src_mac, packet_count, byte_count)| MacRates {
+                    mac: get_mac_string(&src_mac),
+                    alias: settings.aliases.mac(&src_mac).map(String::from),
+                    packets_per_sec: packet_count * ticks_per_sec,
+                    bytes_per_sec: byte_count * ticks_per_sec,
+                })
+                .collect(),
+        };
+
+        match write_sample(&mut out, options.format, &sample) {
+            Ok(()) => {}
+            // The reader went away, as when piping into head, which is as good as being done
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => break,
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    if let Some(format) = options.export {
+        let contexts: [&dyn TsndtContext; 2] = [&interfaces_context, &ethernet_context];
+        export::export_series(contexts, &tick_timestamps, format)?.log();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,327 @@
+// Maps keys to named actions, which the app, the data source and the contexts handle instead of
+// raw key events. The bindings can be changed in the [keys] table of the configuration file, and
+// the command help is generated from them.
+
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use color_eyre::eyre::{eyre, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::KeysConfig;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    Quit,
+    NextContext,
+    PreviousContext,
+    SelectPrevious,
+    SelectNext,
+    ToggleSelected,
+    CycleXdpMode,
+    SortSelection,
+    ZoomBytes,
+    ZoomPackets,
+    ToggleAutoscaling,
+    ZoomIn,
+    ZoomOut,
+    CyclePlots,
+    TogglePeaks,
+    ResizeUp,
+    ResizeDown,
+    ResizeLeft,
+    ResizeRight,
+    ToggleFollow,
+    ClearPackets,
+    TogglePcapng,
+    TogglePause,
+    Step,
+    Slower,
+    Faster,
+    SeekBackward,
+    SeekForward,
+    ExportSeries,
+    PauseDisplay,
+    ScrollBack,
+    ScrollForward,
+    ZoomTimeIn,
+    ZoomTimeOut,
+    CycleTimeRange,
+}
+
+impl Action {
+    /// Every action with its default keys
+    const DEFAULT_BINDINGS: [(Action, &'static str); 35] = [
+        (Action::Quit, "q"),
+        (Action::NextContext, "right"),
+        (Action::PreviousContext, "left"),
+        (Action::SelectPrevious, "up"),
+        (Action::SelectNext, "down"),
+        (Action::ToggleSelected, "t"),
+        (Action::CycleXdpMode, "x"),
+        (Action::SortSelection, "s"),
+        (Action::ZoomBytes, "b"),
+        (Action::ZoomPackets, "p"),
+        (Action::ToggleAutoscaling, "a"),
+        (Action::ZoomIn, "+"),
+        (Action::ZoomOut, "-"),
+        (Action::CyclePlots, "v"),
+        (Action::TogglePeaks, "m"),
+        (Action::ResizeUp, "ctrl+up"),
+        (Action::ResizeDown, "ctrl+down"),
+        (Action::ResizeLeft, "ctrl+left"),
+        (Action::ResizeRight, "ctrl+right"),
+        (Action::ToggleFollow, "f"),
+        (Action::ClearPackets, "c"),
+        (Action::TogglePcapng, "w"),
+        (Action::TogglePause, "space"),
+        (Action::Step, "n"),
+        (Action::Slower, "["),
+        (Action::Faster, "]"),
+        (Action::SeekBackward, "<"),
+        (Action::SeekForward, ">"),
+        (Action::ExportSeries, "e"),
+        (Action::PauseDisplay, "h"),
+        (Action::ScrollBack, ","),
+        (Action::ScrollForward, "."),
+        (Action::ZoomTimeIn, "}"),
+        (Action::ZoomTimeOut, "{"),
+        (Action::CycleTimeRange, "r"),
+    ];
+
+    /// The name of the action in the configuration file
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NextContext => "next_context",
+            Action::PreviousContext => "previous_context",
+            Action::SelectPrevious => "select_previous",
+            Action::SelectNext => "select_next",
+            Action::ToggleSelected => "toggle_selected",
+            Action::CycleXdpMode => "cycle_xdp_mode",
+            Action::SortSelection => "sort_selection",
+            Action::ZoomBytes => "zoom_bytes",
+            Action::ZoomPackets => "zoom_packets",
+            Action::ToggleAutoscaling => "toggle_autoscaling",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::CyclePlots => "cycle_plots",
+            Action::TogglePeaks => "toggle_peaks",
+            Action::ResizeUp => "resize_up",
+            Action::ResizeDown => "resize_down",
+            Action::ResizeLeft => "resize_left",
+            Action::ResizeRight => "resize_right",
+            Action::ToggleFollow => "toggle_follow",
+            Action::ClearPackets => "clear_packets",
+            Action::TogglePcapng => "toggle_pcapng",
+            Action::TogglePause => "toggle_pause",
+            Action::Step => "step",
+            Action::Slower => "slower",
+            Action::Faster => "faster",
+            Action::SeekBackward => "seek_backward",
+            Action::SeekForward => "seek_forward",
+            Action::ExportSeries => "export_series",
+            Action::PauseDisplay => "pause_display",
+            Action::ScrollBack => "scroll_back",
+            Action::ScrollForward => "scroll_forward",
+            Action::ZoomTimeIn => "zoom_time_in",
+            Action::ZoomTimeOut => "zoom_time_out",
+            Action::CycleTimeRange => "cycle_time_range",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Action::DEFAULT_BINDINGS
+            .iter()
+            .map(|(action, _)| *action)
+            .find(|action| action.name() == name)
+            .ok_or_else(|| format!("unknown action '{name}'"))
+    }
+}
+
+/// A key along with the modifiers held down with it
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is already part of the character typed, and terminals differ on reporting it
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers.difference(KeyModifiers::SHIFT),
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+}
+
+impl From<KeyEvent> for KeyBinding {
+    fn from(key_event: KeyEvent) -> Self {
+        Self::new(key_event.code, key_event.modifiers)
+    }
+}
+
+/// Parses keys such as "q", "+", "space", "pagedown", "f5" and "ctrl+up"
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let (modifier_names, key_name) = match key.strip_suffix("++") {
+            Some(modifier_names) => (modifier_names, "+"),
+            None => match key.rsplit_once('+') {
+                Some((modifier_names, key_name)) if !key_name.is_empty() => {
+                    (modifier_names, key_name)
+                }
+                _ => ("", key),
+            },
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier_name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match modifier_name.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{modifier_name}' in key '{key}'")),
+            };
+        }
+
+        let mut chars = key_name.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key_name.to_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{key}'")),
+                },
+            },
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            code => write!(f, "{code}"),
+        }
+    }
+}
+
+/// The key bindings in effect, where each key triggers at most one action
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    actions: HashMap<KeyBinding, Action>,
+    bindings: HashMap<Action, Vec<KeyBinding>>,
+}
+
+impl Keymap {
+    /// Builds the keymap from the defaults, with the keys of the actions in `keys` replaced
+    pub(crate) fn new(keys: &HashMap<String, KeysConfig>) -> Result<Self> {
+        let mut bindings: HashMap<Action, Vec<KeyBinding>> = HashMap::new();
+        for (action, key) in Action::DEFAULT_BINDINGS {
+            let key_binding = key.parse().map_err(|error: String| eyre!(error))?;
+            bindings.insert(action, vec![key_binding]);
+        }
+        for (action_name, action_keys) in keys {
+            let action: Action = action_name.parse().map_err(|error: String| eyre!(error))?;
+            let key_bindings = action_keys
+                .keys()
+                .iter()
+                .map(|key| key.parse())
+                .collect::<Result<Vec<KeyBinding>, String>>()
+                .map_err(|error| eyre!("invalid key for {}: {}", action_name, error))?;
+            bindings.insert(action, key_bindings);
+        }
+
+        let mut actions = HashMap::new();
+        for (action, _) in Action::DEFAULT_BINDINGS {
+            for key_binding in &bindings[&action] {
+                if let Some(other_action) = actions.insert(*key_binding, action) {
+                    return Err(eyre!(
+                        "The key {} is bound to both {} and {}",
+                        key_binding,
+                        other_action.name(),
+                        action.name()
+                    ));
+                }
+            }
+        }
+
+        Ok(Self { actions, bindings })
+    }
+
+    pub(crate) fn action(&self, key_event: KeyEvent) -> Option<Action> {
+        self.actions.get(&KeyBinding::from(key_event)).copied()
+    }
+
+    /// The keys of one or more related actions, as shown in the command help, such as "↑/↓" for
+    /// selecting the previous and the next item. Returns None if none of them are bound.
+    pub(crate) fn keys(&self, actions: &[Action]) -> Option<String> {
+        let keys: Vec<String> = actions
+            .iter()
+            .filter_map(|action| {
+                let key_bindings = self.bindings.get(action)?;
+                (!key_bindings.is_empty()).then(|| {
+                    key_bindings
+                        .iter()
+                        .map(|key_binding| key_binding.to_string())
+                        .collect::<Vec<String>>()
+                        .join(",")
+                })
+            })
+            .collect();
+        (!keys.is_empty()).then(|| keys.join("/"))
+    }
+
+    /// Generates a line of command help, leaving out the commands with no keys bound
+    pub(crate) fn help(&self, commands: &[(&[Action], &str)]) -> String {
+        commands
+            .iter()
+            .filter_map(|(actions, description)| {
+                self.keys(actions)
+                    .map(|keys| format!("({keys}) {description}"))
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
@@ -0,0 +1,127 @@
+use app::App;
+use clap::Parser;
+use cli::{Cli, Command};
+use config::Config;
+use metrics::MetricsExporter;
+use settings::Settings;
+use source::{
+    af_packet::AfPacketSource,
+    ebpf::EbpfSource,
+    pinned::{self, PinnedSource},
+    recording::RecordingSource,
+    replay::ReplaySource,
+    session::{self, SessionSource},
+    DataSource,
+};
+
+pub mod app;
+pub(crate) mod cli;
+pub(crate) mod config;
+pub(crate) mod context;
+pub(crate) mod export;
+pub(crate) mod headless;
+pub(crate) mod keymap;
+pub(crate) mod metrics;
+pub(crate) mod pcapng;
+pub(crate) mod settings;
+pub(crate) mod source;
+pub(crate) mod teardown;
+
+/// What the benchmarks in benches/ are built against, which is not meant for anything else
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::{
+        context::{ethernet::EthernetContext, TsndtContext},
+        export::SeriesPoint,
+        keymap::{Action, Keymap},
+        settings::Settings,
+        source::{ebpf::XdpMode, DataSource},
+    };
+}
+
+/// Runs tsndt as the command line asks, on the tokio runtime that the binary starts
+pub fn run() -> color_eyre::Result<()> {
+    let cli = Cli::parse();
+
+    // 0. Load the configuration file, initialize app logging and error reporting, and resolve
+    // the settings
+    let config = Config::load()?;
+    app::initialize_logging(cli.log_level.as_deref(), config.log_level.as_deref())?;
+    color_eyre::install()?;
+    let settings = Settings::new(&cli, &config)?;
+
+    if let Some(Command::Cleanup) = cli.command {
+        return pinned::cleanup();
+    }
+
+    // 1. Open the data source: a session or a capture to replay, pinned counters to view, or the
+    // eBPF program, and record it if asked to
+    let mut source: Box<dyn DataSource> = match &cli.replay {
+        Some(path) if session::is_session_file(path) => Box::new(SessionSource::open(
+            path,
+            cli.replay_speed,
+            cli.step,
+            settings.tick_rate_ms,
+        )?),
+        Some(path) => Box::new(ReplaySource::open(
+            path,
+            cli.replay_speed,
+            cli.step,
+            &settings,
+        )?),
+        None if cli.viewer => Box::new(PinnedSource::open(&settings.interface_filter)?),
+        None => match EbpfSource::load(&settings, cli.pin) {
+            Ok(bpf) => Box::new(EbpfSource::new(
+                bpf,
+                cli.xdp_modes(),
+                cli.existing_xdp_policy(),
+                cli.pin,
+                &settings,
+            )?),
+            // Pinned counters are kept by the XDP program, so there is nothing to fall back to
+            Err(report) if cli.pin => return Err(report),
+            Err(report) => {
+                tracing::warn!(
+                    "Could not load the eBPF program, falling back to AF_PACKET: {:#}",
+                    report
+                );
+                Box::new(AfPacketSource::open(&settings)?)
+            }
+        },
+    };
+
+    if let Some(path) = &cli.record {
+        source = Box::new(RecordingSource::create(
+            source,
+            path,
+            settings.tick_rate_ms,
+        )?);
+    }
+
+    // 2. Start serving metrics if asked to, which is done at each tick from then on
+    let metrics = cli.metrics_listen.map(MetricsExporter::start).transpose()?;
+
+    // 3. Fire up the display, making sure that it is given back however tsndt stops, or print
+    // the rates without one
+    teardown::install_signal_handlers()?;
+    let result = if cli.no_tui {
+        headless::run(
+            source.as_mut(),
+            &settings,
+            &cli.headless_options(),
+            metrics.as_ref(),
+        )
+    } else {
+        let terminal = ratatui::init();
+        teardown::install_panic_hook();
+        let result = App::new(source.as_ref(), &cli, &settings, metrics)
+            .and_then(|app| app.run(source.as_mut(), terminal));
+        ratatui::restore();
+        result
+    };
+
+    // 4. Detach from the interfaces before the log is flushed, so that any errors are in it
+    drop(source);
+    app::sync_log();
+    result
+}
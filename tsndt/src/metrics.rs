@@ -0,0 +1,208 @@
+// Exposes the interface and source MAC address counters over HTTP, in the Prometheus text format
+// or in OpenMetrics if the scraper asks for it. The counters are read from the data source at each
+// tick, which for the eBPF program is straight from IF_RX_COUNT and SMAC_RX_COUNT, and served from
+// that snapshot by a thread of the exporter's own, so that scrapes never wait on the display.
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use color_eyre::eyre::{Context, Result};
+
+use crate::{context::ethernet::get_mac_string, source::DataSource};
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+// Scrapers which are slow to send their request should not hold up the others for long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Cumulative counters across CPUs, as of the latest tick
+#[derive(Default)]
+struct Snapshot {
+    interfaces: Vec<(String, u64, u64)>,
+    macs: Vec<([u8; 6], u64, u64)>,
+}
+
+pub(crate) struct MetricsExporter {
+    snapshot: Arc<Mutex<Snapshot>>,
+}
+
+impl MetricsExporter {
+    /// Starts serving the metrics on `address`, at /metrics
+    pub(crate) fn start(address: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(address)
+            .wrap_err_with(|| format!("failed to listen for metrics scrapes on {address}"))?;
+        let snapshot = Arc::new(Mutex::new(Snapshot::default()));
+
+        let served_snapshot = Arc::clone(&snapshot);
+        thread::Builder::new()
+            .name(String::from("metrics"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| serve(stream, &served_snapshot));
+                    if let Err(error) = result {
+                        tracing::debug!("Failed to serve a metrics scrape: {}", error);
+                    }
+                }
+            })?;
+        tracing::info!("Serving metrics on http://{}/metrics", address);
+
+        Ok(Self { snapshot })
+    }
+
+    /// Takes a new snapshot of the counters from the data source
+    pub(crate) fn update(&self, source: &dyn DataSource) -> Result<()> {
+        let mut interfaces = Vec::with_capacity(source.interfaces().len());
+        for interface in source.interfaces() {
+            source.interface_counters(interface.index, &mut |counters| {
+                if counters.is_empty() {
+                    return;
+                }
+                interfaces.push((
+                    interface.name.clone(),
+                    counters.iter().map(|counter| counter.packets as u64).sum(),
+                    counters.iter().map(|counter| counter.bytes).sum(),
+                ));
+            })?;
+        }
+
+        let mut macs: Vec<([u8; 6], u64, u64)> = Vec::new();
+        source.mac_counters(&mut |src_mac, counters| {
+            macs.push((
+                *src_mac,
+                counters.iter().map(|counter| counter.packets as u64).sum(),
+                counters.iter().map(|counter| counter.bytes).sum(),
+            ))
+        })?;
+        macs.sort_by_key(|mac| mac.0);
+
+        let mut snapshot = self.snapshot.lock().unwrap();
+        snapshot.interfaces = interfaces;
+        snapshot.macs = macs;
+        Ok(())
+    }
+}
+
+fn serve(stream: TcpStream, snapshot: &Mutex<Snapshot>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut openmetrics = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("accept") && value.contains("application/openmetrics-text")
+            {
+                openmetrics = true;
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = render(&snapshot.lock().unwrap(), openmetrics);
+            let content_type = if openmetrics {
+                OPENMETRICS_CONTENT_TYPE
+            } else {
+                PROMETHEUS_CONTENT_TYPE
+            };
+            ("200 OK", content_type, body)
+        }
+        _ => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            String::from("Metrics are served at /metrics\n"),
+        ),
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: \
+         close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+/// Escapes a label value as the exposition formats require
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render(snapshot: &Snapshot, openmetrics: bool) -> String {
+    let mut body = String::new();
+    // OpenMetrics names counter families without the _total suffix of their samples
+    let mut write_family = |name: &str, help: &str, samples: Vec<(String, u64)>| {
+        let family = if openmetrics {
+            name.trim_end_matches("_total")
+        } else {
+            name
+        };
+        let _ = writeln!(body, "# HELP {family} {help}");
+        let _ = writeln!(body, "# TYPE {family} counter");
+        for (labels, value) in samples {
+            let _ = writeln!(body, "{name}{{{labels}}} {value}");
+        }
+    };
+
+    let interface_labels =
+        |name: &String| format!("ifname=\"{}\"", escape_label_value(name.as_str()));
+    write_family(
+        "tsndt_rx_packets_total",
+        "Packets received on the interface",
+        snapshot
+            .interfaces
+            .iter()
+            .map(|(name, packets, _)| (interface_labels(name), *packets))
+            .collect(),
+    );
+    write_family(
+        "tsndt_rx_bytes_total",
+        "Bytes received on the interface",
+        snapshot
+            .interfaces
+            .iter()
+            .map(|(name, _, bytes)| (interface_labels(name), *bytes))
+            .collect(),
+    );
+
+    let mac_labels = |src_mac: &[u8; 6]| format!("mac=\"{}\"", get_mac_string(src_mac));
+    write_family(
+        "tsndt_src_mac_rx_packets_total",
+        "Packets received from the source MAC address, until it is removed for being idle",
+        snapshot
+            .macs
+            .iter()
+            .map(|(src_mac, packets, _)| (mac_labels(src_mac), *packets))
+            .collect(),
+    );
+    write_family(
+        "tsndt_src_mac_rx_bytes_total",
+        "Bytes received from the source MAC address, until it is removed for being idle",
+        snapshot
+            .macs
+            .iter()
+            .map(|(src_mac, _, bytes)| (mac_labels(src_mac), *bytes))
+            .collect(),
+    );
+
+    if openmetrics {
+        body.push_str("# EOF\n");
+    }
+    body
+}
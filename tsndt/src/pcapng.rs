@@ -0,0 +1,161 @@
+// Minimal pcapng writer for the frames sampled by the eBPF program, following the block layouts in
+// https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-03.html
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{Context, Result};
+use network_interface::NetworkInterface;
+use tsndt_common::PACKET_SAMPLE_MAX_LEN;
+
+const SECTION_HEADER_BLOCK_TYPE: u32 = 0x0a0d0d0a;
+const INTERFACE_DESCRIPTION_BLOCK_TYPE: u32 = 0x00000001;
+const ENHANCED_PACKET_BLOCK_TYPE: u32 = 0x00000006;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const LINKTYPE_ETHERNET: u16 = 1;
+
+const OPT_ENDOFOPT: u16 = 0;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const IF_TSRESOL: u16 = 9;
+// Timestamps are written as nanoseconds (10^-9 seconds)
+const TSRESOL_NANOSECONDS: u8 = 9;
+
+/// Writes sampled frames to a pcapng file, with one interface description block per interface
+pub(crate) struct PcapngWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    // Maps an interface index to the interface ID used by the blocks in this section
+    interface_ids: HashMap<u32, u32>,
+    num_packets: u64,
+}
+
+fn pad_len(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len() + pad_len(value.len()), 0);
+}
+
+fn push_end_of_options(body: &mut Vec<u8>) {
+    push_option(body, OPT_ENDOFOPT, &[]);
+}
+
+impl PcapngWriter {
+    /// Creates the file and writes the section header, followed by an interface description
+    /// block for each of the given interfaces
+    pub(crate) fn create(path: &Path, interfaces: &[NetworkInterface]) -> Result<Self> {
+        let file = File::create(path)
+            .wrap_err_with(|| format!("Failed to create pcapng file {}", path.display()))?;
+        let mut pcapng_writer = Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            interface_ids: HashMap::new(),
+            num_packets: 0,
+        };
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // The section length is not known up front
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        push_option(
+            &mut body,
+            SHB_USERAPPL,
+            concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).as_bytes(),
+        );
+        push_end_of_options(&mut body);
+        pcapng_writer.write_block(SECTION_HEADER_BLOCK_TYPE, &body)?;
+
+        for interface in interfaces {
+            pcapng_writer.add_interface(interface.index, &interface.name)?;
+        }
+
+        Ok(pcapng_writer)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn num_packets(&self) -> u64 {
+        self.num_packets
+    }
+
+    fn add_interface(&mut self, if_index: u32, name: &str) -> Result<u32> {
+        let interface_id = self.interface_ids.len() as u32;
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(PACKET_SAMPLE_MAX_LEN as u32).to_le_bytes());
+        push_option(&mut body, IF_NAME, name.as_bytes());
+        push_option(
+            &mut body,
+            IF_DESCRIPTION,
+            format!("ifindex {if_index}").as_bytes(),
+        );
+        push_option(&mut body, IF_TSRESOL, &[TSRESOL_NANOSECONDS]);
+        push_end_of_options(&mut body);
+        self.write_block(INTERFACE_DESCRIPTION_BLOCK_TYPE, &body)?;
+
+        self.interface_ids.insert(if_index, interface_id);
+        Ok(interface_id)
+    }
+
+    /// Writes a frame as an enhanced packet block. `timestamp_ns` is wall clock time since the
+    /// Unix epoch.
+    pub(crate) fn write_packet(
+        &mut self,
+        if_index: u32,
+        timestamp_ns: u64,
+        data: &[u8],
+        original_len: u32,
+    ) -> Result<()> {
+        // Interfaces which appeared after the file was created get their description blocks on
+        // first use, which pcapng allows anywhere before the packets that reference them
+        let interface_id = match self.interface_ids.get(&if_index) {
+            Some(interface_id) => *interface_id,
+            None => self.add_interface(if_index, &if_index.to_string())?,
+        };
+
+        let mut body = Vec::with_capacity(20 + data.len() + 3);
+        body.extend_from_slice(&interface_id.to_le_bytes());
+        body.extend_from_slice(&((timestamp_ns >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(timestamp_ns as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&original_len.to_le_bytes());
+        body.extend_from_slice(data);
+        body.resize(body.len() + pad_len(data.len()), 0);
+        self.write_block(ENHANCED_PACKET_BLOCK_TYPE, &body)?;
+
+        self.num_packets += 1;
+        Ok(())
+    }
+
+    /// Flushes any buffered blocks to the file
+    pub(crate) fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> Result<()> {
+        // The block type and both copies of the total length wrap the body
+        let total_len = (body.len() + 12) as u32;
+        self.writer.write_all(&block_type.to_le_bytes())?;
+        self.writer.write_all(&total_len.to_le_bytes())?;
+        self.writer.write_all(body)?;
+        self.writer.write_all(&total_len.to_le_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+// Settings which tune how tsndt runs and what it shows, resolved once at startup from the
+// command line and the configuration file, and handed to the data source and the contexts
+
+use std::collections::HashMap;
+
+use color_eyre::eyre::{eyre, Result};
+use ratatui::style::Color;
+use regex::Regex;
+
+use crate::{cli::Cli, config::Config, context::ContextKind, keymap::Keymap};
+
+pub(crate) const DEFAULT_TICK_RATE_MS: u64 = 200;
+pub(crate) const DEFAULT_WINDOW_TICKS: u64 = 50;
+pub(crate) const DEFAULT_HISTORY_TICKS: u64 = 3000;
+pub(crate) const DEFAULT_MAC_IDLE_TIMEOUT_SEC: u64 = 300;
+pub(crate) const DEFAULT_BURST_BUCKET_US: u64 = 1000;
+
+/// Selects interfaces by name, where each pattern is a regex which has to match the whole name.
+/// A plain interface name is a pattern matching just that interface.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InterfaceFilter {
+    /// If not empty, only interfaces matching one of these are selected
+    pub(crate) include: Vec<Regex>,
+    /// Interfaces matching one of these are never selected
+    pub(crate) exclude: Vec<Regex>,
+}
+
+impl InterfaceFilter {
+    pub(crate) fn matches(&self, interface_name: &str) -> bool {
+        (self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|regex| regex.is_match(interface_name)))
+            && !self
+                .exclude
+                .iter()
+                .any(|regex| regex.is_match(interface_name))
+    }
+}
+
+/// Compiles an interface pattern so that it has to match whole interface names
+pub(crate) fn parse_interface_pattern(pattern: &str) -> Result<Regex, String> {
+    Regex::new(&format!("^(?:{pattern})$"))
+        .map_err(|error| format!("invalid interface pattern '{pattern}': {error}"))
+}
+
+/// Initial zoom and layout of the plots, which the keys of each context adjust from there
+#[derive(Clone, Debug)]
+pub(crate) struct PlotSettings {
+    pub(crate) autoscaling: bool,
+    pub(crate) packet_axis_max: f64,
+    pub(crate) byte_axis_max: f64,
+    pub(crate) histogram_width_percentage: u16,
+    pub(crate) byte_counters_height_percentage: u16,
+    pub(crate) multicast_groups_height_percentage: u16,
+    pub(crate) hop_limit_height_percentage: u16,
+}
+
+impl Default for PlotSettings {
+    fn default() -> Self {
+        Self {
+            autoscaling: true,
+            packet_axis_max: 40.0,
+            byte_axis_max: 50000.0,
+            histogram_width_percentage: 25,
+            byte_counters_height_percentage: 50,
+            multicast_groups_height_percentage: 60,
+            hop_limit_height_percentage: 50,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ColorSettings {
+    pub(crate) zoom_context: Color,
+    pub(crate) disabled: Color,
+}
+
+impl Default for ColorSettings {
+    fn default() -> Self {
+        Self {
+            zoom_context: Color::LightBlue,
+            disabled: Color::Rgb(100, 100, 100),
+        }
+    }
+}
+
+/// Names given to interfaces and MAC addresses in the configuration file
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Aliases {
+    interfaces: HashMap<String, String>,
+    macs: HashMap<[u8; 6], String>,
+}
+
+impl Aliases {
+    pub(crate) fn interface(&self, interface_name: &str) -> Option<&str> {
+        self.interfaces.get(interface_name).map(String::as_str)
+    }
+
+    /// The alias of an interface, or its name if it has none
+    pub(crate) fn interface_or_name<'a>(&'a self, interface_name: &'a str) -> &'a str {
+        self.interface(interface_name).unwrap_or(interface_name)
+    }
+
+    pub(crate) fn mac(&self, mac: &[u8; 6]) -> Option<&str> {
+        self.macs.get(mac).map(String::as_str)
+    }
+}
+
+/// Parses a MAC address written as six colon-separated hex octets
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let mut octets = [0u8; 6];
+    let mut parts = mac.split(':');
+    for octet in octets.iter_mut() {
+        let part = parts.next()?;
+        if part.len() != 2 {
+            return None;
+        }
+        *octet = u8::from_str_radix(part, 16).ok()?;
+    }
+    parts.next().is_none().then_some(octets)
+}
+
+fn parse_interface_patterns(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| parse_interface_pattern(pattern).map_err(|error| eyre!(error)))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub(crate) tick_rate_ms: u64,
+    /// Number of ticks shown in the time series plots
+    pub(crate) window_ticks: u64,
+    /// Number of ticks kept for scrolling back through the time series plots
+    pub(crate) history_ticks: u64,
+    pub(crate) mac_idle_timeout_sec: u64,
+    /// Width of the time buckets used to detect microbursts
+    pub(crate) burst_bucket_ns: u64,
+    /// One in this many received frames is sampled to the Packets context, with 0 disabling
+    /// sampling
+    pub(crate) sample_rate: u32,
+    pub(crate) interface_filter: InterfaceFilter,
+    /// The contexts shown, in tab order
+    pub(crate) contexts: Vec<ContextKind>,
+    pub(crate) initial_context: ContextKind,
+    pub(crate) plots: PlotSettings,
+    pub(crate) colors: ColorSettings,
+    pub(crate) aliases: Aliases,
+    pub(crate) keymap: Keymap,
+}
+
+impl Settings {
+    /// Resolves the settings, with command-line options taking precedence over the
+    /// configuration file and the configuration file over the defaults
+    pub(crate) fn new(cli: &Cli, config: &Config) -> Result<Self> {
+        let interface_filter = InterfaceFilter {
+            include: if cli.include.is_empty() {
+                parse_interface_patterns(&config.interfaces)?
+            } else {
+                cli.include.clone()
+            },
+            exclude: if cli.exclude.is_empty() {
+                parse_interface_patterns(&config.exclude_interfaces)?
+            } else {
+                cli.exclude.clone()
+            },
+        };
+
+        let tick_rate_ms = cli
+            .tick_rate
+            .or(config.tick_rate_ms)
+            .unwrap_or(DEFAULT_TICK_RATE_MS);
+        let window_ticks = cli
+            .window
+            .or(config.window_ticks)
+            .unwrap_or(DEFAULT_WINDOW_TICKS);
+        if tick_rate_ms == 0 || window_ticks == 0 {
+            return Err(eyre!("The tick rate and the window have to be at least 1"));
+        }
+        let history_ticks = cli
+            .history
+            .or(config.history_ticks)
+            .unwrap_or(DEFAULT_HISTORY_TICKS);
+        if history_ticks < window_ticks {
+            return Err(eyre!(
+                "The history of {} ticks is shorter than the window of {} ticks",
+                history_ticks,
+                window_ticks
+            ));
+        }
+
+        let burst_bucket_us = cli
+            .burst_bucket
+            .or(config.burst_bucket_us)
+            .unwrap_or(DEFAULT_BURST_BUCKET_US);
+        if burst_bucket_us == 0 {
+            return Err(eyre!("The microburst bucket has to be at least 1 µs"));
+        }
+
+        let sample_rate = cli.sample_rate.or(config.sample_rate).unwrap_or(0);
+        if sample_rate == 0 && cli.pcapng.is_some() {
+            return Err(eyre!(
+                "Writing sampled frames to pcapng needs sampling, turned on with --sample-rate"
+            ));
+        }
+
+        let enabled_contexts = if cli.contexts.is_empty() {
+            &config.contexts
+        } else {
+            &cli.contexts
+        };
+        let mut contexts = ContextKind::ALL.to_vec();
+        if !enabled_contexts.is_empty() {
+            contexts.retain(|context_kind| enabled_contexts.contains(context_kind));
+        }
+        // The Packets context is what writes the sampled frames
+        if cli.pcapng.is_some() && !contexts.contains(&ContextKind::Packets) {
+            return Err(eyre!(
+                "Writing sampled frames to pcapng needs the {} context to be enabled",
+                ContextKind::Packets
+            ));
+        }
+        // The start context from the file is skipped over if --contexts leaves it out
+        let initial_context = cli
+            .start_context
+            .or(config
+                .start_context
+                .filter(|context_kind| contexts.contains(context_kind)))
+            .unwrap_or(contexts[0]);
+        if !contexts.contains(&initial_context) {
+            return Err(eyre!(
+                "The initial context {} is not one of the enabled contexts",
+                initial_context
+            ));
+        }
+
+        let default_plots = PlotSettings::default();
+        let plots = PlotSettings {
+            autoscaling: config
+                .plots
+                .autoscaling
+                .unwrap_or(default_plots.autoscaling),
+            packet_axis_max: config
+                .plots
+                .packet_axis_max
+                .unwrap_or(default_plots.packet_axis_max),
+            byte_axis_max: config
+                .plots
+                .byte_axis_max
+                .unwrap_or(default_plots.byte_axis_max),
+            histogram_width_percentage: config
+                .plots
+                .histogram_width_percentage
+                .unwrap_or(default_plots.histogram_width_percentage),
+            byte_counters_height_percentage: config
+                .plots
+                .byte_counters_height_percentage
+                .unwrap_or(default_plots.byte_counters_height_percentage),
+            multicast_groups_height_percentage: config
+                .plots
+                .multicast_groups_height_percentage
+                .unwrap_or(default_plots.multicast_groups_height_percentage),
+            hop_limit_height_percentage: config
+                .plots
+                .hop_limit_height_percentage
+                .unwrap_or(default_plots.hop_limit_height_percentage),
+        };
+        if plots.packet_axis_max <= 0.0 || plots.byte_axis_max <= 0.0 {
+            return Err(eyre!("The plot axis maximums have to be positive"));
+        }
+        if [
+            plots.histogram_width_percentage,
+            plots.byte_counters_height_percentage,
+            plots.multicast_groups_height_percentage,
+            plots.hop_limit_height_percentage,
+        ]
+        .iter()
+        .any(|percentage| *percentage > 100)
+        {
+            return Err(eyre!("The plot layout percentages have to be at most 100"));
+        }
+
+        let default_colors = ColorSettings::default();
+        let colors = ColorSettings {
+            zoom_context: config
+                .colors
+                .zoom_context
+                .unwrap_or(default_colors.zoom_context),
+            disabled: config.colors.disabled.unwrap_or(default_colors.disabled),
+        };
+
+        // Aliases keyed by something which reads as a MAC address are for that address
+        let mut aliases = Aliases::default();
+        for (name, alias) in &config.aliases {
+            match parse_mac(name) {
+                Some(mac) => aliases.macs.insert(mac, alias.clone()),
+                None => aliases.interfaces.insert(name.clone(), alias.clone()),
+            };
+        }
+
+        Ok(Self {
+            tick_rate_ms,
+            window_ticks,
+            history_ticks,
+            mac_idle_timeout_sec: cli
+                .mac_idle_timeout
+                .or(config.mac_idle_timeout_sec)
+                .unwrap_or(DEFAULT_MAC_IDLE_TIMEOUT_SEC),
+            burst_bucket_ns: burst_bucket_us * 1000,
+            sample_rate,
+            interface_filter,
+            contexts,
+            initial_context,
+            plots,
+            colors,
+            aliases,
+            keymap: Keymap::new(&config.keys)?,
+        })
+    }
+
+    /// Converts a duration in seconds to a number of ticks
+    pub(crate) fn ticks(&self, duration_sec: u64) -> f64 {
+        duration_sec as f64 * (1000.0 / self.tick_rate_ms as f64)
+    }
+
+    /// Resolves the settings from command-line options alone, as if there was no configuration
+    /// file. Panics if the options are invalid.
+    pub fn from_args(args: &[&str]) -> Self {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(std::iter::once("tsndt").chain(args.iter().copied()))
+            .expect("invalid command-line options");
+        Self::new(&cli, &Config::default()).expect("invalid settings")
+    }
+}
@@ -0,0 +1,138 @@
+// A data source supplies the counters which the contexts plot. Examples include:
+//  * The xdp_tsndt eBPF program attached to the host interfaces
+//  * Counters pinned to the BPF filesystem by another run
+//  * A saved capture replayed through the same counting logic in userspace
+//  * A session recorded by another run, played back tick by tick
+//  * Counters held in memory, which can be filled in without root or a kernel
+
+use color_eyre::eyre::{eyre, Result};
+use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+};
+
+use self::ebpf::XdpMode;
+use crate::{
+    keymap::{Action, Keymap},
+    settings::InterfaceFilter,
+};
+
+pub(crate) mod af_packet;
+pub(crate) mod capture_file;
+pub(crate) mod counting;
+pub(crate) mod ebpf;
+pub(crate) mod memory;
+pub(crate) mod netlink;
+pub(crate) mod per_cpu;
+pub(crate) mod pinned;
+pub(crate) mod recording;
+pub(crate) mod replay;
+pub(crate) mod session;
+
+/// Lists the host interfaces selected by the filter, ordered by index
+pub(crate) fn host_interfaces(interface_filter: &InterfaceFilter) -> Result<Vec<NetworkInterface>> {
+    let mut interfaces = NetworkInterface::show()?;
+    interfaces.retain(|interface| interface_filter.matches(&interface.name));
+    if interfaces.is_empty() {
+        return Err(eyre!(
+            "No host interface is selected by the interface filters"
+        ));
+    }
+    interfaces.sort_by(|a, b| a.index.partial_cmp(&b.index).unwrap());
+    Ok(interfaces)
+}
+
+/// Counter values are passed per CPU, like the per-CPU eBPF maps hold them. Sources which do not
+/// count per CPU pass a single value. The values are passed to a visitor rather than returned, so
+/// that sources can read them into buffers which are reused from tick to tick.
+pub trait DataSource {
+    /// The interfaces which counters can be collected on, ordered by index
+    fn interfaces(&self) -> &[NetworkInterface];
+
+    /// Updates the source at each tick. Returns false if no time has passed for the source, in
+    /// which case the contexts should not tick either.
+    fn on_tick(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn handle_action(&mut self, _action: Action) {}
+
+    fn get_command_help(&self, _keymap: &Keymap) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Name of the backend collecting on an interface, if the source has more than one kind
+    fn collection_backend(&self, _if_index: u32) -> Option<String> {
+        None
+    }
+
+    /// The XDP mode requested for an interface, for sources which attach XDP programs
+    fn xdp_mode(&self, _interface: &NetworkInterface) -> Option<XdpMode> {
+        None
+    }
+
+    fn set_xdp_mode(&mut self, _interface: &NetworkInterface, _xdp_mode: XdpMode) -> Result<()> {
+        Err(eyre!("The data source does not attach XDP programs"))
+    }
+
+    /// Starts collecting counters on an interface
+    fn attach(&mut self, interface: &NetworkInterface) -> Result<()>;
+
+    /// Stops collecting counters on an interface
+    fn detach(&mut self, interface: &NetworkInterface) -> Result<()>;
+
+    /// Calls `visit` with the counters of an interface, which it is an error not to have
+    fn interface_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()>;
+
+    fn rx_queue_counters(&self, visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()>;
+
+    /// Calls `visit` with the microburst counters of an interface, if it has any
+    fn burst_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[BurstCounter])) -> Result<()>;
+
+    /// Starts a new microburst read epoch, which resets the peaks
+    fn set_burst_epoch(&mut self, epoch: u64) -> Result<()>;
+
+    /// Calls `visit` with the inter-arrival histograms of an interface, if it has any
+    fn inter_arrival_histograms(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()>;
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()>;
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()>;
+
+    /// Calls `visit` with the IP fragment counters of an interface, if it has any
+    fn fragment_counters(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()>;
+
+    fn hop_limit_counts(&self, visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()>;
+
+    fn multicast_group_counters(
+        &self,
+        visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()>;
+
+    fn remove_multicast_group_counters(&mut self, key: &MulticastGroupKey) -> Result<()>;
+
+    fn multicast_members(
+        &self,
+        visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()>;
+
+    fn remove_multicast_member(&mut self, key: &MulticastMemberKey) -> Result<()>;
+
+    /// Calls `visit` with the packets sampled since the previous call, which are then dropped
+    fn packet_samples(&mut self, visit: &mut dyn FnMut(&PacketSample));
+
+    /// Current time on the clock that the source timestamps are taken from
+    fn now_ns(&self) -> u64;
+
+    /// Offset to add to source timestamps to get wall clock time
+    fn realtime_offset_ns(&self) -> u64;
+}
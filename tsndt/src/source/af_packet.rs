@@ -0,0 +1,458 @@
+// Userspace capture through an AF_PACKET socket with a TPACKET_V3 memory-mapped receive ring, for
+// interfaces which the XDP program cannot be attached to, or for all of them on kernels which
+// cannot load it. The ring is made of blocks which the kernel fills with frames and hands over to
+// userspace, see https://docs.kernel.org/networking/packet_mmap.html
+//
+// The ring headers are read at their byte offsets from the kernel UAPI (linux/if_packet.h), so
+// that this does not depend on the libc version in use declaring them.
+
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+    sync::atomic::{fence, Ordering},
+};
+
+use color_eyre::eyre::{eyre, Context, Result};
+use network_interface::NetworkInterface;
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+};
+
+use super::{
+    counting::CountingEngine, ebpf::clock_now_ns, host_interfaces, memory::MemorySource, DataSource,
+};
+use crate::settings::Settings;
+
+const TPACKET_V3: libc::c_int = 2;
+const TP_STATUS_KERNEL: u32 = 0;
+const TP_STATUS_USER: u32 = 1 << 0;
+const TP_STATUS_VLAN_VALID: u32 = 1 << 4;
+const TP_STATUS_VLAN_TPID_VALID: u32 = 1 << 6;
+const PACKET_OUTGOING: u8 = 4;
+
+// 4 blocks of 1MiB give each interface 4MiB of ring, and a block is handed over to userspace at
+// least every 50ms even if it is not full, which keeps up with the tick rate
+const BLOCK_SIZE: u32 = 1 << 20;
+const BLOCK_NR: u32 = 4;
+const FRAME_SIZE: u32 = 2048;
+const BLOCK_RETIRE_TIMEOUT_MS: u32 = 50;
+
+// struct tpacket_block_desc, where the block header follows the version and private data offset
+const BLOCK_STATUS_OFFSET: usize = 8;
+const BLOCK_NUM_PKTS_OFFSET: usize = 12;
+const BLOCK_OFFSET_TO_FIRST_PKT_OFFSET: usize = 16;
+
+// struct tpacket3_hdr
+const PKT_NEXT_OFFSET_OFFSET: usize = 0;
+const PKT_SEC_OFFSET: usize = 4;
+const PKT_NSEC_OFFSET: usize = 8;
+const PKT_SNAPLEN_OFFSET: usize = 12;
+const PKT_LEN_OFFSET: usize = 16;
+const PKT_STATUS_OFFSET: usize = 20;
+const PKT_MAC_OFFSET: usize = 24;
+const PKT_VLAN_TCI_OFFSET: usize = 32;
+const PKT_VLAN_TPID_OFFSET: usize = 36;
+// The struct sockaddr_ll which follows the header at TPACKET_ALIGN(sizeof(struct tpacket3_hdr))
+const PKT_SLL_PKTTYPE_OFFSET: usize = 48 + 10;
+
+const ETH_ADDRS_LEN: usize = 12;
+const ETH_P_8021Q: u16 = 0x8100;
+
+/// A frame received on the socket
+pub(crate) struct AfPacketFrame<'a> {
+    /// Wall clock time that the kernel received the frame at
+    pub(crate) timestamp_ns: u64,
+    pub(crate) data: &'a [u8],
+    pub(crate) packet_len: u32,
+}
+
+pub(crate) struct AfPacketCapture {
+    fd: OwnedFd,
+    ring: *mut u8,
+    ring_len: usize,
+    next_block: usize,
+    // Frames are rebuilt here when the kernel has moved their VLAN tag out of the data
+    frame_buffer: Vec<u8>,
+}
+
+fn check_ret(ret: libc::c_int, operation: &str) -> Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error()).wrap_err(operation.to_string())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn setsockopt<T>(fd: &OwnedFd, name: libc::c_int, value: &T, operation: &str) -> Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_PACKET,
+            name,
+            value as *const T as *const libc::c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    check_ret(ret, operation).map(|_| ())
+}
+
+fn read_u16(ring: *const u8, offset: usize) -> u16 {
+    unsafe { (ring.add(offset) as *const u16).read_unaligned() }
+}
+
+fn read_u32(ring: *const u8, offset: usize) -> u32 {
+    unsafe { (ring.add(offset) as *const u32).read_volatile() }
+}
+
+/// struct tpacket_req3
+#[repr(C)]
+struct TpacketReq3 {
+    tp_block_size: libc::c_uint,
+    tp_block_nr: libc::c_uint,
+    tp_frame_size: libc::c_uint,
+    tp_frame_nr: libc::c_uint,
+    tp_retire_blk_tov: libc::c_uint,
+    tp_sizeof_priv: libc::c_uint,
+    tp_feature_req_word: libc::c_uint,
+}
+
+impl AfPacketCapture {
+    /// Opens a socket receiving every frame on the interface with index `if_index`
+    pub(crate) fn open(if_index: u32) -> Result<Self> {
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        let raw_fd = check_ret(
+            unsafe {
+                libc::socket(
+                    libc::AF_PACKET,
+                    libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                    protocol as libc::c_int,
+                )
+            },
+            "failed to open an AF_PACKET socket",
+        )?;
+        let fd = unsafe { OwnedFd::from_raw_fd(raw_fd) };
+
+        setsockopt(
+            &fd,
+            libc::PACKET_VERSION,
+            &TPACKET_V3,
+            "failed to select TPACKET_V3",
+        )?;
+        let req = TpacketReq3 {
+            tp_block_size: BLOCK_SIZE,
+            tp_block_nr: BLOCK_NR,
+            tp_frame_size: FRAME_SIZE,
+            tp_frame_nr: BLOCK_SIZE / FRAME_SIZE * BLOCK_NR,
+            tp_retire_blk_tov: BLOCK_RETIRE_TIMEOUT_MS,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        setsockopt(
+            &fd,
+            libc::PACKET_RX_RING,
+            &req,
+            "failed to set up the receive ring",
+        )?;
+
+        let ring_len = (BLOCK_SIZE * BLOCK_NR) as usize;
+        let ring = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                ring_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if ring == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error()).wrap_err("failed to map the receive ring");
+        }
+        let capture = Self {
+            fd,
+            ring: ring as *mut u8,
+            ring_len,
+            next_block: 0,
+            frame_buffer: Vec::with_capacity(FRAME_SIZE as usize),
+        };
+
+        // Frames are only received once the socket is bound to the interface
+        let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = protocol;
+        addr.sll_ifindex = if_index as libc::c_int;
+        check_ret(
+            unsafe {
+                libc::bind(
+                    capture.fd.as_raw_fd(),
+                    &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+                )
+            },
+            "failed to bind the AF_PACKET socket to the interface",
+        )?;
+
+        Ok(capture)
+    }
+
+    /// Calls `on_frame` for every frame received since the previous poll, without blocking.
+    /// Frames sent from the host are skipped, since XDP only sees received frames.
+    pub(crate) fn poll(&mut self, mut on_frame: impl FnMut(AfPacketFrame)) {
+        loop {
+            let block = unsafe { self.ring.add(self.next_block * BLOCK_SIZE as usize) };
+            if read_u32(block, BLOCK_STATUS_OFFSET) & TP_STATUS_USER == 0 {
+                break;
+            }
+            // Frame data written by the kernel must not be read before the block status
+            fence(Ordering::Acquire);
+
+            let num_packets = read_u32(block, BLOCK_NUM_PKTS_OFFSET);
+            let mut offset = read_u32(block, BLOCK_OFFSET_TO_FIRST_PKT_OFFSET) as usize;
+            for _ in 0..num_packets {
+                if offset >= BLOCK_SIZE as usize {
+                    break;
+                }
+                let packet = unsafe { block.add(offset) };
+                let next_offset = read_u32(packet, PKT_NEXT_OFFSET_OFFSET) as usize;
+                let pkttype = unsafe { *packet.add(PKT_SLL_PKTTYPE_OFFSET) };
+                if pkttype != PACKET_OUTGOING {
+                    self.handle_packet(packet, offset, &mut on_frame);
+                }
+                if next_offset == 0 {
+                    break;
+                }
+                offset += next_offset;
+            }
+
+            // Give the block back to the kernel once done reading it
+            fence(Ordering::Release);
+            unsafe {
+                (block.add(BLOCK_STATUS_OFFSET) as *mut u32).write_volatile(TP_STATUS_KERNEL)
+            };
+            self.next_block = (self.next_block + 1) % BLOCK_NR as usize;
+        }
+    }
+
+    fn handle_packet(
+        &mut self,
+        packet: *const u8,
+        block_offset: usize,
+        on_frame: &mut impl FnMut(AfPacketFrame),
+    ) {
+        let timestamp_ns = read_u32(packet, PKT_SEC_OFFSET) as u64 * 1_000_000_000
+            + read_u32(packet, PKT_NSEC_OFFSET) as u64;
+        let snaplen = read_u32(packet, PKT_SNAPLEN_OFFSET) as usize;
+        let mut packet_len = read_u32(packet, PKT_LEN_OFFSET);
+        let status = read_u32(packet, PKT_STATUS_OFFSET);
+        let mac_offset = read_u16(packet, PKT_MAC_OFFSET) as usize;
+
+        // Stay within the block even if the header is corrupt
+        let snaplen = snaplen.min((BLOCK_SIZE as usize).saturating_sub(block_offset + mac_offset));
+        let data = unsafe { std::slice::from_raw_parts(packet.add(mac_offset), snaplen) };
+
+        // With VLAN offload the kernel strips the tag from the frame, so put it back to count the
+        // frame as it was on the wire
+        if status & TP_STATUS_VLAN_VALID != 0 && data.len() >= ETH_ADDRS_LEN {
+            let tci = read_u32(packet, PKT_VLAN_TCI_OFFSET) as u16;
+            let tpid = if status & TP_STATUS_VLAN_TPID_VALID != 0 {
+                read_u16(packet, PKT_VLAN_TPID_OFFSET)
+            } else {
+                ETH_P_8021Q
+            };
+            self.frame_buffer.clear();
+            self.frame_buffer.extend_from_slice(&data[..ETH_ADDRS_LEN]);
+            self.frame_buffer.extend_from_slice(&tpid.to_be_bytes());
+            self.frame_buffer.extend_from_slice(&tci.to_be_bytes());
+            self.frame_buffer.extend_from_slice(&data[ETH_ADDRS_LEN..]);
+            packet_len += 4;
+            on_frame(AfPacketFrame {
+                timestamp_ns,
+                data: &self.frame_buffer,
+                packet_len,
+            });
+        } else {
+            on_frame(AfPacketFrame {
+                timestamp_ns,
+                data,
+                packet_len,
+            });
+        }
+    }
+}
+
+impl Drop for AfPacketCapture {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ring as *mut libc::c_void, self.ring_len) };
+    }
+}
+
+/// Counts the frames captured on AF_PACKET sockets in userspace, with the same logic as the XDP
+/// program. Counters are on the monotonic clock, like the ones the XDP program keeps.
+pub(crate) struct AfPacketSource {
+    interfaces: Vec<NetworkInterface>,
+    captures: HashMap<u32, AfPacketCapture>,
+    counters: MemorySource,
+}
+
+impl AfPacketSource {
+    /// Creates a source which is not capturing on any of `interfaces` yet
+    pub(crate) fn new(interfaces: Vec<NetworkInterface>, engine: CountingEngine) -> Self {
+        Self {
+            interfaces,
+            captures: HashMap::new(),
+            counters: MemorySource::new(Vec::new(), engine),
+        }
+    }
+
+    /// Captures on all the selected host interfaces
+    pub(crate) fn open(settings: &Settings) -> Result<Self> {
+        let interfaces = host_interfaces(&settings.interface_filter)?;
+        let mut af_packet_source = Self::new(
+            interfaces.clone(),
+            CountingEngine::new(settings.burst_bucket_ns, settings.sample_rate),
+        );
+        for interface in &interfaces {
+            af_packet_source.attach(interface)?;
+        }
+        Ok(af_packet_source)
+    }
+
+    pub(crate) fn is_attached(&self, if_index: u32) -> bool {
+        self.captures.contains_key(&if_index)
+    }
+
+    /// The counters of the frames captured so far
+    pub(crate) fn engine(&self) -> &CountingEngine {
+        &self.counters.engine
+    }
+}
+
+impl DataSource for AfPacketSource {
+    fn interfaces(&self) -> &[NetworkInterface] {
+        &self.interfaces
+    }
+
+    /// Counts the frames received on the sockets since the previous tick
+    fn on_tick(&mut self) -> Result<bool> {
+        // Socket timestamps come from the wall clock
+        let realtime_offset_ns = self.realtime_offset_ns();
+        let engine = &mut self.counters.engine;
+        for (if_index, capture) in self.captures.iter_mut() {
+            capture.poll(|frame| {
+                engine.count_frame(
+                    *if_index,
+                    0,
+                    frame.timestamp_ns.saturating_sub(realtime_offset_ns),
+                    frame.data,
+                    frame.packet_len,
+                )
+            });
+        }
+        self.counters.set_now_ns(self.now_ns());
+        Ok(true)
+    }
+
+    fn collection_backend(&self, if_index: u32) -> Option<String> {
+        self.is_attached(if_index)
+            .then(|| String::from("AF_PACKET"))
+    }
+
+    fn attach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        let capture = AfPacketCapture::open(interface.index)
+            .wrap_err_with(|| format!("failed to capture on {} with AF_PACKET", interface.name))?;
+        self.counters.attach(interface)?;
+        self.captures.insert(interface.index, capture);
+        Ok(())
+    }
+
+    fn detach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        if self.captures.remove(&interface.index).is_none() {
+            return Err(eyre!(
+                "Not capturing on interface with index {}",
+                interface.index
+            ));
+        }
+        self.counters.detach(interface)
+    }
+
+    fn interface_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()> {
+        self.counters.interface_counters(if_index, visit)
+    }
+
+    fn rx_queue_counters(&self, visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()> {
+        self.counters.rx_queue_counters(visit)
+    }
+
+    fn burst_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[BurstCounter])) -> Result<()> {
+        self.counters.burst_counters(if_index, visit)
+    }
+
+    fn set_burst_epoch(&mut self, epoch: u64) -> Result<()> {
+        self.counters.set_burst_epoch(epoch)
+    }
+
+    fn inter_arrival_histograms(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()> {
+        self.counters.inter_arrival_histograms(if_index, visit)
+    }
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()> {
+        self.counters.mac_counters(visit)
+    }
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()> {
+        self.counters.remove_mac_counters(src_mac)
+    }
+
+    fn fragment_counters(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()> {
+        self.counters.fragment_counters(if_index, visit)
+    }
+
+    fn hop_limit_counts(&self, visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()> {
+        self.counters.hop_limit_counts(visit)
+    }
+
+    fn multicast_group_counters(
+        &self,
+        visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()> {
+        self.counters.multicast_group_counters(visit)
+    }
+
+    fn remove_multicast_group_counters(&mut self, key: &MulticastGroupKey) -> Result<()> {
+        self.counters.remove_multicast_group_counters(key)
+    }
+
+    fn multicast_members(
+        &self,
+        visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()> {
+        self.counters.multicast_members(visit)
+    }
+
+    fn remove_multicast_member(&mut self, key: &MulticastMemberKey) -> Result<()> {
+        self.counters.remove_multicast_member(key)
+    }
+
+    fn packet_samples(&mut self, visit: &mut dyn FnMut(&PacketSample)) {
+        self.counters.packet_samples(visit)
+    }
+
+    fn now_ns(&self) -> u64 {
+        clock_now_ns(libc::CLOCK_MONOTONIC)
+    }
+
+    fn realtime_offset_ns(&self) -> u64 {
+        clock_now_ns(libc::CLOCK_REALTIME) - clock_now_ns(libc::CLOCK_MONOTONIC)
+    }
+}
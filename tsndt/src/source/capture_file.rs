@@ -0,0 +1,391 @@
+// Streaming reader for pcap and pcapng capture files. Only the blocks needed to replay packets are
+// interpreted, everything else is skipped.
+
+use std::{
+    fs::File,
+    io::{BufReader, ErrorKind, Read},
+    path::Path,
+};
+
+use color_eyre::eyre::{eyre, Context, Result};
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+const PCAP_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+const SECTION_HEADER_BLOCK_TYPE: u32 = 0x0a0d0d0a;
+const INTERFACE_DESCRIPTION_BLOCK_TYPE: u32 = 0x00000001;
+const PACKET_BLOCK_TYPE: u32 = 0x00000002;
+const SIMPLE_PACKET_BLOCK_TYPE: u32 = 0x00000003;
+const ENHANCED_PACKET_BLOCK_TYPE: u32 = 0x00000006;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const OPT_ENDOFOPT: u16 = 0;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+// pcapng timestamps are in microseconds unless an interface says otherwise
+const DEFAULT_TSRESOL: u8 = 6;
+
+pub(crate) const LINKTYPE_ETHERNET: u16 = 1;
+
+/// An interface that packets in a capture were recorded on
+pub(crate) struct CaptureInterface {
+    pub(crate) name: Option<String>,
+    pub(crate) link_type: u16,
+    tsresol: u8,
+}
+
+pub(crate) struct CapturedPacket {
+    /// Position of the capture interface in [`CaptureReader::interfaces`]
+    pub(crate) interface_id: usize,
+    /// Wall clock time since the Unix epoch
+    pub(crate) timestamp_ns: u64,
+    pub(crate) data: Vec<u8>,
+    pub(crate) original_len: u32,
+}
+
+enum CaptureFormat {
+    Pcap { big_endian: bool, nanos: bool },
+    Pcapng { big_endian: bool },
+}
+
+pub(crate) struct CaptureReader {
+    reader: BufReader<File>,
+    format: CaptureFormat,
+    interfaces: Vec<CaptureInterface>,
+    // Interface IDs in pcapng blocks are relative to the section they appear in
+    section_interface_base: usize,
+    last_timestamp_ns: u64,
+}
+
+fn read_u16(bytes: &[u8], offset: usize, big_endian: bool) -> Result<u16> {
+    let raw: [u8; 2] = bytes
+        .get(offset..offset + 2)
+        .and_then(|raw| raw.try_into().ok())
+        .ok_or_else(|| eyre!("Capture block is truncated"))?;
+    Ok(if big_endian {
+        u16::from_be_bytes(raw)
+    } else {
+        u16::from_le_bytes(raw)
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Result<u32> {
+    let raw: [u8; 4] = bytes
+        .get(offset..offset + 4)
+        .and_then(|raw| raw.try_into().ok())
+        .ok_or_else(|| eyre!("Capture block is truncated"))?;
+    Ok(if big_endian {
+        u32::from_be_bytes(raw)
+    } else {
+        u32::from_le_bytes(raw)
+    })
+}
+
+/// Converts a timestamp in units of an if_tsresol option value to nanoseconds
+fn tsresol_to_ns(timestamp: u64, tsresol: u8) -> u64 {
+    let exponent = (tsresol & 0x7f) as u32;
+    let timestamp = timestamp as u128;
+    let timestamp_ns = if tsresol & 0x80 != 0 {
+        (timestamp * 1_000_000_000) >> exponent
+    } else if exponent <= 9 {
+        timestamp * 10u128.pow(9 - exponent)
+    } else {
+        timestamp / 10u128.pow(exponent - 9)
+    };
+    timestamp_ns as u64
+}
+
+impl CaptureReader {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .wrap_err_with(|| format!("Failed to open capture file {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .wrap_err("Capture file is too short")?;
+
+        if u32::from_le_bytes(magic) == SECTION_HEADER_BLOCK_TYPE {
+            let mut capture_reader = Self {
+                reader,
+                format: CaptureFormat::Pcapng { big_endian: false },
+                interfaces: Vec::new(),
+                section_interface_base: 0,
+                last_timestamp_ns: 0,
+            };
+            capture_reader.read_section_header()?;
+            return Ok(capture_reader);
+        }
+
+        let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC_MICROS, _) => (false, false),
+            (PCAP_MAGIC_NANOS, _) => (false, true),
+            (_, PCAP_MAGIC_MICROS) => (true, false),
+            (_, PCAP_MAGIC_NANOS) => (true, true),
+            _ => return Err(eyre!("{} is not a pcap or pcapng file", path.display())),
+        };
+
+        let mut header = [0; PCAP_HEADER_LEN - 4];
+        reader
+            .read_exact(&mut header)
+            .wrap_err("pcap file header is truncated")?;
+        // The link type shares its field with FCS information in the upper bits
+        let link_type = (read_u32(&header, 16, big_endian)? & 0xffff) as u16;
+
+        Ok(Self {
+            reader,
+            format: CaptureFormat::Pcap { big_endian, nanos },
+            interfaces: vec![CaptureInterface {
+                name: None,
+                link_type,
+                tsresol: if nanos { 9 } else { 6 },
+            }],
+            section_interface_base: 0,
+            last_timestamp_ns: 0,
+        })
+    }
+
+    /// Interfaces described so far. For pcapng files, this grows as description blocks are read.
+    pub(crate) fn interfaces(&self) -> &[CaptureInterface] {
+        &self.interfaces
+    }
+
+    /// Reads the next packet, or returns `None` at the end of the file
+    pub(crate) fn next_packet(&mut self) -> Result<Option<CapturedPacket>> {
+        match self.format {
+            CaptureFormat::Pcap { big_endian, nanos } => self.next_pcap_packet(big_endian, nanos),
+            CaptureFormat::Pcapng { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    /// Fills `buf` from the file, returning false if the file ended before any bytes were read
+    fn read_or_eof(&mut self, buf: &mut [u8]) -> Result<bool> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn next_pcap_packet(
+        &mut self,
+        big_endian: bool,
+        nanos: bool,
+    ) -> Result<Option<CapturedPacket>> {
+        let mut record_header = [0; PCAP_RECORD_HEADER_LEN];
+        if !self.read_or_eof(&mut record_header)? {
+            return Ok(None);
+        }
+
+        let ts_sec = read_u32(&record_header, 0, big_endian)? as u64;
+        let ts_frac = read_u32(&record_header, 4, big_endian)? as u64;
+        let captured_len = read_u32(&record_header, 8, big_endian)?;
+        let original_len = read_u32(&record_header, 12, big_endian)?;
+
+        let mut data = vec![0; captured_len as usize];
+        self.reader
+            .read_exact(&mut data)
+            .wrap_err("pcap packet record is truncated")?;
+
+        let timestamp_ns = if nanos {
+            ts_sec * 1_000_000_000 + ts_frac
+        } else {
+            ts_sec * 1_000_000_000 + ts_frac * 1000
+        };
+
+        Ok(Some(CapturedPacket {
+            interface_id: 0,
+            timestamp_ns,
+            data,
+            original_len,
+        }))
+    }
+
+    fn pcapng_big_endian(&self) -> bool {
+        matches!(self.format, CaptureFormat::Pcapng { big_endian: true })
+    }
+
+    /// Reads the rest of a section header block whose type has already been read, which sets the
+    /// byte order of the blocks that follow
+    fn read_section_header(&mut self) -> Result<()> {
+        let mut len_and_magic = [0; 8];
+        self.reader
+            .read_exact(&mut len_and_magic)
+            .wrap_err("pcapng section header is truncated")?;
+        let big_endian = match read_u32(&len_and_magic, 4, false)? {
+            BYTE_ORDER_MAGIC => false,
+            magic if magic.swap_bytes() == BYTE_ORDER_MAGIC => true,
+            _ => {
+                return Err(eyre!(
+                    "pcapng section header has an invalid byte order magic"
+                ))
+            }
+        };
+        self.format = CaptureFormat::Pcapng { big_endian };
+        self.section_interface_base = self.interfaces.len();
+
+        let block_len = read_u32(&len_and_magic, 0, big_endian)? as usize;
+        let mut rest = vec![0; block_len.saturating_sub(12)];
+        self.reader
+            .read_exact(&mut rest)
+            .wrap_err("pcapng section header is truncated")?;
+        Ok(())
+    }
+
+    fn next_pcapng_packet(&mut self) -> Result<Option<CapturedPacket>> {
+        loop {
+            let mut block_type = [0; 4];
+            if !self.read_or_eof(&mut block_type)? {
+                return Ok(None);
+            }
+            let big_endian = self.pcapng_big_endian();
+            let block_type = read_u32(&block_type, 0, big_endian)?;
+            if block_type == SECTION_HEADER_BLOCK_TYPE {
+                self.read_section_header()?;
+                continue;
+            }
+
+            let mut block_len = [0; 4];
+            self.reader
+                .read_exact(&mut block_len)
+                .wrap_err("pcapng block is truncated")?;
+            let block_len = read_u32(&block_len, 0, big_endian)? as usize;
+            if block_len < 12 || !block_len.is_multiple_of(4) {
+                return Err(eyre!("pcapng block has an invalid length of {}", block_len));
+            }
+
+            // The body is followed by a second copy of the block length
+            let mut body = vec![0; block_len - 8];
+            self.reader
+                .read_exact(&mut body)
+                .wrap_err("pcapng block is truncated")?;
+            body.truncate(block_len - 12);
+
+            match block_type {
+                INTERFACE_DESCRIPTION_BLOCK_TYPE => self.read_interface_description(&body)?,
+                ENHANCED_PACKET_BLOCK_TYPE => {
+                    let interface_id = read_u32(&body, 0, big_endian)? as usize;
+                    let timestamp = ((read_u32(&body, 4, big_endian)? as u64) << 32)
+                        | read_u32(&body, 8, big_endian)? as u64;
+                    let captured_len = read_u32(&body, 12, big_endian)? as usize;
+                    let original_len = read_u32(&body, 16, big_endian)?;
+                    return self
+                        .pcapng_packet(
+                            interface_id,
+                            timestamp,
+                            &body,
+                            20,
+                            captured_len,
+                            original_len,
+                        )
+                        .map(Some);
+                }
+                PACKET_BLOCK_TYPE => {
+                    let interface_id = read_u16(&body, 0, big_endian)? as usize;
+                    let timestamp = ((read_u32(&body, 4, big_endian)? as u64) << 32)
+                        | read_u32(&body, 8, big_endian)? as u64;
+                    let captured_len = read_u32(&body, 12, big_endian)? as usize;
+                    let original_len = read_u32(&body, 16, big_endian)?;
+                    return self
+                        .pcapng_packet(
+                            interface_id,
+                            timestamp,
+                            &body,
+                            20,
+                            captured_len,
+                            original_len,
+                        )
+                        .map(Some);
+                }
+                SIMPLE_PACKET_BLOCK_TYPE => {
+                    // Simple packet blocks have no timestamp, so they are placed right after the
+                    // previous packet
+                    let original_len = read_u32(&body, 0, big_endian)?;
+                    let captured_len = (original_len as usize).min(body.len() - 4);
+                    let interface_id = self.section_interface_base;
+                    return Ok(Some(CapturedPacket {
+                        interface_id,
+                        timestamp_ns: self.last_timestamp_ns,
+                        data: body[4..4 + captured_len].to_vec(),
+                        original_len,
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn pcapng_packet(
+        &mut self,
+        section_interface_id: usize,
+        timestamp: u64,
+        body: &[u8],
+        data_offset: usize,
+        captured_len: usize,
+        original_len: u32,
+    ) -> Result<CapturedPacket> {
+        let interface_id = self.section_interface_base + section_interface_id;
+        let interface = self.interfaces.get(interface_id).ok_or_else(|| {
+            eyre!(
+                "pcapng packet refers to undescribed interface {}",
+                section_interface_id
+            )
+        })?;
+        let data = body
+            .get(data_offset..data_offset + captured_len)
+            .ok_or_else(|| eyre!("pcapng packet data is truncated"))?
+            .to_vec();
+
+        let timestamp_ns = tsresol_to_ns(timestamp, interface.tsresol);
+        self.last_timestamp_ns = timestamp_ns;
+
+        Ok(CapturedPacket {
+            interface_id,
+            timestamp_ns,
+            data,
+            original_len,
+        })
+    }
+
+    fn read_interface_description(&mut self, body: &[u8]) -> Result<()> {
+        let big_endian = self.pcapng_big_endian();
+        let link_type = read_u16(body, 0, big_endian)?;
+        let mut interface = CaptureInterface {
+            name: None,
+            link_type,
+            tsresol: DEFAULT_TSRESOL,
+        };
+
+        // Options start after the link type, reserved field and snap length
+        let mut offset = 8;
+        while offset + 4 <= body.len() {
+            let code = read_u16(body, offset, big_endian)?;
+            let len = read_u16(body, offset + 2, big_endian)? as usize;
+            let value = body
+                .get(offset + 4..offset + 4 + len)
+                .ok_or_else(|| eyre!("pcapng interface option is truncated"))?;
+            match code {
+                OPT_ENDOFOPT => break,
+                IF_NAME => {
+                    interface.name = Some(
+                        String::from_utf8_lossy(value)
+                            .trim_end_matches('\0')
+                            .to_string(),
+                    );
+                }
+                IF_TSRESOL => {
+                    if let Some(tsresol) = value.first() {
+                        interface.tsresol = *tsresol;
+                    }
+                }
+                _ => {}
+            }
+            offset += 4 + len.div_ceil(4) * 4;
+        }
+
+        self.interfaces.push(interface);
+        Ok(())
+    }
+}
@@ -0,0 +1,785 @@
+// Userspace port of the per-frame accounting done by the xdp_tsndt eBPF program, for data sources
+// which see frames without running it. The tables mirror the eBPF maps, with a single entry
+// standing in for the per-CPU values.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use tsndt_common::{
+    BurstCounter, Counter, InterArrivalHistogram, IpFragmentCounter, MulticastMembership,
+    PacketSample, INTER_ARRIVAL_NUM_BUCKETS, MULTICAST_MEMBERSHIP_JOINED,
+    MULTICAST_MEMBERSHIP_LEFT, MULTICAST_REPORT_IGMP_V1, MULTICAST_REPORT_IGMP_V2,
+    MULTICAST_REPORT_IGMP_V3, MULTICAST_REPORT_MLD_V1, MULTICAST_REPORT_MLD_V2,
+    PACKET_SAMPLE_MAX_LEN,
+};
+
+// Sampled packets which the Packets context has not picked up yet are dropped past this point,
+// like a full ring buffer would
+const MAX_NUM_PENDING_SAMPLES: usize = 4096;
+const MAX_MULTICAST_GROUP_RECORDS: usize = 8;
+const MAX_IPV6_EXT_HEADERS: usize = 4;
+
+const ETH_HDR_LEN: usize = 14;
+const VLAN_HDR_LEN: usize = 4;
+const ETH_P_IPV4: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+const ETH_P_8021Q: u16 = 0x8100;
+const ETH_P_8021AD: u16 = 0x88a8;
+const IPV6_HDR_LEN: usize = 40;
+const IPV4_MORE_FRAGMENTS: u16 = 0x2000;
+const IPV4_FRAGMENT_OFFSET_MASK: u16 = 0x1fff;
+const IPPROTO_HOPOPTS: u8 = 0;
+const IPPROTO_IGMP: u8 = 2;
+const IPPROTO_ROUTING: u8 = 43;
+const IPPROTO_FRAGMENT: u8 = 44;
+const IPPROTO_ICMPV6: u8 = 58;
+const IPPROTO_DSTOPTS: u8 = 60;
+
+const IGMP_V1_MEMBERSHIP_REPORT: u8 = 0x12;
+const IGMP_V2_MEMBERSHIP_REPORT: u8 = 0x16;
+const IGMP_V2_LEAVE_GROUP: u8 = 0x17;
+const IGMP_V3_MEMBERSHIP_REPORT: u8 = 0x22;
+const MLD_V1_LISTENER_REPORT: u8 = 131;
+const MLD_V1_LISTENER_DONE: u8 = 132;
+const MLD_V2_LISTENER_REPORT: u8 = 143;
+
+const GROUP_RECORD_MODE_IS_INCLUDE: u8 = 1;
+const GROUP_RECORD_CHANGE_TO_INCLUDE: u8 = 3;
+
+pub(crate) type RxQueueId = (u32, u32);
+pub(crate) type GroupId = (u32, [u8; 16]);
+pub(crate) type MemberId = (u32, [u8; 16], [u8; 16]);
+pub(crate) type HopLimitId = (u32, u32);
+
+pub(crate) struct CountingEngine {
+    burst_bucket_ns: u64,
+    sample_rate: u32,
+    frames_since_sample: u32,
+    attached: HashSet<u32>,
+    last_rx_ns: HashMap<u32, u64>,
+    pub(crate) burst_epoch: u64,
+    pub(crate) interface_counters: HashMap<u32, Counter>,
+    pub(crate) rx_queue_counters: HashMap<RxQueueId, Counter>,
+    pub(crate) burst_counters: HashMap<u32, BurstCounter>,
+    pub(crate) inter_arrival_histograms: HashMap<u32, InterArrivalHistogram>,
+    pub(crate) mac_counters: HashMap<[u8; 6], Counter>,
+    pub(crate) fragment_counters: HashMap<u32, IpFragmentCounter>,
+    pub(crate) hop_limit_counts: HashMap<HopLimitId, u64>,
+    pub(crate) multicast_group_counters: HashMap<GroupId, Counter>,
+    pub(crate) multicast_members: HashMap<MemberId, MulticastMembership>,
+    pub(crate) packet_samples: VecDeque<PacketSample>,
+}
+
+fn bytes_at<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset + N)?.try_into().ok()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    bytes_at::<2>(data, offset).map(u16::from_be_bytes)
+}
+
+fn ipv4_mapped(addr: [u8; 4]) -> [u8; 16] {
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, addr[0], addr[1], addr[2], addr[3],
+    ]
+}
+
+fn log2_bucket(gap_ns: u64) -> usize {
+    if gap_ns == 0 {
+        return 0;
+    }
+    let bucket = (u64::BITS - gap_ns.leading_zeros()) as usize;
+    bucket.min(INTER_ARRIVAL_NUM_BUCKETS - 1)
+}
+
+fn group_record_state(record_type: u8, num_sources: u16) -> u32 {
+    if (record_type == GROUP_RECORD_MODE_IS_INCLUDE
+        || record_type == GROUP_RECORD_CHANGE_TO_INCLUDE)
+        && num_sources == 0
+    {
+        MULTICAST_MEMBERSHIP_LEFT
+    } else {
+        MULTICAST_MEMBERSHIP_JOINED
+    }
+}
+
+fn add_to_counter(counter: &mut Counter, packet_byte_count: u64) {
+    counter.packets += 1;
+    counter.bytes += packet_byte_count;
+}
+
+impl CountingEngine {
+    pub(crate) fn new(burst_bucket_ns: u64, sample_rate: u32) -> Self {
+        Self {
+            burst_bucket_ns,
+            sample_rate,
+            frames_since_sample: 0,
+            attached: HashSet::new(),
+            last_rx_ns: HashMap::new(),
+            burst_epoch: 0,
+            interface_counters: HashMap::new(),
+            rx_queue_counters: HashMap::new(),
+            burst_counters: HashMap::new(),
+            inter_arrival_histograms: HashMap::new(),
+            mac_counters: HashMap::new(),
+            fragment_counters: HashMap::new(),
+            hop_limit_counts: HashMap::new(),
+            multicast_group_counters: HashMap::new(),
+            multicast_members: HashMap::new(),
+            packet_samples: VecDeque::new(),
+        }
+    }
+
+    /// Starts counting frames received on an interface, like attaching the XDP program does
+    pub(crate) fn attach(&mut self, if_index: u32) {
+        self.attached.insert(if_index);
+        self.interface_counters.entry(if_index).or_insert(Counter {
+            bytes: 0,
+            packets: 0,
+        });
+    }
+
+    pub(crate) fn detach(&mut self, if_index: u32) {
+        self.attached.remove(&if_index);
+    }
+
+    pub(crate) fn is_attached(&self, if_index: u32) -> bool {
+        self.attached.contains(&if_index)
+    }
+
+    /// Accounts for a received frame. `data` holds the captured bytes and `packet_len` the
+    /// length of the frame on the wire.
+    pub(crate) fn count_frame(
+        &mut self,
+        if_index: u32,
+        rx_queue: u32,
+        timestamp_ns: u64,
+        data: &[u8],
+        packet_len: u32,
+    ) {
+        if !self.attached.contains(&if_index) {
+            return;
+        }
+        let packet_byte_count = packet_len as u64;
+
+        add_to_counter(
+            self.interface_counters.entry(if_index).or_insert(Counter {
+                bytes: 0,
+                packets: 0,
+            }),
+            packet_byte_count,
+        );
+        self.track_burst(if_index, timestamp_ns, packet_byte_count);
+        self.track_inter_arrival(if_index, timestamp_ns);
+        self.sample_packet(if_index, timestamp_ns, data, packet_len);
+        add_to_counter(
+            self.rx_queue_counters
+                .entry((if_index, rx_queue))
+                .or_insert(Counter {
+                    bytes: 0,
+                    packets: 0,
+                }),
+            packet_byte_count,
+        );
+
+        let Some(src_mac) = bytes_at::<6>(data, 6) else {
+            return;
+        };
+        add_to_counter(
+            self.mac_counters.entry(src_mac).or_insert(Counter {
+                bytes: 0,
+                packets: 0,
+            }),
+            packet_byte_count,
+        );
+
+        self.count_network_layer(if_index, timestamp_ns, data, packet_byte_count);
+    }
+
+    fn track_burst(&mut self, if_index: u32, timestamp_ns: u64, packet_byte_count: u64) {
+        if self.burst_bucket_ns == 0 {
+            return;
+        }
+
+        let epoch = self.burst_epoch;
+        let bucket_start_ns = timestamp_ns - (timestamp_ns % self.burst_bucket_ns);
+        let counter = self.burst_counters.entry(if_index).or_insert(BurstCounter {
+            epoch,
+            bucket_start_ns,
+            ..Default::default()
+        });
+
+        if counter.epoch != epoch {
+            counter.epoch = epoch;
+            counter.peak_packets = 0;
+            counter.peak_bytes = 0;
+        }
+
+        if counter.bucket_start_ns != bucket_start_ns {
+            counter.bucket_start_ns = bucket_start_ns;
+            counter.bucket_packets = 0;
+            counter.bucket_bytes = 0;
+        }
+
+        counter.bucket_packets += 1;
+        counter.bucket_bytes += packet_byte_count;
+        counter.peak_packets = counter.peak_packets.max(counter.bucket_packets);
+        counter.peak_bytes = counter.peak_bytes.max(counter.bucket_bytes);
+    }
+
+    fn track_inter_arrival(&mut self, if_index: u32, timestamp_ns: u64) {
+        let Some(prev_ns) = self.last_rx_ns.insert(if_index, timestamp_ns) else {
+            return;
+        };
+
+        let histogram = self.inter_arrival_histograms.entry(if_index).or_default();
+        histogram.buckets[log2_bucket(timestamp_ns.saturating_sub(prev_ns))] += 1;
+    }
+
+    /// Samples every `sample_rate`-th frame, since a replay has no need for the randomness the
+    /// eBPF program uses to avoid aliasing with periodic traffic
+    fn sample_packet(&mut self, if_index: u32, timestamp_ns: u64, data: &[u8], packet_len: u32) {
+        if self.sample_rate == 0 {
+            return;
+        }
+        self.frames_since_sample += 1;
+        if self.frames_since_sample < self.sample_rate {
+            return;
+        }
+        self.frames_since_sample = 0;
+
+        let captured_len = data.len().min(PACKET_SAMPLE_MAX_LEN);
+        let mut sample = PacketSample {
+            timestamp_ns,
+            if_index,
+            packet_len,
+            captured_len: captured_len as u32,
+            data: [0; PACKET_SAMPLE_MAX_LEN],
+        };
+        sample.data[..captured_len].copy_from_slice(&data[..captured_len]);
+
+        if self.packet_samples.len() < MAX_NUM_PENDING_SAMPLES {
+            self.packet_samples.push_back(sample);
+        }
+    }
+
+    fn count_network_layer(
+        &mut self,
+        if_index: u32,
+        timestamp_ns: u64,
+        data: &[u8],
+        packet_byte_count: u64,
+    ) {
+        let Some(ether_type) = u16_at(data, 12) else {
+            return;
+        };
+        let (ether_type, offset) = if ether_type == ETH_P_8021Q || ether_type == ETH_P_8021AD {
+            match u16_at(data, 16) {
+                Some(inner_ether_type) => (inner_ether_type, ETH_HDR_LEN + VLAN_HDR_LEN),
+                None => return,
+            }
+        } else {
+            (ether_type, ETH_HDR_LEN)
+        };
+
+        match ether_type {
+            ETH_P_IPV4 => {
+                self.count_ipv4_diagnostics(if_index, data, offset);
+                self.count_ipv4_multicast(if_index, timestamp_ns, data, offset, packet_byte_count);
+            }
+            ETH_P_IPV6 => {
+                self.count_ipv6_diagnostics(if_index, data, offset);
+                self.count_ipv6_multicast(if_index, timestamp_ns, data, offset, packet_byte_count);
+            }
+            _ => {}
+        }
+    }
+
+    fn count_ipv4_diagnostics(&mut self, if_index: u32, data: &[u8], offset: usize) -> Option<()> {
+        let flags_fragment_offset = u16_at(data, offset + 6)?;
+        let ttl = *data.get(offset + 8)?;
+
+        *self
+            .hop_limit_counts
+            .entry((if_index, ttl as u32))
+            .or_insert(0) += 1;
+
+        let more_fragments = flags_fragment_offset & IPV4_MORE_FRAGMENTS != 0;
+        let fragment_offset = flags_fragment_offset & IPV4_FRAGMENT_OFFSET_MASK;
+        if !more_fragments && fragment_offset == 0 {
+            return Some(());
+        }
+
+        let counter = self.fragment_counters.entry(if_index).or_default();
+        if fragment_offset == 0 {
+            counter.ipv4_first += 1;
+        } else if more_fragments {
+            counter.ipv4_middle += 1;
+        } else {
+            counter.ipv4_last += 1;
+        }
+
+        Some(())
+    }
+
+    fn count_ipv6_diagnostics(&mut self, if_index: u32, data: &[u8], offset: usize) -> Option<()> {
+        let mut next_header = *data.get(offset + 6)?;
+        let hop_limit = *data.get(offset + 7)?;
+
+        *self
+            .hop_limit_counts
+            .entry((if_index, hop_limit as u32))
+            .or_insert(0) += 1;
+
+        let mut ext_offset = offset + IPV6_HDR_LEN;
+        for _ in 0..MAX_IPV6_EXT_HEADERS {
+            match next_header {
+                IPPROTO_FRAGMENT => {
+                    self.fragment_counters.entry(if_index).or_default().ipv6 += 1;
+                    return Some(());
+                }
+                IPPROTO_HOPOPTS | IPPROTO_ROUTING | IPPROTO_DSTOPTS => {
+                    next_header = *data.get(ext_offset)?;
+                    let ext_header_len = *data.get(ext_offset + 1)? as usize;
+                    ext_offset += (ext_header_len + 1) * 8;
+                }
+                _ => return Some(()),
+            }
+        }
+
+        Some(())
+    }
+
+    fn count_ipv4_multicast(
+        &mut self,
+        if_index: u32,
+        timestamp_ns: u64,
+        data: &[u8],
+        offset: usize,
+        packet_byte_count: u64,
+    ) -> Option<()> {
+        let header_len = ((*data.get(offset)? & 0x0f) as usize) * 4;
+        let protocol = *data.get(offset + 9)?;
+        let src_addr = bytes_at::<4>(data, offset + 12)?;
+        let dst_addr = bytes_at::<4>(data, offset + 16)?;
+
+        // 224.0.0.0/4
+        if dst_addr[0] & 0xf0 != 0xe0 {
+            return Some(());
+        }
+
+        self.count_group_traffic(if_index, ipv4_mapped(dst_addr), packet_byte_count);
+
+        if protocol != IPPROTO_IGMP {
+            return Some(());
+        }
+
+        let reporter = ipv4_mapped(src_addr);
+        let igmp_offset = offset + header_len;
+        let igmp_type = *data.get(igmp_offset)?;
+        match igmp_type {
+            IGMP_V1_MEMBERSHIP_REPORT | IGMP_V2_MEMBERSHIP_REPORT | IGMP_V2_LEAVE_GROUP => {
+                let group = bytes_at::<4>(data, igmp_offset + 4)?;
+                let (report_kind, state) = match igmp_type {
+                    IGMP_V1_MEMBERSHIP_REPORT => {
+                        (MULTICAST_REPORT_IGMP_V1, MULTICAST_MEMBERSHIP_JOINED)
+                    }
+                    IGMP_V2_MEMBERSHIP_REPORT => {
+                        (MULTICAST_REPORT_IGMP_V2, MULTICAST_MEMBERSHIP_JOINED)
+                    }
+                    _ => (MULTICAST_REPORT_IGMP_V2, MULTICAST_MEMBERSHIP_LEFT),
+                };
+                self.record_membership(
+                    (if_index, ipv4_mapped(group), reporter),
+                    timestamp_ns,
+                    report_kind,
+                    state,
+                );
+            }
+            IGMP_V3_MEMBERSHIP_REPORT => {
+                let num_records = u16_at(data, igmp_offset + 6)? as usize;
+                let mut record_offset = igmp_offset + 8;
+                for _ in 0..num_records.min(MAX_MULTICAST_GROUP_RECORDS) {
+                    let record_type = *data.get(record_offset)?;
+                    let aux_data_len = *data.get(record_offset + 1)? as usize;
+                    let num_sources = u16_at(data, record_offset + 2)?;
+                    let group = bytes_at::<4>(data, record_offset + 4)?;
+                    self.record_membership(
+                        (if_index, ipv4_mapped(group), reporter),
+                        timestamp_ns,
+                        MULTICAST_REPORT_IGMP_V3,
+                        group_record_state(record_type, num_sources),
+                    );
+                    record_offset += 8 + (num_sources as usize) * 4 + aux_data_len * 4;
+                }
+            }
+            _ => {}
+        }
+
+        Some(())
+    }
+
+    fn count_ipv6_multicast(
+        &mut self,
+        if_index: u32,
+        timestamp_ns: u64,
+        data: &[u8],
+        offset: usize,
+        packet_byte_count: u64,
+    ) -> Option<()> {
+        let mut next_header = *data.get(offset + 6)?;
+        let src_addr = bytes_at::<16>(data, offset + 8)?;
+        let dst_addr = bytes_at::<16>(data, offset + 24)?;
+
+        // ff00::/8
+        if dst_addr[0] != 0xff {
+            return Some(());
+        }
+
+        self.count_group_traffic(if_index, dst_addr, packet_byte_count);
+
+        let mut icmp_offset = offset + IPV6_HDR_LEN;
+        if next_header == IPPROTO_HOPOPTS {
+            next_header = *data.get(icmp_offset)?;
+            let ext_header_len = *data.get(icmp_offset + 1)? as usize;
+            icmp_offset += (ext_header_len + 1) * 8;
+        }
+
+        if next_header != IPPROTO_ICMPV6 {
+            return Some(());
+        }
+
+        let icmp_type = *data.get(icmp_offset)?;
+        match icmp_type {
+            MLD_V1_LISTENER_REPORT | MLD_V1_LISTENER_DONE => {
+                let group = bytes_at::<16>(data, icmp_offset + 8)?;
+                let state = if icmp_type == MLD_V1_LISTENER_REPORT {
+                    MULTICAST_MEMBERSHIP_JOINED
+                } else {
+                    MULTICAST_MEMBERSHIP_LEFT
+                };
+                self.record_membership(
+                    (if_index, group, src_addr),
+                    timestamp_ns,
+                    MULTICAST_REPORT_MLD_V1,
+                    state,
+                );
+            }
+            MLD_V2_LISTENER_REPORT => {
+                let num_records = u16_at(data, icmp_offset + 6)? as usize;
+                let mut record_offset = icmp_offset + 8;
+                for _ in 0..num_records.min(MAX_MULTICAST_GROUP_RECORDS) {
+                    let record_type = *data.get(record_offset)?;
+                    let aux_data_len = *data.get(record_offset + 1)? as usize;
+                    let num_sources = u16_at(data, record_offset + 2)?;
+                    let group = bytes_at::<16>(data, record_offset + 4)?;
+                    self.record_membership(
+                        (if_index, group, src_addr),
+                        timestamp_ns,
+                        MULTICAST_REPORT_MLD_V2,
+                        group_record_state(record_type, num_sources),
+                    );
+                    record_offset += 20 + (num_sources as usize) * 16 + aux_data_len * 4;
+                }
+            }
+            _ => {}
+        }
+
+        Some(())
+    }
+
+    fn count_group_traffic(&mut self, if_index: u32, group: [u8; 16], packet_byte_count: u64) {
+        add_to_counter(
+            self.multicast_group_counters
+                .entry((if_index, group))
+                .or_insert(Counter {
+                    bytes: 0,
+                    packets: 0,
+                }),
+            packet_byte_count,
+        );
+    }
+
+    fn record_membership(
+        &mut self,
+        member_id: MemberId,
+        timestamp_ns: u64,
+        report_kind: u32,
+        state: u32,
+    ) {
+        let report_count = self
+            .multicast_members
+            .get(&member_id)
+            .map(|membership| membership.report_count + 1)
+            .unwrap_or(1);
+        self.multicast_members.insert(
+            member_id,
+            MulticastMembership {
+                last_report_ns: timestamp_ns,
+                report_count,
+                report_kind,
+                state,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IF_INDEX: u32 = 1;
+    const SRC_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+
+    fn engine() -> CountingEngine {
+        let mut engine = CountingEngine::new(1_000_000, 0);
+        engine.attach(IF_INDEX);
+        engine
+    }
+
+    fn count(engine: &mut CountingEngine, frame: &[u8]) {
+        engine.count_frame(IF_INDEX, 0, 0, frame, frame.len() as u32);
+    }
+
+    fn ethernet_frame(ether_type: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x01, 0, 0x5e, 0, 0, 1];
+        frame.extend_from_slice(&SRC_MAC);
+        frame.extend_from_slice(&ether_type.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// An IPv4 packet whose header carries `options`, which have to be a multiple of 4 bytes
+    fn ipv4_frame(
+        protocol: u8,
+        src_addr: [u8; 4],
+        dst_addr: [u8; 4],
+        flags_fragment_offset: u16,
+        options: &[u8],
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let header_len = 20 + options.len();
+        let mut packet = vec![0x40 | (header_len / 4) as u8, 0];
+        packet.extend_from_slice(&((header_len + payload.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&flags_fragment_offset.to_be_bytes());
+        packet.extend_from_slice(&[64, protocol, 0, 0]);
+        packet.extend_from_slice(&src_addr);
+        packet.extend_from_slice(&dst_addr);
+        packet.extend_from_slice(options);
+        packet.extend_from_slice(payload);
+        ethernet_frame(ETH_P_IPV4, &packet)
+    }
+
+    fn ipv6_frame(
+        next_header: u8,
+        src_addr: [u8; 16],
+        dst_addr: [u8; 16],
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[next_header, 1]);
+        packet.extend_from_slice(&src_addr);
+        packet.extend_from_slice(&dst_addr);
+        packet.extend_from_slice(payload);
+        ethernet_frame(ETH_P_IPV6, &packet)
+    }
+
+    fn ipv6_addr(prefix: u16, suffix: u16) -> [u8; 16] {
+        let mut addr = [0; 16];
+        addr[..2].copy_from_slice(&prefix.to_be_bytes());
+        addr[14..].copy_from_slice(&suffix.to_be_bytes());
+        addr
+    }
+
+    fn igmp_v3_record(record_type: u8, group: [u8; 4], num_sources: u16, aux_words: u8) -> Vec<u8> {
+        let mut record = vec![record_type, aux_words];
+        record.extend_from_slice(&num_sources.to_be_bytes());
+        record.extend_from_slice(&group);
+        record.extend(std::iter::repeat_n(0x0a, num_sources as usize * 4));
+        record.extend(std::iter::repeat_n(0xee, aux_words as usize * 4));
+        record
+    }
+
+    fn igmp_v3_report(num_records: u16, records: &[Vec<u8>]) -> Vec<u8> {
+        let mut report = vec![IGMP_V3_MEMBERSHIP_REPORT, 0, 0, 0, 0, 0];
+        report.extend_from_slice(&num_records.to_be_bytes());
+        for record in records {
+            report.extend_from_slice(record);
+        }
+        report
+    }
+
+    fn mld_v2_record(record_type: u8, group: [u8; 16], num_sources: u16, aux_words: u8) -> Vec<u8> {
+        let mut record = vec![record_type, aux_words];
+        record.extend_from_slice(&num_sources.to_be_bytes());
+        record.extend_from_slice(&group);
+        record.extend(std::iter::repeat_n(0x0a, num_sources as usize * 16));
+        record.extend(std::iter::repeat_n(0xee, aux_words as usize * 4));
+        record
+    }
+
+    fn membership(
+        engine: &CountingEngine,
+        group: [u8; 16],
+        reporter: [u8; 16],
+    ) -> Option<MulticastMembership> {
+        engine
+            .multicast_members
+            .get(&(IF_INDEX, group, reporter))
+            .copied()
+    }
+
+    #[test]
+    fn classifies_ipv4_fragments() {
+        let mut engine = engine();
+        let src_addr = [192, 168, 0, 1];
+        let dst_addr = [192, 168, 0, 2];
+        // Unfragmented, then with don't fragment set, then first, middle and last fragments
+        for flags_fragment_offset in [0, 0x4000, 0x2000, 0x2000 | 185, 370] {
+            count(
+                &mut engine,
+                &ipv4_frame(17, src_addr, dst_addr, flags_fragment_offset, &[], &[0; 8]),
+            );
+        }
+
+        let fragments = engine.fragment_counters[&IF_INDEX];
+        assert_eq!(fragments.ipv4_first, 1);
+        assert_eq!(fragments.ipv4_middle, 1);
+        assert_eq!(fragments.ipv4_last, 1);
+        assert_eq!(fragments.ipv6, 0);
+        assert_eq!(engine.hop_limit_counts[&(IF_INDEX, 64)], 5);
+    }
+
+    #[test]
+    fn counts_ipv6_fragments_behind_extension_headers() {
+        let mut engine = engine();
+        let hop_by_hop = [IPPROTO_FRAGMENT, 0, 0, 0, 0, 0, 0, 0];
+        let mut payload = hop_by_hop.to_vec();
+        payload.extend_from_slice(&[17, 0, 0, 1, 0, 0, 0, 1]);
+        count(
+            &mut engine,
+            &ipv6_frame(
+                IPPROTO_HOPOPTS,
+                ipv6_addr(0xfe80, 1),
+                ipv6_addr(0xfe80, 2),
+                &payload,
+            ),
+        );
+
+        assert_eq!(engine.fragment_counters[&IF_INDEX].ipv6, 1);
+        assert_eq!(engine.hop_limit_counts[&(IF_INDEX, 1)], 1);
+    }
+
+    #[test]
+    fn walks_igmp_v3_group_records() {
+        let mut engine = engine();
+        let reporter = [192, 168, 0, 1];
+        let report = igmp_v3_report(
+            3,
+            &[
+                // MODE_IS_EXCLUDE with a source and auxiliary data to skip over
+                igmp_v3_record(2, [239, 1, 1, 1], 1, 1),
+                // CHANGE_TO_INCLUDE with no sources, which leaves the group
+                igmp_v3_record(GROUP_RECORD_CHANGE_TO_INCLUDE, [239, 1, 1, 2], 0, 0),
+                // MODE_IS_INCLUDE with sources, which stays joined
+                igmp_v3_record(GROUP_RECORD_MODE_IS_INCLUDE, [239, 1, 1, 3], 2, 0),
+            ],
+        );
+        // With the router alert option, as IGMP reports are sent
+        let router_alert = [0x94, 0x04, 0, 0];
+        count(
+            &mut engine,
+            &ipv4_frame(
+                IPPROTO_IGMP,
+                reporter,
+                [224, 0, 0, 22],
+                0,
+                &router_alert,
+                &report,
+            ),
+        );
+
+        let reporter = ipv4_mapped(reporter);
+        let states: Vec<Option<u32>> = [[239, 1, 1, 1], [239, 1, 1, 2], [239, 1, 1, 3]]
+            .into_iter()
+            .map(|group| {
+                membership(&engine, ipv4_mapped(group), reporter).map(|membership| {
+                    assert_eq!(membership.report_kind, MULTICAST_REPORT_IGMP_V3);
+                    membership.state
+                })
+            })
+            .collect();
+        assert_eq!(
+            states,
+            [
+                Some(MULTICAST_MEMBERSHIP_JOINED),
+                Some(MULTICAST_MEMBERSHIP_LEFT),
+                Some(MULTICAST_MEMBERSHIP_JOINED),
+            ]
+        );
+        assert_eq!(
+            engine.multicast_group_counters[&(IF_INDEX, ipv4_mapped([224, 0, 0, 22]))].packets,
+            1
+        );
+    }
+
+    #[test]
+    fn stops_at_truncated_igmp_v3_group_records() {
+        let mut engine = engine();
+        let reporter = [192, 168, 0, 1];
+        // Two records are announced, but the frame ends after the first
+        let report = igmp_v3_report(2, &[igmp_v3_record(2, [239, 1, 1, 1], 0, 0)]);
+        count(
+            &mut engine,
+            &ipv4_frame(IPPROTO_IGMP, reporter, [224, 0, 0, 22], 0, &[], &report),
+        );
+
+        assert_eq!(engine.multicast_members.len(), 1);
+        assert!(membership(&engine, ipv4_mapped([239, 1, 1, 1]), ipv4_mapped(reporter)).is_some());
+    }
+
+    #[test]
+    fn walks_mld_v2_group_records() {
+        let mut engine = engine();
+        let reporter = ipv6_addr(0xfe80, 1);
+        let mut payload = vec![IPPROTO_ICMPV6, 0, 5, 2, 0, 0, 1, 0];
+        payload.extend_from_slice(&[MLD_V2_LISTENER_REPORT, 0, 0, 0, 0, 0, 0, 3]);
+        // CHANGE_TO_EXCLUDE, then MODE_IS_INCLUDE with a source and auxiliary data, then
+        // CHANGE_TO_INCLUDE with no sources, which leaves the group
+        payload.extend(mld_v2_record(4, ipv6_addr(0xff05, 1), 0, 0));
+        payload.extend(mld_v2_record(
+            GROUP_RECORD_MODE_IS_INCLUDE,
+            ipv6_addr(0xff05, 2),
+            1,
+            1,
+        ));
+        payload.extend(mld_v2_record(
+            GROUP_RECORD_CHANGE_TO_INCLUDE,
+            ipv6_addr(0xff05, 3),
+            0,
+            0,
+        ));
+        count(
+            &mut engine,
+            &ipv6_frame(IPPROTO_HOPOPTS, reporter, ipv6_addr(0xff02, 0x16), &payload),
+        );
+
+        let states: Vec<Option<u32>> = (1..=3)
+            .map(|group| {
+                membership(&engine, ipv6_addr(0xff05, group), reporter).map(|membership| {
+                    assert_eq!(membership.report_kind, MULTICAST_REPORT_MLD_V2);
+                    membership.state
+                })
+            })
+            .collect();
+        assert_eq!(
+            states,
+            [
+                Some(MULTICAST_MEMBERSHIP_JOINED),
+                Some(MULTICAST_MEMBERSHIP_JOINED),
+                Some(MULTICAST_MEMBERSHIP_LEFT),
+            ]
+        );
+    }
+}
@@ -0,0 +1,980 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    os::fd::{AsFd, AsRawFd},
+    path::{Path, PathBuf},
+    slice,
+    str::FromStr,
+};
+
+use aya::{
+    maps::{
+        Array, IterableMap, Map, MapData, MapError, PerCpuHashMap, PerCpuValues, ProgramArray,
+        RingBuf,
+    },
+    programs::{
+        links::{FdLink, PinnedLink},
+        loaded_programs,
+        xdp::XdpLinkId,
+        ProgramFd, Xdp, XdpFlags,
+    },
+};
+use aya_log::EbpfLogger;
+use color_eyre::eyre::{eyre, Context, Result};
+use network_interface::NetworkInterface;
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+    CHAINED_PROGRAMS_MAX_ENTRIES,
+};
+
+use super::{
+    af_packet::AfPacketSource,
+    counting::CountingEngine,
+    host_interfaces,
+    netlink::{self, AttachedXdpProgram},
+    per_cpu::PerCpuBuffer,
+    pinned::{self, PINNED_INTERFACE_COUNTERS, PINNED_MAC_COUNTERS, PIN_DIR},
+    DataSource,
+};
+use crate::{settings::Settings, teardown};
+
+pub(crate) const XDP_PROGRAM_NAME: &str = "xdp_tsndt";
+
+/// Where the XDP program runs on an interface
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum XdpMode {
+    /// In the driver, before socket buffers are allocated
+    #[default]
+    Native,
+    /// In the generic networking stack, which works with any driver
+    Skb,
+    /// Offloaded to the NIC
+    Hardware,
+}
+
+impl XdpMode {
+    pub(crate) fn next(&self) -> Self {
+        match self {
+            XdpMode::Native => XdpMode::Skb,
+            XdpMode::Skb => XdpMode::Hardware,
+            XdpMode::Hardware => XdpMode::Native,
+        }
+    }
+
+    /// The mode to try next if attaching in this one fails
+    fn fallback(&self) -> Option<Self> {
+        match self {
+            XdpMode::Hardware => Some(XdpMode::Native),
+            XdpMode::Native => Some(XdpMode::Skb),
+            XdpMode::Skb => None,
+        }
+    }
+
+    fn flags(&self) -> XdpFlags {
+        match self {
+            XdpMode::Native => XdpFlags::DRV_MODE,
+            XdpMode::Skb => XdpFlags::SKB_MODE,
+            XdpMode::Hardware => XdpFlags::HW_MODE,
+        }
+    }
+
+    /// The XDP_FLAGS_*_MODE flag that netlink attaches in this mode with
+    pub(crate) fn mode_flags(&self) -> u32 {
+        match self {
+            XdpMode::Native => netlink::XDP_FLAGS_DRV_MODE,
+            XdpMode::Skb => netlink::XDP_FLAGS_SKB_MODE,
+            XdpMode::Hardware => netlink::XDP_FLAGS_HW_MODE,
+        }
+    }
+
+    fn from_mode_flags(mode_flags: u32) -> Option<Self> {
+        [XdpMode::Native, XdpMode::Skb, XdpMode::Hardware]
+            .into_iter()
+            .find(|xdp_mode| xdp_mode.mode_flags() == mode_flags)
+    }
+}
+
+impl fmt::Display for XdpMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XdpMode::Native => write!(f, "native"),
+            XdpMode::Skb => write!(f, "skb"),
+            XdpMode::Hardware => write!(f, "hw"),
+        }
+    }
+}
+
+impl FromStr for XdpMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" | "drv" => Ok(XdpMode::Native),
+            "skb" | "generic" => Ok(XdpMode::Skb),
+            "hw" | "hardware" => Ok(XdpMode::Hardware),
+            _ => Err(format!(
+                "unknown XDP mode '{s}', expected one of native, skb or hw"
+            )),
+        }
+    }
+}
+
+/// The XDP modes requested for the interfaces, by name
+#[derive(Clone, Debug, Default)]
+pub(crate) struct XdpModes {
+    pub(crate) default: XdpMode,
+    pub(crate) per_interface: HashMap<String, XdpMode>,
+}
+
+impl XdpModes {
+    fn get(&self, interface_name: &str) -> XdpMode {
+        self.per_interface
+            .get(interface_name)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// What to do about an XDP program that another tool attached to an interface
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum ExistingXdpPolicy {
+    /// Leave the program attached and collect with AF_PACKET instead
+    #[default]
+    Keep,
+    /// Attach xdp_tsndt in place of the program while collecting
+    Replace,
+    /// Attach xdp_tsndt in place of the program while collecting, and have xdp_tsndt tail call it
+    /// so that it still handles every frame
+    Chain,
+}
+
+/// An XDP program attached by another tool, which xdp_tsndt took the place of and which is put
+/// back when collection stops
+struct DisplacedProgram {
+    id: u32,
+    fd: ProgramFd,
+    mode_flags: u32,
+    chained: bool,
+    pin_path: PathBuf,
+}
+
+/// How frames are collected on an interface
+#[derive(Clone, Copy, PartialEq)]
+enum Backend {
+    Xdp(XdpMode),
+    // Used when the XDP program cannot be attached to the interface in any mode
+    AfPacket,
+}
+
+/// Reads the counters maintained by the xdp_tsndt program attached to the host interfaces.
+/// Interfaces which XDP does not work on fall back to AF_PACKET sockets, whose frames are counted
+/// in userspace.
+pub(crate) struct EbpfSource {
+    bpf: aya::Ebpf,
+    interfaces: Vec<NetworkInterface>,
+    xdp_modes: XdpModes,
+    existing_xdp_policy: ExistingXdpPolicy,
+    pin: bool,
+    xdp_link_ids: HashMap<u32, XdpLinkId>,
+    pinned_links: HashMap<u32, PinnedLink>,
+    displaced_programs: HashMap<u32, DisplacedProgram>,
+    packet_samples: RingBuf<MapData>,
+    maps: EbpfMaps,
+    buffers: EbpfBuffers,
+    // The backend an interface was last attached with, which its counters are read from even
+    // after detaching
+    backends: HashMap<u32, Backend>,
+    af_packet: AfPacketSource,
+}
+
+/// Handles on the maps which the counters are read from, taken out of the loaded program once
+/// rather than looked up by name at every read
+struct EbpfMaps {
+    interface_rx_counters: PerCpuHashMap<MapData, u32, Counter>,
+    rx_queue_rx_counters: PerCpuHashMap<MapData, RxQueueKey, Counter>,
+    interface_bursts: PerCpuHashMap<MapData, u32, BurstCounter>,
+    burst_epoch: Array<MapData, u64>,
+    inter_arrival_histograms: PerCpuHashMap<MapData, u32, InterArrivalHistogram>,
+    src_mac_rx_counters: PerCpuHashMap<MapData, [u8; 6], Counter>,
+    fragment_rx_counters: PerCpuHashMap<MapData, u32, IpFragmentCounter>,
+    hop_limit_rx_counters: PerCpuHashMap<MapData, HopLimitKey, u64>,
+    group_rx_counters: PerCpuHashMap<MapData, MulticastGroupKey, Counter>,
+    group_members: aya::maps::HashMap<MapData, MulticastMemberKey, MulticastMembership>,
+}
+
+impl EbpfMaps {
+    fn take(bpf: &mut aya::Ebpf) -> Result<Self> {
+        Ok(Self {
+            interface_rx_counters: take_map(bpf, PINNED_INTERFACE_COUNTERS)?,
+            rx_queue_rx_counters: take_map(bpf, "IF_RXQ_RX_COUNT")?,
+            interface_bursts: take_map(bpf, "IF_BURST_RX")?,
+            burst_epoch: take_map(bpf, "BURST_EPOCH")?,
+            inter_arrival_histograms: take_map(bpf, "IF_IAT_HIST")?,
+            src_mac_rx_counters: take_map(bpf, PINNED_MAC_COUNTERS)?,
+            fragment_rx_counters: take_map(bpf, "IP_FRAG_RX_COUNT")?,
+            hop_limit_rx_counters: take_map(bpf, "HOP_LIMIT_RX_COUNT")?,
+            group_rx_counters: take_map(bpf, "MCAST_GROUP_RX_COUNT")?,
+            group_members: take_map(bpf, "MCAST_GROUP_MEMBERS")?,
+        })
+    }
+}
+
+/// The per-CPU values of the map entry being read, for each type of value
+struct EbpfBuffers {
+    counters: PerCpuBuffer<Counter>,
+    bursts: PerCpuBuffer<BurstCounter>,
+    inter_arrival_histograms: PerCpuBuffer<InterArrivalHistogram>,
+    fragment_counters: PerCpuBuffer<IpFragmentCounter>,
+    hop_limit_counts: PerCpuBuffer<u64>,
+}
+
+impl EbpfBuffers {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            counters: PerCpuBuffer::new()?,
+            bursts: PerCpuBuffer::new()?,
+            inter_arrival_histograms: PerCpuBuffer::new()?,
+            fragment_counters: PerCpuBuffer::new()?,
+            hop_limit_counts: PerCpuBuffer::new()?,
+        })
+    }
+}
+
+fn take_map<M: TryFrom<Map, Error = MapError>>(bpf: &mut aya::Ebpf, name: &str) -> Result<M> {
+    let map = bpf
+        .take_map(name)
+        .ok_or_else(|| eyre!("Could not find the {} eBPF map", name))?;
+    M::try_from(map).wrap_err_with(|| format!("failed to open the {} eBPF map", name))
+}
+
+/// Puts back the XDP programs that xdp_tsndt took the place of, then detaches it from the
+/// interfaces that it was attached to without a link, unless they are left attached by --pin
+fn undo_netlink_attachments(interfaces: &[NetworkInterface], pin: bool) {
+    if let Err(report) = pinned::restore_displaced_programs(interfaces) {
+        tracing::error!("{:#}", report);
+    }
+    if pin {
+        return;
+    }
+    let result = pinned::detach_unlinked_xdp_tsndt(interfaces, |interface, result| match result {
+        Ok(()) => tracing::info!("Detached the XDP program from {}", interface.name),
+        // Links cannot be detached through netlink, and exiting releases them anyway
+        Err(report) => tracing::debug!("{:#}", report),
+    });
+    if let Err(report) = result {
+        tracing::error!("{:#}", report);
+    }
+}
+
+fn remove_pin(path: &Path) -> Result<()> {
+    fs::remove_file(path).wrap_err_with(|| format!("failed to remove {}", path.display()))
+}
+
+pub(crate) fn clock_now_ns(clock_id: libc::clockid_t) -> u64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(clock_id, &mut now) };
+    now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64
+}
+
+impl EbpfSource {
+    /// Loads the eBPF object and the XDP program in it into the kernel, which fails on kernels
+    /// without BTF or ring buffer support. With `pin`, the counters pinned by an earlier run are
+    /// reused rather than created, and new ones are pinned.
+    pub(crate) fn load(settings: &Settings, pin: bool) -> Result<aya::Ebpf> {
+        let map_pin_dir = match pin {
+            true => PathBuf::from(PIN_DIR),
+            false => pinned::loading_pin_dir(),
+        };
+        fs::create_dir_all(&map_pin_dir).wrap_err_with(|| {
+            format!(
+                "failed to create the pin directory {}",
+                map_pin_dir.display()
+            )
+        })?;
+        let loaded = aya::EbpfLoader::new()
+            .set_global("BURST_BUCKET_NS", &settings.burst_bucket_ns, true)
+            .set_global("SAMPLE_RATE", &settings.sample_rate, true)
+            .map_pin_path(&map_pin_dir)
+            .load(aya::include_bytes_aligned!(concat!(
+                env!("OUT_DIR"),
+                "/tsndt"
+            )));
+        if !pin {
+            fs::remove_dir_all(&map_pin_dir)
+                .wrap_err_with(|| format!("failed to remove {}", map_pin_dir.display()))?;
+        }
+        let mut bpf = loaded.wrap_err("failed to load the eBPF object")?;
+        EbpfLogger::init(&mut bpf).wrap_err("failed to initialize the eBPF logger")?;
+
+        let program: &mut Xdp = bpf
+            .program_mut(XDP_PROGRAM_NAME)
+            .ok_or_else(|| eyre!("Could not find the {} eBPF program", XDP_PROGRAM_NAME))?
+            .try_into()?;
+        program
+            .load()
+            .wrap_err_with(|| format!("failed to load the {} program", XDP_PROGRAM_NAME))?;
+        Ok(bpf)
+    }
+
+    /// Loads the XDP program and attaches it to the selected host interfaces, in the requested
+    /// modes. XDP programs which are already attached are dealt with according to the policy.
+    /// With `pin`, the counters and XDP links are pinned, taking over the ones pinned by an
+    /// earlier run.
+    pub(crate) fn new(
+        mut bpf: aya::Ebpf,
+        xdp_modes: XdpModes,
+        existing_xdp_policy: ExistingXdpPolicy,
+        pin: bool,
+        settings: &Settings,
+    ) -> Result<Self> {
+        // Initialize the interfaces list to include the selected interfaces on the host system
+        let interfaces = host_interfaces(&settings.interface_filter)?;
+        for interface_name in xdp_modes.per_interface.keys() {
+            if !interfaces
+                .iter()
+                .any(|interface| &interface.name == interface_name)
+            {
+                tracing::warn!(
+                    "An XDP mode was given for interface {}, which is unknown or not selected",
+                    interface_name
+                );
+            }
+        }
+
+        // The ring buffer is drained only by this source, so it takes ownership of the map, as
+        // it does of the counter maps so that reading them costs no lookups
+        let packet_samples = take_map(&mut bpf, "PACKET_SAMPLES")?;
+        let maps = EbpfMaps::take(&mut bpf)?;
+
+        let mut ebpf_source = Self {
+            bpf,
+            interfaces: interfaces.clone(),
+            xdp_modes,
+            existing_xdp_policy,
+            pin,
+            xdp_link_ids: HashMap::new(),
+            pinned_links: HashMap::new(),
+            displaced_programs: HashMap::new(),
+            packet_samples,
+            maps,
+            buffers: EbpfBuffers::new()?,
+            backends: HashMap::new(),
+            af_packet: AfPacketSource::new(
+                Vec::new(),
+                CountingEngine::new(settings.burst_bucket_ns, settings.sample_rate),
+            ),
+        };
+        if pin {
+            fs::create_dir_all(pinned::links_pin_dir())
+                .wrap_err_with(|| format!("failed to create the pin directory {}", PIN_DIR))?;
+        }
+        // Exiting releases the XDP links held by this run, but not what it attached through
+        // netlink, so a second signal, which exits without dropping the source, undoes that
+        let attached_interfaces = interfaces.clone();
+        teardown::set_exit_hook(move || undo_netlink_attachments(&attached_interfaces, pin));
+        for interface in &interfaces {
+            ebpf_source.attach(interface)?;
+        }
+
+        Ok(ebpf_source)
+    }
+
+    /// Swaps xdp_tsndt into the XDP link that an earlier run with --pin left attached to the
+    /// interface, so that no frames go uncounted in between. Returns the mode that the link was
+    /// attached in, or None if there is no such link.
+    fn take_over_pinned_link(&mut self, interface: &NetworkInterface) -> Result<Option<XdpMode>> {
+        let pin_path = pinned::link_pin_path(&interface.name);
+        if !pin_path.exists() {
+            return Ok(None);
+        }
+        // The interface may have gone away since, taking the link with it
+        let Some(attached) = netlink::attached_xdp_program(interface.index)? else {
+            remove_pin(&pin_path)?;
+            return Ok(None);
+        };
+        let xdp_mode = XdpMode::from_mode_flags(attached.mode_flags).ok_or_else(|| {
+            eyre!(
+                "The pinned XDP link is attached with unknown flags {:#x}",
+                attached.mode_flags
+            )
+        })?;
+        if xdp_mode != self.xdp_modes.get(&interface.name) {
+            tracing::warn!(
+                "Keeping the XDP program on {} in {} mode, which the pinned XDP link is attached in",
+                interface.name,
+                xdp_mode
+            );
+        }
+
+        // Updating the link replaces the program in one step. The link stays pinned, so this run
+        // holds on to it through the pin rather than through the program.
+        let program = self.xdp_program()?;
+        let xdp_link_id = program.attach_to_link(PinnedLink::from_pin(&pin_path)?.into())?;
+        program.take_link(xdp_link_id)?;
+        self.pinned_links
+            .insert(interface.index, PinnedLink::from_pin(&pin_path)?);
+        Ok(Some(xdp_mode))
+    }
+
+    /// Moves the XDP link of an interface to the BPF filesystem, so that it outlives tsndt
+    fn pin_xdp_link(&mut self, interface: &NetworkInterface) -> Result<()> {
+        let xdp_link_id = self
+            .xdp_link_ids
+            .remove(&interface.index)
+            .ok_or_else(|| eyre!("Could not find the XDP link of {} to pin", interface.name))?;
+        let xdp_link = self.xdp_program()?.take_link(xdp_link_id)?;
+        let pinned_link = FdLink::try_from(xdp_link)
+            .wrap_err("pinning XDP links requires Linux 5.9 or newer")?
+            .pin(pinned::link_pin_path(&interface.name))?;
+        self.pinned_links.insert(interface.index, pinned_link);
+        Ok(())
+    }
+
+    /// Makes sure that the interface has a counter entry, so that it can be read before the
+    /// first frame arrives
+    fn init_interface_counters(&mut self, if_index: u32) -> Result<()> {
+        let num_cpus = aya::util::nr_cpus().map_err(|(_, error)| error)?;
+        let ebpf_interface_rx_counters = &mut self.maps.interface_rx_counters;
+        if ebpf_interface_rx_counters.get(&if_index, 0).is_err() {
+            ebpf_interface_rx_counters.insert(
+                if_index,
+                PerCpuValues::try_from(vec![
+                    Counter {
+                        bytes: 0,
+                        packets: 0
+                    };
+                    num_cpus
+                ])?,
+                0,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn xdp_program(&mut self) -> Result<&mut Xdp> {
+        Ok(self
+            .bpf
+            .program_mut(XDP_PROGRAM_NAME)
+            .ok_or_else(|| eyre!("Could not find the {} eBPF program", XDP_PROGRAM_NAME))?
+            .try_into()?)
+    }
+
+    fn is_af_packet(&self, if_index: u32) -> bool {
+        self.backends.get(&if_index) == Some(&Backend::AfPacket)
+    }
+
+    fn is_attached(&self, if_index: u32) -> bool {
+        self.xdp_link_ids.contains_key(&if_index)
+            || self.displaced_programs.contains_key(&if_index)
+            || self.pinned_links.contains_key(&if_index)
+            || self.af_packet.is_attached(if_index)
+    }
+
+    /// Attaches the XDP program in the requested mode, falling back to modes which are more
+    /// widely supported. Returns the mode attached in, if any worked.
+    fn attach_xdp(&mut self, interface: &NetworkInterface) -> Result<Option<XdpMode>> {
+        if self.pin {
+            if let Some(xdp_mode) = self.take_over_pinned_link(interface)? {
+                return Ok(Some(xdp_mode));
+            }
+        }
+        if let Some(existing) = netlink::attached_xdp_program(interface.index)? {
+            return self.displace_existing_program(interface, existing);
+        }
+        let mut mode = Some(self.xdp_modes.get(&interface.name));
+        while let Some(xdp_mode) = mode {
+            let program = self.xdp_program()?;
+            match program.attach(&interface.name, xdp_mode.flags()) {
+                Ok(xdp_link_id) => {
+                    self.xdp_link_ids.insert(interface.index, xdp_link_id);
+                    return Ok(Some(xdp_mode));
+                }
+                Err(error) => {
+                    tracing::warn!(
+                        "Could not attach the XDP program to {} in {} mode: {}",
+                        interface.name,
+                        xdp_mode,
+                        error
+                    );
+                    mode = xdp_mode.fallback();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Attaches xdp_tsndt in place of an XDP program that another tool attached to the interface,
+    /// if the policy allows it, and chains to that program if requested. xdp_tsndt takes over the
+    /// mode that the program was attached in. Returns None if the program has to be left in
+    /// place.
+    fn displace_existing_program(
+        &mut self,
+        interface: &NetworkInterface,
+        existing: AttachedXdpProgram,
+    ) -> Result<Option<XdpMode>> {
+        let chained = match self.existing_xdp_policy {
+            ExistingXdpPolicy::Keep => {
+                tracing::warn!(
+                    "{} already has XDP program {} attached, pass --chain or --force to attach \
+                     the XDP program anyway",
+                    interface.name,
+                    existing.id
+                );
+                return Ok(None);
+            }
+            ExistingXdpPolicy::Replace => false,
+            ExistingXdpPolicy::Chain => true,
+        };
+        let xdp_mode = XdpMode::from_mode_flags(existing.mode_flags).ok_or_else(|| {
+            eyre!(
+                "XDP program {} is attached with unknown flags {:#x}",
+                existing.id,
+                existing.mode_flags
+            )
+        })?;
+        if xdp_mode != self.xdp_modes.get(&interface.name) {
+            tracing::warn!(
+                "Attaching the XDP program to {} in {} mode, which XDP program {} was attached in",
+                interface.name,
+                xdp_mode,
+                existing.id
+            );
+        }
+
+        // Holding a file descriptor keeps the program loaded while it is detached, and pinning it
+        // keeps it loaded for `tsndt cleanup` to put back should tsndt be killed
+        let fd = loaded_programs()
+            .filter_map(|program_info| program_info.ok())
+            .find(|program_info| program_info.id() == existing.id)
+            .ok_or_else(|| eyre!("Could not find XDP program {}", existing.id))?
+            .fd()?;
+        let pin_path = pinned::displaced_pin_path(&interface.name, xdp_mode);
+        pinned::pin_program(fd.as_fd(), &pin_path).wrap_err(
+            "run `tsndt cleanup` to put back the XDP programs displaced by an earlier run",
+        )?;
+        if chained {
+            if interface.index >= CHAINED_PROGRAMS_MAX_ENTRIES {
+                remove_pin(&pin_path)?;
+                return Err(eyre!(
+                    "Cannot chain to XDP programs on interfaces with an index of {} or more",
+                    CHAINED_PROGRAMS_MAX_ENTRIES
+                ));
+            }
+            self.chained_programs()?.set(interface.index, &fd, 0)?;
+        }
+        let xdp_tsndt_fd = self.xdp_program()?.fd()?.as_fd().as_raw_fd();
+        if let Err(report) = netlink::replace_xdp_program(
+            interface.index,
+            xdp_tsndt_fd,
+            fd.as_fd().as_raw_fd(),
+            existing.mode_flags,
+        ) {
+            if chained {
+                self.chained_programs()?.clear_index(&interface.index)?;
+            }
+            remove_pin(&pin_path)?;
+            return Err(report);
+        }
+
+        self.displaced_programs.insert(
+            interface.index,
+            DisplacedProgram {
+                id: existing.id,
+                fd,
+                mode_flags: existing.mode_flags,
+                chained,
+                pin_path,
+            },
+        );
+        Ok(Some(xdp_mode))
+    }
+
+    /// Puts back the XDP program displaced from an interface in place of xdp_tsndt
+    fn restore_displaced_program(&mut self, if_index: u32) -> Result<()> {
+        let Some(displaced) = self.displaced_programs.remove(&if_index) else {
+            return Ok(());
+        };
+        let xdp_tsndt_fd = self.xdp_program()?.fd()?.as_fd().as_raw_fd();
+        netlink::replace_xdp_program(
+            if_index,
+            displaced.fd.as_fd().as_raw_fd(),
+            xdp_tsndt_fd,
+            displaced.mode_flags,
+        )
+        .wrap_err_with(|| format!("failed to put back XDP program {}", displaced.id))?;
+        // Only once xdp_tsndt no longer runs, so that it chains to the program until then
+        if displaced.chained {
+            self.chained_programs()?.clear_index(&if_index)?;
+        }
+        remove_pin(&displaced.pin_path)
+    }
+
+    fn chained_programs(&mut self) -> Result<ProgramArray<&mut MapData>> {
+        Ok(ProgramArray::try_from(
+            self.find_map_mut("CHAINED_PROGRAMS")?,
+        )?)
+    }
+
+    fn attach_af_packet(&mut self, interface: &NetworkInterface) -> Result<()> {
+        self.af_packet.attach(interface)?;
+        self.backends.insert(interface.index, Backend::AfPacket);
+        Ok(())
+    }
+
+    fn find_map_mut(&mut self, name: &str) -> Result<&mut Map> {
+        self.bpf
+            .map_mut(name)
+            .ok_or_else(|| eyre!("Could not find the {} eBPF map", name))
+    }
+}
+
+impl DataSource for EbpfSource {
+    fn interfaces(&self) -> &[NetworkInterface] {
+        &self.interfaces
+    }
+
+    /// Counts the frames received on the AF_PACKET sockets since the previous tick
+    fn on_tick(&mut self) -> Result<bool> {
+        self.af_packet.on_tick()
+    }
+
+    fn collection_backend(&self, if_index: u32) -> Option<String> {
+        match self.backends.get(&if_index)? {
+            Backend::Xdp(xdp_mode) => Some(match self.displaced_programs.get(&if_index) {
+                Some(displaced) if displaced.chained => {
+                    format!("XDP {xdp_mode}, chaining {}", displaced.id)
+                }
+                Some(displaced) => format!("XDP {xdp_mode}, replacing {}", displaced.id),
+                None => format!("XDP {xdp_mode}"),
+            }),
+            Backend::AfPacket => Some(String::from("AF_PACKET")),
+        }
+    }
+
+    fn xdp_mode(&self, interface: &NetworkInterface) -> Option<XdpMode> {
+        Some(self.xdp_modes.get(&interface.name))
+    }
+
+    fn set_xdp_mode(&mut self, interface: &NetworkInterface, xdp_mode: XdpMode) -> Result<()> {
+        self.xdp_modes
+            .per_interface
+            .insert(interface.name.clone(), xdp_mode);
+        // The new mode takes effect when collection is next enabled on a detached interface
+        if self.is_attached(interface.index) {
+            self.detach(interface)?;
+            self.attach(interface)?;
+        }
+        Ok(())
+    }
+
+    fn attach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        let xdp_mode = self.attach_xdp(interface).unwrap_or_else(|report| {
+            tracing::warn!(
+                "Could not attach the XDP program to {}: {:#}",
+                interface.name,
+                report
+            );
+            None
+        });
+        match xdp_mode {
+            Some(xdp_mode) => {
+                self.backends
+                    .insert(interface.index, Backend::Xdp(xdp_mode));
+                if self.pin && !self.pinned_links.contains_key(&interface.index) {
+                    self.pin_xdp_link(interface)?;
+                }
+            }
+            None => {
+                tracing::warn!("Falling back to AF_PACKET to collect on {}", interface.name);
+                self.attach_af_packet(interface)?;
+            }
+        }
+        self.init_interface_counters(interface.index)
+    }
+
+    fn detach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        if self.af_packet.is_attached(interface.index) {
+            return self.af_packet.detach(interface);
+        }
+        if self.displaced_programs.contains_key(&interface.index) {
+            self.restore_displaced_program(interface.index)?;
+            return self.init_interface_counters(interface.index);
+        }
+        if let Some(pinned_link) = self.pinned_links.remove(&interface.index) {
+            // Dropping the unpinned link detaches the program
+            pinned_link.unpin()?;
+            return self.init_interface_counters(interface.index);
+        }
+
+        let xdp_link_id = self.xdp_link_ids.remove(&interface.index).ok_or_else(|| {
+            eyre!(
+                "Could not find an interface with index {} to detach eBPF program from",
+                interface.index
+            )
+        })?;
+        let program = self.xdp_program()?;
+        program.detach(xdp_link_id)?;
+        self.init_interface_counters(interface.index)
+    }
+
+    fn interface_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()> {
+        if self.is_af_packet(if_index) {
+            return self.af_packet.interface_counters(if_index, visit);
+        }
+        let found = self.buffers.counters.get(
+            self.maps.interface_rx_counters.map(),
+            &if_index,
+            |values| visit(values),
+        )?;
+        match found {
+            true => Ok(()),
+            false => Err(eyre!("No counters for interface with index {}", if_index)),
+        }
+    }
+
+    fn rx_queue_counters(&self, visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()> {
+        // A queue can be counted by both backends, for instance on an interface which fell back
+        // to AF_PACKET. Frames counted in userspace have no CPU, so they are added to the first
+        // one.
+        let engine = self.af_packet.engine();
+        let map = self.maps.rx_queue_rx_counters.map();
+        self.buffers
+            .counters
+            .for_each(map, |key: &RxQueueKey, values| {
+                if let Some(counter) = engine.rx_queue_counters.get(&(key.if_index, key.rx_queue)) {
+                    values[0].packets += counter.packets;
+                    values[0].bytes += counter.bytes;
+                }
+                visit(key, values)
+            })?;
+        for ((if_index, rx_queue), counter) in &engine.rx_queue_counters {
+            let key = RxQueueKey {
+                if_index: *if_index,
+                rx_queue: *rx_queue,
+            };
+            if !self.buffers.counters.get(map, &key, |_| ())? {
+                visit(&key, slice::from_ref(counter));
+            }
+        }
+        Ok(())
+    }
+
+    fn burst_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[BurstCounter])) -> Result<()> {
+        if self.is_af_packet(if_index) {
+            return self.af_packet.burst_counters(if_index, visit);
+        }
+        self.buffers
+            .bursts
+            .get(self.maps.interface_bursts.map(), &if_index, |values| {
+                visit(values)
+            })?;
+        Ok(())
+    }
+
+    fn set_burst_epoch(&mut self, epoch: u64) -> Result<()> {
+        self.maps.burst_epoch.set(0, epoch, 0)?;
+        self.af_packet.set_burst_epoch(epoch)
+    }
+
+    fn inter_arrival_histograms(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()> {
+        if self.is_af_packet(if_index) {
+            return self.af_packet.inter_arrival_histograms(if_index, visit);
+        }
+        self.buffers.inter_arrival_histograms.get(
+            self.maps.inter_arrival_histograms.map(),
+            &if_index,
+            |values| visit(values),
+        )?;
+        Ok(())
+    }
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()> {
+        // A MAC address can be seen through both backends. Frames counted in userspace have no
+        // CPU, so they are added to the first one.
+        let engine = self.af_packet.engine();
+        let map = self.maps.src_mac_rx_counters.map();
+        self.buffers
+            .counters
+            .for_each(map, |src_mac: &[u8; 6], values| {
+                if let Some(counter) = engine.mac_counters.get(src_mac) {
+                    values[0].packets += counter.packets;
+                    values[0].bytes += counter.bytes;
+                }
+                visit(src_mac, values)
+            })?;
+        for (src_mac, counter) in &engine.mac_counters {
+            if !self.buffers.counters.get(map, src_mac, |_| ())? {
+                visit(src_mac, slice::from_ref(counter));
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()> {
+        self.af_packet.remove_mac_counters(src_mac)?;
+        let src_mac_rx_counters = &mut self.maps.src_mac_rx_counters;
+        // The MAC address may only have been seen through AF_PACKET
+        if src_mac_rx_counters.get(src_mac, 0).is_ok() {
+            src_mac_rx_counters.remove(src_mac)?;
+        }
+        Ok(())
+    }
+
+    fn fragment_counters(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()> {
+        if self.is_af_packet(if_index) {
+            return self.af_packet.fragment_counters(if_index, visit);
+        }
+        self.buffers.fragment_counters.get(
+            self.maps.fragment_rx_counters.map(),
+            &if_index,
+            |values| visit(values),
+        )?;
+        Ok(())
+    }
+
+    fn hop_limit_counts(&self, visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()> {
+        // As for RX queues, frames counted in userspace are added to the first CPU
+        let engine = self.af_packet.engine();
+        let map = self.maps.hop_limit_rx_counters.map();
+        self.buffers
+            .hop_limit_counts
+            .for_each(map, |key: &HopLimitKey, values| {
+                if let Some(count) = engine.hop_limit_counts.get(&(key.if_index, key.hop_limit)) {
+                    values[0] += count;
+                }
+                visit(key, values)
+            })?;
+        for ((if_index, hop_limit), count) in &engine.hop_limit_counts {
+            let key = HopLimitKey {
+                if_index: *if_index,
+                hop_limit: *hop_limit,
+            };
+            if !self.buffers.hop_limit_counts.get(map, &key, |_| ())? {
+                visit(&key, slice::from_ref(count));
+            }
+        }
+        Ok(())
+    }
+
+    fn multicast_group_counters(
+        &self,
+        visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()> {
+        let engine = self.af_packet.engine();
+        let map = self.maps.group_rx_counters.map();
+        self.buffers
+            .counters
+            .for_each(map, |key: &MulticastGroupKey, values| {
+                if let Some(counter) = engine
+                    .multicast_group_counters
+                    .get(&(key.if_index, key.group))
+                {
+                    values[0].packets += counter.packets;
+                    values[0].bytes += counter.bytes;
+                }
+                visit(key, values)
+            })?;
+        for ((if_index, group), counter) in &engine.multicast_group_counters {
+            let key = MulticastGroupKey {
+                if_index: *if_index,
+                group: *group,
+            };
+            if !self.buffers.counters.get(map, &key, |_| ())? {
+                visit(&key, slice::from_ref(counter));
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_multicast_group_counters(&mut self, key: &MulticastGroupKey) -> Result<()> {
+        self.af_packet.remove_multicast_group_counters(key)?;
+        let group_rx_counters = &mut self.maps.group_rx_counters;
+        // The group may only have been seen through AF_PACKET
+        if group_rx_counters.get(key, 0).is_ok() {
+            group_rx_counters.remove(key)?;
+        }
+        Ok(())
+    }
+
+    fn multicast_members(
+        &self,
+        visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()> {
+        for entry in self.maps.group_members.iter() {
+            let (key, membership) = entry?;
+            visit(&key, &membership);
+        }
+        self.af_packet.multicast_members(visit)
+    }
+
+    fn remove_multicast_member(&mut self, key: &MulticastMemberKey) -> Result<()> {
+        if self.is_af_packet(key.if_index) {
+            return self.af_packet.remove_multicast_member(key);
+        }
+        self.maps.group_members.remove(key)?;
+        Ok(())
+    }
+
+    fn packet_samples(&mut self, visit: &mut dyn FnMut(&PacketSample)) {
+        while let Some(item) = self.packet_samples.next() {
+            if item.len() < std::mem::size_of::<PacketSample>() {
+                continue;
+            }
+            visit(&unsafe { (item.as_ptr() as *const PacketSample).read_unaligned() });
+        }
+        self.af_packet.packet_samples(visit)
+    }
+
+    /// Nanoseconds on the same clock used by `bpf_ktime_get_ns` in the eBPF program
+    fn now_ns(&self) -> u64 {
+        clock_now_ns(libc::CLOCK_MONOTONIC)
+    }
+
+    /// Offset to add to eBPF timestamps to get wall clock time
+    fn realtime_offset_ns(&self) -> u64 {
+        clock_now_ns(libc::CLOCK_REALTIME) - clock_now_ns(libc::CLOCK_MONOTONIC)
+    }
+}
+
+impl Drop for EbpfSource {
+    /// Detaches xdp_tsndt from the interfaces and puts back the XDP programs it displaced, which
+    /// would otherwise stay detached after exiting. Pinned links are left for the next run.
+    fn drop(&mut self) {
+        teardown::clear_exit_hook();
+        let if_indexes: Vec<u32> = self.xdp_link_ids.keys().copied().collect();
+        for if_index in if_indexes {
+            let Some(xdp_link_id) = self.xdp_link_ids.remove(&if_index) else {
+                continue;
+            };
+            match self
+                .xdp_program()
+                .and_then(|program| Ok(program.detach(xdp_link_id)?))
+            {
+                Ok(()) => tracing::info!("Detached the XDP program from interface {}", if_index),
+                Err(report) => tracing::error!("{:#}", report),
+            }
+        }
+        let if_indexes: Vec<u32> = self.displaced_programs.keys().copied().collect();
+        for if_index in if_indexes {
+            match self.restore_displaced_program(if_index) {
+                Ok(()) => tracing::info!(
+                    "Put back the displaced XDP program on interface {}",
+                    if_index
+                ),
+                Err(report) => tracing::error!("{:#}", report),
+            }
+        }
+    }
+}
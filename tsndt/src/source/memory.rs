@@ -0,0 +1,214 @@
+use std::slice;
+
+use color_eyre::eyre::{eyre, Result};
+use network_interface::NetworkInterface;
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+};
+
+use super::{counting::CountingEngine, DataSource};
+
+/// Keeps counters in memory rather than in eBPF maps. Frames are counted by whatever owns the
+/// source, and the counting tables can also be filled in directly.
+pub(crate) struct MemorySource {
+    interfaces: Vec<NetworkInterface>,
+    pub(crate) engine: CountingEngine,
+    now_ns: u64,
+}
+
+impl MemorySource {
+    /// Creates a source which is collecting on all of `interfaces`
+    pub(crate) fn new(interfaces: Vec<NetworkInterface>, mut engine: CountingEngine) -> Self {
+        for interface in &interfaces {
+            engine.attach(interface.index);
+        }
+
+        Self {
+            interfaces,
+            engine,
+            now_ns: 0,
+        }
+    }
+
+    /// Moves the source clock, which frame timestamps are expected to be on
+    pub(crate) fn set_now_ns(&mut self, now_ns: u64) {
+        self.now_ns = now_ns;
+    }
+}
+
+impl DataSource for MemorySource {
+    fn interfaces(&self) -> &[NetworkInterface] {
+        &self.interfaces
+    }
+
+    fn attach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        self.engine.attach(interface.index);
+        Ok(())
+    }
+
+    fn detach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        if !self.engine.is_attached(interface.index) {
+            return Err(eyre!(
+                "Not collecting on interface with index {}",
+                interface.index
+            ));
+        }
+        self.engine.detach(interface.index);
+        Ok(())
+    }
+
+    fn interface_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()> {
+        let counter = self
+            .engine
+            .interface_counters
+            .get(&if_index)
+            .ok_or_else(|| eyre!("No counters for interface with index {}", if_index))?;
+        visit(slice::from_ref(counter));
+        Ok(())
+    }
+
+    fn rx_queue_counters(&self, visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()> {
+        for ((if_index, rx_queue), counter) in &self.engine.rx_queue_counters {
+            let key = RxQueueKey {
+                if_index: *if_index,
+                rx_queue: *rx_queue,
+            };
+            visit(&key, slice::from_ref(counter));
+        }
+        Ok(())
+    }
+
+    fn burst_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[BurstCounter])) -> Result<()> {
+        if let Some(counter) = self.engine.burst_counters.get(&if_index) {
+            visit(slice::from_ref(counter));
+        }
+        Ok(())
+    }
+
+    fn set_burst_epoch(&mut self, epoch: u64) -> Result<()> {
+        self.engine.burst_epoch = epoch;
+        Ok(())
+    }
+
+    fn inter_arrival_histograms(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()> {
+        if let Some(histogram) = self.engine.inter_arrival_histograms.get(&if_index) {
+            visit(slice::from_ref(histogram));
+        }
+        Ok(())
+    }
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()> {
+        for (src_mac, counter) in &self.engine.mac_counters {
+            visit(src_mac, slice::from_ref(counter));
+        }
+        Ok(())
+    }
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()> {
+        self.engine.mac_counters.remove(src_mac);
+        Ok(())
+    }
+
+    fn fragment_counters(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()> {
+        if let Some(counter) = self.engine.fragment_counters.get(&if_index) {
+            visit(slice::from_ref(counter));
+        }
+        Ok(())
+    }
+
+    fn hop_limit_counts(&self, visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()> {
+        for ((if_index, hop_limit), count) in &self.engine.hop_limit_counts {
+            let key = HopLimitKey {
+                if_index: *if_index,
+                hop_limit: *hop_limit,
+            };
+            visit(&key, slice::from_ref(count));
+        }
+        Ok(())
+    }
+
+    fn multicast_group_counters(
+        &self,
+        visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()> {
+        for ((if_index, group), counter) in &self.engine.multicast_group_counters {
+            let key = MulticastGroupKey {
+                if_index: *if_index,
+                group: *group,
+            };
+            visit(&key, slice::from_ref(counter));
+        }
+        Ok(())
+    }
+
+    fn remove_multicast_group_counters(&mut self, key: &MulticastGroupKey) -> Result<()> {
+        self.engine
+            .multicast_group_counters
+            .remove(&(key.if_index, key.group));
+        Ok(())
+    }
+
+    fn multicast_members(
+        &self,
+        visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()> {
+        for ((if_index, group, reporter), membership) in &self.engine.multicast_members {
+            let key = MulticastMemberKey {
+                if_index: *if_index,
+                group: *group,
+                reporter: *reporter,
+            };
+            visit(&key, membership);
+        }
+        Ok(())
+    }
+
+    fn remove_multicast_member(&mut self, key: &MulticastMemberKey) -> Result<()> {
+        self.engine
+            .multicast_members
+            .remove(&(key.if_index, key.group, key.reporter));
+        Ok(())
+    }
+
+    fn packet_samples(&mut self, visit: &mut dyn FnMut(&PacketSample)) {
+        for sample in self.engine.packet_samples.drain(..) {
+            visit(&sample);
+        }
+    }
+
+    fn now_ns(&self) -> u64 {
+        self.now_ns
+    }
+
+    fn realtime_offset_ns(&self) -> u64 {
+        // Frames counted in userspace are timestamped with wall clock time already
+        0
+    }
+}
+
+#[cfg(test)]
+impl MemorySource {
+    /// A source collecting on interfaces test1, test2 and so on, with indexes from 1, which
+    /// samples no frames
+    pub(crate) fn with_test_interfaces(num_interfaces: u32) -> Self {
+        let interfaces = (1..=num_interfaces)
+            .map(|index| NetworkInterface {
+                name: format!("test{index}"),
+                addr: Vec::new(),
+                mac_addr: None,
+                index,
+                internal: false,
+            })
+            .collect();
+        Self::new(interfaces, CountingEngine::new(1_000_000, 0))
+    }
+}
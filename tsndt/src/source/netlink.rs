@@ -0,0 +1,290 @@
+// Minimal rtnetlink client for inspecting and changing the XDP program attached to an interface,
+// which is how XDP programs attached by other tools are found and moved aside. See
+// https://docs.kernel.org/userspace-api/netlink/intro.html and include/uapi/linux/if_link.h
+
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+use color_eyre::eyre::{eyre, Context, Result};
+
+const NLMSG_HDR_LEN: usize = 16;
+const IFINFOMSG_LEN: usize = 16;
+const RTATTR_HDR_LEN: usize = 4;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 1;
+const NLM_F_ACK: u16 = 4;
+const NLA_F_NESTED: u16 = 1 << 15;
+const NLA_TYPE_MASK: u16 = !(NLA_F_NESTED | (1 << 14));
+const RTM_NEWLINK: u16 = 16;
+const RTM_GETLINK: u16 = 18;
+const RTM_SETLINK: u16 = 19;
+
+const IFLA_XDP: u16 = 43;
+const IFLA_XDP_FD: u16 = 1;
+const IFLA_XDP_ATTACHED: u16 = 2;
+const IFLA_XDP_FLAGS: u16 = 3;
+const IFLA_XDP_PROG_ID: u16 = 4;
+const IFLA_XDP_DRV_PROG_ID: u16 = 5;
+const IFLA_XDP_SKB_PROG_ID: u16 = 6;
+const IFLA_XDP_HW_PROG_ID: u16 = 7;
+const IFLA_XDP_EXPECTED_FD: u16 = 8;
+
+const XDP_ATTACHED_DRV: u8 = 1;
+const XDP_ATTACHED_SKB: u8 = 2;
+const XDP_ATTACHED_HW: u8 = 3;
+const XDP_ATTACHED_MULTI: u8 = 4;
+
+pub(crate) const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+pub(crate) const XDP_FLAGS_DRV_MODE: u32 = 1 << 2;
+pub(crate) const XDP_FLAGS_HW_MODE: u32 = 1 << 3;
+const XDP_FLAGS_REPLACE: u32 = 1 << 4;
+
+const RECV_BUFFER_LEN: usize = 32 * 1024;
+
+/// An XDP program attached to an interface
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AttachedXdpProgram {
+    pub(crate) id: u32,
+    /// The XDP_FLAGS_*_MODE flag that the program is attached with
+    pub(crate) mode_flags: u32,
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn push_attr(buffer: &mut Vec<u8>, attr_type: u16, payload: &[u8]) {
+    buffer.extend_from_slice(&((RTATTR_HDR_LEN + payload.len()) as u16).to_ne_bytes());
+    buffer.extend_from_slice(&attr_type.to_ne_bytes());
+    buffer.extend_from_slice(payload);
+    buffer.resize(align4(buffer.len()), 0);
+}
+
+/// Iterates over the (type, payload) pairs of a run of attributes
+fn attrs(mut buffer: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buffer.len() < RTATTR_HDR_LEN {
+            return None;
+        }
+        let len = u16::from_ne_bytes([buffer[0], buffer[1]]) as usize;
+        let attr_type = u16::from_ne_bytes([buffer[2], buffer[3]]) & NLA_TYPE_MASK;
+        if len < RTATTR_HDR_LEN || len > buffer.len() {
+            return None;
+        }
+        let payload = &buffer[RTATTR_HDR_LEN..len];
+        buffer = &buffer[align4(len).min(buffer.len())..];
+        Some((attr_type, payload))
+    })
+}
+
+fn read_u32(payload: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(payload.get(..4)?.try_into().ok()?))
+}
+
+fn ifinfomsg(if_index: u32) -> Vec<u8> {
+    let mut buffer = vec![0; IFINFOMSG_LEN];
+    buffer[0] = libc::AF_UNSPEC as u8;
+    buffer[4..8].copy_from_slice(&(if_index as i32).to_ne_bytes());
+    buffer
+}
+
+struct RouteSocket {
+    fd: OwnedFd,
+    seq: u32,
+}
+
+impl RouteSocket {
+    fn open() -> Result<Self> {
+        let raw_fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if raw_fd < 0 {
+            return Err(io::Error::last_os_error()).wrap_err("failed to open a netlink socket");
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(raw_fd) },
+            seq: 0,
+        })
+    }
+
+    /// Sends a request and returns the payload of the reply, which is empty for acknowledgements
+    fn request(&mut self, msg_type: u16, flags: u16, payload: &[u8]) -> Result<Vec<u8>> {
+        self.seq += 1;
+        let mut message = Vec::with_capacity(NLMSG_HDR_LEN + payload.len());
+        message.extend_from_slice(&((NLMSG_HDR_LEN + payload.len()) as u32).to_ne_bytes());
+        message.extend_from_slice(&msg_type.to_ne_bytes());
+        message.extend_from_slice(&(flags | NLM_F_REQUEST).to_ne_bytes());
+        message.extend_from_slice(&self.seq.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(payload);
+
+        let sent = unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error()).wrap_err("failed to send a netlink request");
+        }
+
+        let mut buffer = vec![0u8; RECV_BUFFER_LEN];
+        loop {
+            let received = unsafe {
+                libc::recv(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                    0,
+                )
+            };
+            if received < 0 {
+                return Err(io::Error::last_os_error())
+                    .wrap_err("failed to receive a netlink reply");
+            }
+
+            let mut messages = &buffer[..received as usize];
+            while messages.len() >= NLMSG_HDR_LEN {
+                let len = u32::from_ne_bytes(messages[0..4].try_into().unwrap()) as usize;
+                let reply_type = u16::from_ne_bytes([messages[4], messages[5]]);
+                let seq = u32::from_ne_bytes(messages[8..12].try_into().unwrap());
+                if len < NLMSG_HDR_LEN || len > messages.len() {
+                    return Err(eyre!("Received a malformed netlink reply"));
+                }
+                let reply = &messages[NLMSG_HDR_LEN..len];
+                messages = &messages[align4(len).min(messages.len())..];
+                if seq != self.seq {
+                    continue;
+                }
+
+                match reply_type {
+                    NLMSG_ERROR => {
+                        let error = reply
+                            .get(..4)
+                            .map(|error| i32::from_ne_bytes(error.try_into().unwrap()))
+                            .ok_or_else(|| eyre!("Received a truncated netlink error"))?;
+                        if error != 0 {
+                            return Err(io::Error::from_raw_os_error(-error).into());
+                        }
+                        return Ok(Vec::new());
+                    }
+                    NLMSG_DONE => return Ok(Vec::new()),
+                    RTM_NEWLINK => return Ok(reply.to_vec()),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Looks up the XDP program attached to an interface, if any
+pub(crate) fn attached_xdp_program(if_index: u32) -> Result<Option<AttachedXdpProgram>> {
+    let mut socket = RouteSocket::open()?;
+    let reply = socket
+        .request(RTM_GETLINK, 0, &ifinfomsg(if_index))
+        .wrap_err_with(|| format!("failed to look up interface with index {if_index}"))?;
+    if reply.len() < IFINFOMSG_LEN {
+        return Err(eyre!(
+            "Received no link information for interface with index {}",
+            if_index
+        ));
+    }
+
+    let Some((_, xdp_attrs)) =
+        attrs(&reply[IFINFOMSG_LEN..]).find(|(attr_type, _)| *attr_type == IFLA_XDP)
+    else {
+        return Ok(None);
+    };
+
+    let mut attached = 0;
+    let mut prog_id = None;
+    let mut drv_prog_id = None;
+    let mut skb_prog_id = None;
+    let mut hw_prog_id = None;
+    for (attr_type, payload) in attrs(xdp_attrs) {
+        match attr_type {
+            IFLA_XDP_ATTACHED => attached = payload.first().copied().unwrap_or(0),
+            IFLA_XDP_PROG_ID => prog_id = read_u32(payload),
+            IFLA_XDP_DRV_PROG_ID => drv_prog_id = read_u32(payload),
+            IFLA_XDP_SKB_PROG_ID => skb_prog_id = read_u32(payload),
+            IFLA_XDP_HW_PROG_ID => hw_prog_id = read_u32(payload),
+            _ => {}
+        }
+    }
+
+    // With programs attached in several modes, the one running first on received frames is
+    // reported
+    let program = match attached {
+        XDP_ATTACHED_DRV => prog_id.map(|id| (id, XDP_FLAGS_DRV_MODE)),
+        XDP_ATTACHED_SKB => prog_id.map(|id| (id, XDP_FLAGS_SKB_MODE)),
+        XDP_ATTACHED_HW => prog_id.map(|id| (id, XDP_FLAGS_HW_MODE)),
+        XDP_ATTACHED_MULTI => hw_prog_id
+            .map(|id| (id, XDP_FLAGS_HW_MODE))
+            .or(drv_prog_id.map(|id| (id, XDP_FLAGS_DRV_MODE)))
+            .or(skb_prog_id.map(|id| (id, XDP_FLAGS_SKB_MODE))),
+        _ => None,
+    };
+
+    Ok(program.map(|(id, mode_flags)| AttachedXdpProgram { id, mode_flags }))
+}
+
+/// Attaches the program behind `prog_fd` to an interface, or detaches the interface's program
+/// if `prog_fd` is -1. `mode_flags` must match the mode of the program being detached.
+pub(crate) fn set_xdp_program(if_index: u32, prog_fd: RawFd, mode_flags: u32) -> Result<()> {
+    change_xdp_program(if_index, prog_fd, None, mode_flags)
+}
+
+/// Attaches the program behind `prog_fd` to an interface in place of the one behind
+/// `expected_fd`, in a single step so that no frame goes unhandled in between. Fails if the
+/// interface does not have the expected program attached in the mode given by `mode_flags`.
+pub(crate) fn replace_xdp_program(
+    if_index: u32,
+    prog_fd: RawFd,
+    expected_fd: RawFd,
+    mode_flags: u32,
+) -> Result<()> {
+    change_xdp_program(
+        if_index,
+        prog_fd,
+        Some(expected_fd),
+        mode_flags | XDP_FLAGS_REPLACE,
+    )
+}
+
+fn change_xdp_program(
+    if_index: u32,
+    prog_fd: RawFd,
+    expected_fd: Option<RawFd>,
+    flags: u32,
+) -> Result<()> {
+    let mut xdp_attrs = Vec::new();
+    push_attr(&mut xdp_attrs, IFLA_XDP_FD, &prog_fd.to_ne_bytes());
+    push_attr(&mut xdp_attrs, IFLA_XDP_FLAGS, &flags.to_ne_bytes());
+    if let Some(expected_fd) = expected_fd {
+        push_attr(
+            &mut xdp_attrs,
+            IFLA_XDP_EXPECTED_FD,
+            &expected_fd.to_ne_bytes(),
+        );
+    }
+
+    let mut payload = ifinfomsg(if_index);
+    push_attr(&mut payload, IFLA_XDP | NLA_F_NESTED, &xdp_attrs);
+
+    let mut socket = RouteSocket::open()?;
+    socket
+        .request(RTM_SETLINK, NLM_F_ACK, &payload)
+        .wrap_err_with(|| {
+            format!("failed to change the XDP program on interface with index {if_index}")
+        })?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+// Reads per-CPU eBPF hash maps through the bpf syscall, into buffers which are reused from tick to
+// tick. aya allocates the values of every entry it reads, which adds up to many allocations per
+// tick with many entries and CPUs. See https://docs.kernel.org/userspace-api/ebpf/syscall.html
+
+use std::{
+    cell::RefCell,
+    io, mem,
+    os::fd::{AsFd, AsRawFd},
+    ptr,
+};
+
+use aya::{maps::MapData, Pod};
+use color_eyre::eyre::{Context, Result};
+
+const BPF_MAP_LOOKUP_ELEM: libc::c_long = 1;
+const BPF_MAP_GET_NEXT_KEY: libc::c_long = 4;
+
+/// The bpf_attr fields of the map element commands, see include/uapi/linux/bpf.h
+#[repr(C)]
+struct MapElemAttr {
+    map_fd: u32,
+    key: u64,
+    // The value for lookups, or the next key when iterating
+    value: u64,
+    flags: u64,
+}
+
+/// Runs a map element command. Returns false if the map has no such element.
+fn map_elem(cmd: libc::c_long, map: &MapData, key: *const u8, value: *mut u8) -> Result<bool> {
+    let attr = MapElemAttr {
+        map_fd: map.fd().as_fd().as_raw_fd() as u32,
+        key: key as u64,
+        value: value as u64,
+        flags: 0,
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            cmd,
+            &attr as *const MapElemAttr,
+            mem::size_of::<MapElemAttr>(),
+        )
+    };
+    if result < 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::ENOENT) {
+            return Ok(false);
+        }
+        return Err(error).wrap_err("failed to read an eBPF map");
+    }
+    Ok(true)
+}
+
+/// Holds the values of a per-CPU map entry on each possible CPU, which is how many the kernel
+/// copies out
+pub(crate) struct PerCpuBuffer<V> {
+    values: RefCell<Vec<V>>,
+}
+
+impl<V: Pod> PerCpuBuffer<V> {
+    pub(crate) fn new() -> Result<Self> {
+        // The kernel lays the values out 8 bytes apart at least
+        const {
+            assert!(mem::size_of::<V>().is_multiple_of(8));
+        }
+        let num_cpus = aya::util::nr_cpus().map_err(|(_, error)| error)?;
+        Ok(Self {
+            // All zeros is a valid plain data value
+            values: RefCell::new(vec![unsafe { mem::zeroed() }; num_cpus]),
+        })
+    }
+
+    /// Calls `visit` with the values of the entry for `key`. Returns false if there is none.
+    pub(crate) fn get<K: Pod>(
+        &self,
+        map: &MapData,
+        key: &K,
+        visit: impl FnOnce(&mut [V]),
+    ) -> Result<bool> {
+        let mut values = self.values.borrow_mut();
+        let found = map_elem(
+            BPF_MAP_LOOKUP_ELEM,
+            map,
+            key as *const K as *const u8,
+            values.as_mut_ptr() as *mut u8,
+        )?;
+        if found {
+            visit(&mut values);
+        }
+        Ok(found)
+    }
+
+    /// Calls `visit` with every entry of the map, skipping those deleted in the meantime
+    pub(crate) fn for_each<K: Pod>(
+        &self,
+        map: &MapData,
+        mut visit: impl FnMut(&K, &mut [V]),
+    ) -> Result<()> {
+        let mut key: K = unsafe { mem::zeroed() };
+        // Passing no key gets the first one
+        let mut has_key = map_elem(
+            BPF_MAP_GET_NEXT_KEY,
+            map,
+            ptr::null(),
+            &mut key as *mut K as *mut u8,
+        )?;
+        while has_key {
+            self.get(map, &key, |values| visit(&key, values))?;
+            let mut next_key: K = unsafe { mem::zeroed() };
+            has_key = map_elem(
+                BPF_MAP_GET_NEXT_KEY,
+                map,
+                &key as *const K as *const u8,
+                &mut next_key as *mut K as *mut u8,
+            )?;
+            key = next_key;
+        }
+        Ok(())
+    }
+}
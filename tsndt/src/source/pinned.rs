@@ -0,0 +1,441 @@
+// Counters pinned to the BPF filesystem by a run with --pin, so that they outlive tsndt. The XDP
+// links are pinned too, which keeps xdp_tsndt attached and counting after exiting, until the next
+// run with --pin takes the counters over. Other runs can view the pinned counters in the meantime.
+//
+// XDP programs which xdp_tsndt takes the place of are pinned by every run, so that `tsndt cleanup`
+// can put them back if tsndt is killed before it does so itself.
+
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fs, io, mem,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+};
+
+use aya::{
+    maps::{IterableMap, Map, MapData, PerCpuHashMap},
+    programs::loaded_programs,
+};
+use color_eyre::eyre::{eyre, Context, Result};
+use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+};
+
+use super::{
+    ebpf::{clock_now_ns, XdpMode, XDP_PROGRAM_NAME},
+    host_interfaces, netlink,
+    per_cpu::PerCpuBuffer,
+    DataSource,
+};
+use crate::settings::InterfaceFilter;
+
+/// Directory in the BPF filesystem which tsndt pins to
+pub(crate) const PIN_DIR: &str = "/sys/fs/bpf/tsndt";
+
+/// Names of the eBPF maps which are pinned
+pub(crate) const PINNED_INTERFACE_COUNTERS: &str = "IF_RX_COUNT";
+pub(crate) const PINNED_MAC_COUNTERS: &str = "SMAC_RX_COUNT";
+
+pub(crate) fn map_pin_path(map_name: &str) -> PathBuf {
+    Path::new(PIN_DIR).join(map_name)
+}
+
+/// Where a run without --pin has its counters pinned while the eBPF object loads, since the
+/// loader pins every map that the object declares as pinned. Removed once loaded.
+pub(crate) fn loading_pin_dir() -> PathBuf {
+    Path::new(PIN_DIR)
+        .join("loading")
+        .join(std::process::id().to_string())
+}
+
+pub(crate) fn links_pin_dir() -> PathBuf {
+    Path::new(PIN_DIR).join("links")
+}
+
+/// Where the XDP link attaching xdp_tsndt to an interface is pinned
+pub(crate) fn link_pin_path(interface_name: &str) -> PathBuf {
+    links_pin_dir().join(interface_name)
+}
+
+fn displaced_pin_dir() -> PathBuf {
+    Path::new(PIN_DIR).join("displaced")
+}
+
+/// Where the XDP program displaced from an interface is pinned, under the mode it was attached in
+pub(crate) fn displaced_pin_path(interface_name: &str, xdp_mode: XdpMode) -> PathBuf {
+    displaced_pin_dir()
+        .join(xdp_mode.to_string())
+        .join(interface_name)
+}
+
+const BPF_OBJ_PIN: libc::c_long = 6;
+const BPF_OBJ_GET: libc::c_long = 7;
+
+/// The bpf_attr fields of the BPF_OBJ_PIN and BPF_OBJ_GET commands, see include/uapi/linux/bpf.h
+#[repr(C)]
+struct ObjAttr {
+    pathname: u64,
+    bpf_fd: u32,
+    file_flags: u32,
+}
+
+fn bpf_obj(cmd: libc::c_long, path: &Path, bpf_fd: u32) -> io::Result<libc::c_long> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let attr = ObjAttr {
+        pathname: path.as_ptr() as u64,
+        bpf_fd,
+        file_flags: 0,
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_bpf,
+            cmd,
+            &attr as *const ObjAttr,
+            mem::size_of::<ObjAttr>(),
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(result)
+}
+
+/// Pins the eBPF program behind `fd`, which aya only does for the programs it loaded
+pub(crate) fn pin_program(fd: BorrowedFd<'_>, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .wrap_err_with(|| format!("failed to create the pin directory {}", parent.display()))?;
+    }
+    bpf_obj(BPF_OBJ_PIN, path, fd.as_raw_fd() as u32)
+        .wrap_err_with(|| format!("failed to pin the eBPF program to {}", path.display()))?;
+    Ok(())
+}
+
+fn open_pinned_program(path: &Path) -> Result<OwnedFd> {
+    let raw_fd = bpf_obj(BPF_OBJ_GET, path, 0)
+        .wrap_err_with(|| format!("failed to open {}", path.display()))?;
+    Ok(unsafe { OwnedFd::from_raw_fd(raw_fd as i32) })
+}
+
+/// Puts back the XDP programs displaced by runs that did not get to do it themselves, leaving
+/// pinned the ones which could not be. Returns whether any are left.
+pub(crate) fn restore_displaced_programs(interfaces: &[NetworkInterface]) -> Result<bool> {
+    let displaced_dir = displaced_pin_dir();
+    if !displaced_dir.exists() {
+        return Ok(false);
+    }
+    let mut any_left = false;
+    for mode_entry in fs::read_dir(&displaced_dir)? {
+        let mode_entry = mode_entry?;
+        let Ok(xdp_mode) = mode_entry.file_name().to_string_lossy().parse::<XdpMode>() else {
+            continue;
+        };
+        for entry in fs::read_dir(mode_entry.path())? {
+            let path = entry?.path();
+            let interface_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            match restore_displaced_program(&path, &interface_name, xdp_mode, interfaces) {
+                Ok(()) => {
+                    fs::remove_file(&path)
+                        .wrap_err_with(|| format!("failed to remove {}", path.display()))?;
+                    println!("Put back the XDP program displaced from {}", interface_name);
+                }
+                Err(report) => {
+                    any_left = true;
+                    println!(
+                        "Could not put back the XDP program displaced from {}: {:#}",
+                        interface_name, report
+                    );
+                }
+            }
+        }
+    }
+    Ok(any_left)
+}
+
+fn restore_displaced_program(
+    path: &Path,
+    interface_name: &str,
+    xdp_mode: XdpMode,
+    interfaces: &[NetworkInterface],
+) -> Result<()> {
+    let if_index = interfaces
+        .iter()
+        .find(|interface| interface.name == interface_name)
+        .ok_or_else(|| eyre!("Could not find interface {}", interface_name))?
+        .index;
+    let fd = open_pinned_program(path)?;
+    let Some(attached) = netlink::attached_xdp_program(if_index)? else {
+        return netlink::set_xdp_program(if_index, fd.as_raw_fd(), xdp_mode.mode_flags());
+    };
+    let xdp_tsndt = loaded_programs()
+        .filter_map(|program_info| program_info.ok())
+        .find(|program_info| {
+            program_info.id() == attached.id && program_info.name_as_str() == Some(XDP_PROGRAM_NAME)
+        })
+        .ok_or_else(|| eyre!("XDP program {} is attached in its place", attached.id))?;
+    netlink::replace_xdp_program(
+        if_index,
+        fd.as_raw_fd(),
+        xdp_tsndt.fd()?.as_fd().as_raw_fd(),
+        xdp_mode.mode_flags(),
+    )
+}
+
+/// Opens a pinned per-CPU hash map, which is also how the LRU ones are read
+pub(crate) fn open_pinned_map(map_name: &str) -> Result<Map> {
+    let path = map_pin_path(map_name);
+    let map_data = MapData::from_pin(&path).wrap_err_with(|| {
+        format!(
+            "failed to open {}, which a run with --pin creates",
+            path.display()
+        )
+    })?;
+    Ok(Map::PerCpuHashMap(map_data))
+}
+
+/// Puts back the XDP programs displaced by killed runs, removes the pins left behind by runs with
+/// --pin, which detaches the XDP links that they pinned, and detaches xdp_tsndt from interfaces
+/// that it was left attached to without a link
+pub(crate) fn cleanup() -> Result<()> {
+    let interfaces = NetworkInterface::show()?;
+    let pin_dir = Path::new(PIN_DIR);
+    if pin_dir.exists() {
+        let any_displaced_left = restore_displaced_programs(&interfaces)?;
+        // Unpinning drops the last reference to a pinned link, unless a running tsndt holds one
+        for entry in fs::read_dir(pin_dir)? {
+            let path = entry?.path();
+            if any_displaced_left && path == displaced_pin_dir() {
+                continue;
+            }
+            fs::remove_dir_all(&path)
+                .or_else(|_| fs::remove_file(&path))
+                .wrap_err_with(|| format!("failed to remove {}", path.display()))?;
+        }
+        if any_displaced_left {
+            println!(
+                "Removed the pins under {}, except for the XDP programs under {}",
+                pin_dir.display(),
+                displaced_pin_dir().display()
+            );
+        } else {
+            fs::remove_dir(pin_dir)
+                .wrap_err_with(|| format!("failed to remove {}", pin_dir.display()))?;
+            println!("Removed the pins under {}", pin_dir.display());
+        }
+    }
+
+    detach_unlinked_xdp_tsndt(&interfaces, |interface, result| match result {
+        Ok(()) => println!("Detached {} from {}", XDP_PROGRAM_NAME, interface.name),
+        // Links held by a running tsndt cannot be detached through netlink
+        Err(report) => println!(
+            "Could not detach {} from {}: {:#}",
+            XDP_PROGRAM_NAME, interface.name, report
+        ),
+    })
+}
+
+/// Detaches xdp_tsndt from the interfaces that it is attached to without a link, as on kernels
+/// older than 5.9 or after taking the place of another XDP program, since it would otherwise stay
+/// attached after exiting. Each attempt is handed to `on_detach`.
+pub(crate) fn detach_unlinked_xdp_tsndt(
+    interfaces: &[NetworkInterface],
+    mut on_detach: impl FnMut(&NetworkInterface, Result<()>),
+) -> Result<()> {
+    for interface in interfaces {
+        let Some(attached) = netlink::attached_xdp_program(interface.index)? else {
+            continue;
+        };
+        let is_xdp_tsndt = loaded_programs()
+            .filter_map(|program_info| program_info.ok())
+            .any(|program_info| {
+                program_info.id() == attached.id
+                    && program_info.name_as_str() == Some(XDP_PROGRAM_NAME)
+            });
+        if !is_xdp_tsndt {
+            continue;
+        }
+        on_detach(
+            interface,
+            netlink::set_xdp_program(interface.index, -1, attached.mode_flags),
+        );
+    }
+    Ok(())
+}
+
+/// Views the counters pinned by a run with --pin, without attaching anything or changing them
+pub(crate) struct PinnedSource {
+    interfaces: Vec<NetworkInterface>,
+    interface_rx_counters: PerCpuHashMap<MapData, u32, Counter>,
+    src_mac_rx_counters: PerCpuHashMap<MapData, [u8; 6], Counter>,
+    // The pinned counters of MAC addresses at the time they were removed from view, since the
+    // pinned map is left as it is
+    removed_mac_counters: HashMap<[u8; 6], Vec<Counter>>,
+    counter_buffer: PerCpuBuffer<Counter>,
+}
+
+impl PinnedSource {
+    pub(crate) fn open(interface_filter: &InterfaceFilter) -> Result<Self> {
+        let interfaces = host_interfaces(interface_filter)?;
+
+        Ok(Self {
+            interfaces,
+            interface_rx_counters: PerCpuHashMap::try_from(open_pinned_map(
+                PINNED_INTERFACE_COUNTERS,
+            )?)?,
+            src_mac_rx_counters: PerCpuHashMap::try_from(open_pinned_map(PINNED_MAC_COUNTERS)?)?,
+            removed_mac_counters: HashMap::new(),
+            counter_buffer: PerCpuBuffer::new()?,
+        })
+    }
+}
+
+impl DataSource for PinnedSource {
+    fn interfaces(&self) -> &[NetworkInterface] {
+        &self.interfaces
+    }
+
+    fn collection_backend(&self, if_index: u32) -> Option<String> {
+        let interface = self
+            .interfaces
+            .iter()
+            .find(|interface| interface.index == if_index)?;
+        link_pin_path(&interface.name)
+            .exists()
+            .then(|| String::from("pinned"))
+    }
+
+    fn attach(&mut self, _interface: &NetworkInterface) -> Result<()> {
+        Err(eyre!(
+            "Pinned counters are only viewed, collection is up to the run with --pin"
+        ))
+    }
+
+    fn detach(&mut self, _interface: &NetworkInterface) -> Result<()> {
+        Err(eyre!(
+            "Pinned counters are only viewed, collection is up to the run with --pin"
+        ))
+    }
+
+    fn interface_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()> {
+        let found =
+            self.counter_buffer
+                .get(self.interface_rx_counters.map(), &if_index, |values| {
+                    visit(values)
+                })?;
+        if !found {
+            visit(&[]);
+        }
+        Ok(())
+    }
+
+    fn rx_queue_counters(&self, _visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()> {
+        Ok(())
+    }
+
+    fn burst_counters(
+        &self,
+        _if_index: u32,
+        _visit: &mut dyn FnMut(&[BurstCounter]),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_burst_epoch(&mut self, _epoch: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn inter_arrival_histograms(
+        &self,
+        _if_index: u32,
+        _visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()> {
+        self.counter_buffer
+            .for_each(self.src_mac_rx_counters.map(), |src_mac, values| {
+                // A removed MAC address shows up again with the traffic since its removal, once
+                // there is some
+                if let Some(removed) = self.removed_mac_counters.get(src_mac) {
+                    let idle = values
+                        .iter()
+                        .zip(removed)
+                        .all(|(counter, removed)| counter.packets == removed.packets);
+                    if idle {
+                        return;
+                    }
+                    for (counter, removed) in values.iter_mut().zip(removed) {
+                        counter.packets = counter.packets.saturating_sub(removed.packets);
+                        counter.bytes = counter.bytes.saturating_sub(removed.bytes);
+                    }
+                }
+                visit(src_mac, values);
+            })
+    }
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()> {
+        match self.src_mac_rx_counters.get(src_mac, 0) {
+            Ok(values) => {
+                self.removed_mac_counters.insert(*src_mac, values.to_vec());
+            }
+            Err(_) => {
+                self.removed_mac_counters.remove(src_mac);
+            }
+        }
+        Ok(())
+    }
+
+    fn fragment_counters(
+        &self,
+        _if_index: u32,
+        _visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn hop_limit_counts(&self, _visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()> {
+        Ok(())
+    }
+
+    fn multicast_group_counters(
+        &self,
+        _visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove_multicast_group_counters(&mut self, _key: &MulticastGroupKey) -> Result<()> {
+        Ok(())
+    }
+
+    fn multicast_members(
+        &self,
+        _visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove_multicast_member(&mut self, _key: &MulticastMemberKey) -> Result<()> {
+        Ok(())
+    }
+
+    fn packet_samples(&mut self, _visit: &mut dyn FnMut(&PacketSample)) {}
+
+    fn now_ns(&self) -> u64 {
+        clock_now_ns(libc::CLOCK_MONOTONIC)
+    }
+
+    fn realtime_offset_ns(&self) -> u64 {
+        clock_now_ns(libc::CLOCK_REALTIME) - clock_now_ns(libc::CLOCK_MONOTONIC)
+    }
+}
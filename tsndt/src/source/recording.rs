@@ -0,0 +1,186 @@
+use std::path::Path;
+
+use color_eyre::eyre::{Context, Result};
+use network_interface::NetworkInterface;
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+};
+
+use super::{
+    ebpf::XdpMode,
+    session::{Frame, SessionWriter},
+    DataSource,
+};
+use crate::keymap::{Action, Keymap};
+
+/// Records every tick of another data source to a session file, while passing everything through
+/// to the contexts unchanged
+pub(crate) struct RecordingSource {
+    inner: Box<dyn DataSource>,
+    writer: SessionWriter,
+    burst_epoch: u64,
+    // Packets sampled during the latest tick, which are taken from the inner source to be
+    // recorded before the contexts take them
+    packet_samples: Vec<PacketSample>,
+}
+
+impl RecordingSource {
+    /// Starts recording to `path`, beginning with the counters that the contexts start from
+    pub(crate) fn create(
+        inner: Box<dyn DataSource>,
+        path: &Path,
+        tick_rate_ms: u64,
+    ) -> Result<Self> {
+        let mut writer = SessionWriter::create(path, inner.interfaces(), tick_rate_ms)?;
+        writer.write_frame(&Frame::capture(inner.as_ref(), 0, &[])?)?;
+        tracing::info!("Recording the session to {}", path.display());
+
+        Ok(Self {
+            inner,
+            writer,
+            burst_epoch: 0,
+            packet_samples: Vec::new(),
+        })
+    }
+}
+
+impl DataSource for RecordingSource {
+    fn interfaces(&self) -> &[NetworkInterface] {
+        self.inner.interfaces()
+    }
+
+    fn on_tick(&mut self) -> Result<bool> {
+        if !self.inner.on_tick()? {
+            return Ok(false);
+        }
+
+        self.packet_samples.clear();
+        self.inner
+            .packet_samples(&mut |sample| self.packet_samples.push(*sample));
+        let frame = Frame::capture(self.inner.as_ref(), self.burst_epoch, &self.packet_samples)?;
+        self.writer.write_frame(&frame).wrap_err_with(|| {
+            format!(
+                "Failed to record to session file {}",
+                self.writer.path().display()
+            )
+        })?;
+
+        Ok(true)
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        self.inner.handle_action(action)
+    }
+
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String> {
+        let mut command_help = self.inner.get_command_help(keymap);
+        command_help.push(format!(
+            "Recording to {} ({} ticks)",
+            self.writer.path().display(),
+            self.writer.num_frames()
+        ));
+        command_help
+    }
+
+    fn collection_backend(&self, if_index: u32) -> Option<String> {
+        self.inner.collection_backend(if_index)
+    }
+
+    fn xdp_mode(&self, interface: &NetworkInterface) -> Option<XdpMode> {
+        self.inner.xdp_mode(interface)
+    }
+
+    fn set_xdp_mode(&mut self, interface: &NetworkInterface, xdp_mode: XdpMode) -> Result<()> {
+        self.inner.set_xdp_mode(interface, xdp_mode)
+    }
+
+    fn attach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        self.inner.attach(interface)
+    }
+
+    fn detach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        self.inner.detach(interface)
+    }
+
+    fn interface_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()> {
+        self.inner.interface_counters(if_index, visit)
+    }
+
+    fn rx_queue_counters(&self, visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()> {
+        self.inner.rx_queue_counters(visit)
+    }
+
+    fn burst_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[BurstCounter])) -> Result<()> {
+        self.inner.burst_counters(if_index, visit)
+    }
+
+    fn set_burst_epoch(&mut self, epoch: u64) -> Result<()> {
+        self.burst_epoch = epoch;
+        self.inner.set_burst_epoch(epoch)
+    }
+
+    fn inter_arrival_histograms(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()> {
+        self.inner.inter_arrival_histograms(if_index, visit)
+    }
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()> {
+        self.inner.mac_counters(visit)
+    }
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()> {
+        self.inner.remove_mac_counters(src_mac)
+    }
+
+    fn fragment_counters(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()> {
+        self.inner.fragment_counters(if_index, visit)
+    }
+
+    fn hop_limit_counts(&self, visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()> {
+        self.inner.hop_limit_counts(visit)
+    }
+
+    fn multicast_group_counters(
+        &self,
+        visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()> {
+        self.inner.multicast_group_counters(visit)
+    }
+
+    fn remove_multicast_group_counters(&mut self, key: &MulticastGroupKey) -> Result<()> {
+        self.inner.remove_multicast_group_counters(key)
+    }
+
+    fn multicast_members(
+        &self,
+        visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()> {
+        self.inner.multicast_members(visit)
+    }
+
+    fn remove_multicast_member(&mut self, key: &MulticastMemberKey) -> Result<()> {
+        self.inner.remove_multicast_member(key)
+    }
+
+    fn packet_samples(&mut self, visit: &mut dyn FnMut(&PacketSample)) {
+        for sample in self.packet_samples.drain(..) {
+            visit(&sample);
+        }
+    }
+
+    fn now_ns(&self) -> u64 {
+        self.inner.now_ns()
+    }
+
+    fn realtime_offset_ns(&self) -> u64 {
+        self.inner.realtime_offset_ns()
+    }
+}
@@ -0,0 +1,293 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Result};
+use network_interface::NetworkInterface;
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+};
+
+use super::{
+    capture_file::{CaptureReader, CapturedPacket, LINKTYPE_ETHERNET},
+    counting::CountingEngine,
+    memory::MemorySource,
+    DataSource,
+};
+use crate::{
+    keymap::{Action, Keymap},
+    settings::Settings,
+};
+
+const MIN_REPLAY_SPEED: f64 = 1.0 / 64.0;
+const MAX_REPLAY_SPEED: f64 = 1024.0;
+
+/// Feeds the frames of a pcap or pcapng file into an in-memory source, paced by the capture
+/// timestamps
+pub(crate) struct ReplaySource {
+    path: PathBuf,
+    reader: CaptureReader,
+    // Whether each capture interface carries Ethernet frames, which are the only ones counted
+    ethernet_interfaces: Vec<bool>,
+    memory: MemorySource,
+    next_packet: Option<CapturedPacket>,
+    replay_time_ns: Option<u64>,
+    speed: f64,
+    paused: bool,
+    pending_steps: u32,
+    tick_rate_ms: u64,
+}
+
+/// Capture interfaces are numbered from 1, since an interface index of 0 is never valid
+fn capture_if_index(interface_id: usize) -> u32 {
+    interface_id as u32 + 1
+}
+
+impl ReplaySource {
+    /// Opens a capture for replay at `speed` times real time, or one tick at a time if `paused`
+    pub(crate) fn open(path: &Path, speed: f64, paused: bool, settings: &Settings) -> Result<Self> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(eyre!("Replay speed must be positive, got {}", speed));
+        }
+
+        // pcapng files can describe interfaces anywhere before their first packet, so the whole
+        // file is scanned for them up front
+        let mut scanner = CaptureReader::open(path)?;
+        while scanner.next_packet()?.is_some() {}
+        let default_name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("capture"));
+        let mut interfaces = Vec::new();
+        let mut ethernet_interfaces = Vec::new();
+        for (interface_id, capture_interface) in scanner.interfaces().iter().enumerate() {
+            let name = capture_interface
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{default_name}{interface_id}"));
+            if capture_interface.link_type != LINKTYPE_ETHERNET {
+                tracing::warn!(
+                    "Skipping frames on capture interface {} with unsupported link type {}",
+                    name,
+                    capture_interface.link_type
+                );
+            }
+            ethernet_interfaces.push(capture_interface.link_type == LINKTYPE_ETHERNET);
+            interfaces.push(NetworkInterface {
+                name,
+                addr: Vec::new(),
+                mac_addr: None,
+                index: capture_if_index(interface_id),
+                internal: false,
+            });
+        }
+
+        let memory = MemorySource::new(
+            interfaces,
+            CountingEngine::new(settings.burst_bucket_ns, settings.sample_rate),
+        );
+
+        let mut reader = CaptureReader::open(path)?;
+        let next_packet = reader.next_packet()?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            reader,
+            ethernet_interfaces,
+            memory,
+            next_packet,
+            replay_time_ns: None,
+            speed,
+            paused,
+            pending_steps: 0,
+            tick_rate_ms: settings.tick_rate_ms,
+        })
+    }
+
+    fn toggle_paused(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Advances a paused replay by a single tick
+    fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    fn change_speed(&mut self, factor: f64) {
+        self.speed = (self.speed * factor).clamp(MIN_REPLAY_SPEED, MAX_REPLAY_SPEED);
+    }
+}
+
+impl DataSource for ReplaySource {
+    fn interfaces(&self) -> &[NetworkInterface] {
+        self.memory.interfaces()
+    }
+
+    /// Replays the frames captured during the next tick. Returns false if the replay is paused
+    /// and the contexts should not tick.
+    fn on_tick(&mut self) -> Result<bool> {
+        if self.paused {
+            if self.pending_steps == 0 {
+                return Ok(false);
+            }
+            self.pending_steps -= 1;
+        }
+
+        let tick_ns = self.tick_rate_ms * 1_000_000;
+        let advance_ns = if self.paused {
+            tick_ns
+        } else {
+            (tick_ns as f64 * self.speed) as u64
+        };
+
+        // The replay clock starts at the first captured frame
+        let replay_time_ns = match (self.replay_time_ns, &self.next_packet) {
+            (Some(replay_time_ns), _) => replay_time_ns + advance_ns,
+            (None, Some(packet)) => packet.timestamp_ns + advance_ns,
+            (None, None) => advance_ns,
+        };
+        self.replay_time_ns = Some(replay_time_ns);
+        self.memory.set_now_ns(replay_time_ns);
+
+        while let Some(packet) = self.next_packet.take() {
+            if packet.timestamp_ns > replay_time_ns {
+                self.next_packet = Some(packet);
+                break;
+            }
+            let is_ethernet = self
+                .ethernet_interfaces
+                .get(packet.interface_id)
+                .copied()
+                .unwrap_or(false);
+            if is_ethernet {
+                self.memory.engine.count_frame(
+                    capture_if_index(packet.interface_id),
+                    0,
+                    packet.timestamp_ns,
+                    &packet.data,
+                    packet.original_len,
+                );
+            }
+            self.next_packet = self.reader.next_packet()?;
+        }
+
+        Ok(true)
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::TogglePause => self.toggle_paused(),
+            Action::Step => self.step(),
+            Action::Slower => self.change_speed(0.5),
+            Action::Faster => self.change_speed(2.0),
+            _ => {}
+        }
+    }
+
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String> {
+        let state = if self.next_packet.is_none() {
+            String::from("finished")
+        } else if self.paused {
+            String::from("paused")
+        } else {
+            format!("playing at {}x", self.speed)
+        };
+        vec![format!(
+            "Replaying {} ({state}): {}",
+            self.path.display(),
+            keymap.help(&[
+                (&[Action::TogglePause], "Pause/resume"),
+                (&[Action::Step], "Step one tick"),
+                (&[Action::Slower, Action::Faster], "Slower/faster"),
+            ])
+        )]
+    }
+
+    fn attach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        self.memory.attach(interface)
+    }
+
+    fn detach(&mut self, interface: &NetworkInterface) -> Result<()> {
+        self.memory.detach(interface)
+    }
+
+    fn interface_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()> {
+        self.memory.interface_counters(if_index, visit)
+    }
+
+    fn rx_queue_counters(&self, visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()> {
+        self.memory.rx_queue_counters(visit)
+    }
+
+    fn burst_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[BurstCounter])) -> Result<()> {
+        self.memory.burst_counters(if_index, visit)
+    }
+
+    fn set_burst_epoch(&mut self, epoch: u64) -> Result<()> {
+        self.memory.set_burst_epoch(epoch)
+    }
+
+    fn inter_arrival_histograms(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()> {
+        self.memory.inter_arrival_histograms(if_index, visit)
+    }
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()> {
+        self.memory.mac_counters(visit)
+    }
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()> {
+        self.memory.remove_mac_counters(src_mac)
+    }
+
+    fn fragment_counters(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()> {
+        self.memory.fragment_counters(if_index, visit)
+    }
+
+    fn hop_limit_counts(&self, visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()> {
+        self.memory.hop_limit_counts(visit)
+    }
+
+    fn multicast_group_counters(
+        &self,
+        visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()> {
+        self.memory.multicast_group_counters(visit)
+    }
+
+    fn remove_multicast_group_counters(&mut self, key: &MulticastGroupKey) -> Result<()> {
+        self.memory.remove_multicast_group_counters(key)
+    }
+
+    fn multicast_members(
+        &self,
+        visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()> {
+        self.memory.multicast_members(visit)
+    }
+
+    fn remove_multicast_member(&mut self, key: &MulticastMemberKey) -> Result<()> {
+        self.memory.remove_multicast_member(key)
+    }
+
+    fn packet_samples(&mut self, visit: &mut dyn FnMut(&PacketSample)) {
+        self.memory.packet_samples(visit)
+    }
+
+    /// Capture time that the replay has reached
+    fn now_ns(&self) -> u64 {
+        self.memory.now_ns()
+    }
+
+    fn realtime_offset_ns(&self) -> u64 {
+        self.memory.realtime_offset_ns()
+    }
+}
@@ -0,0 +1,1147 @@
+// Sessions recorded with --record hold a snapshot of everything that the contexts read from the
+// data source at each tick, so that they can be played back later without root or a kernel. The
+// file starts with a header describing the interfaces, followed by one length-prefixed frame per
+// tick. Integers are written as LEB128 varints, which keeps the mostly zero counters small.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs::File,
+    hash::Hash,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{eyre, Context, Result};
+use network_interface::NetworkInterface;
+use tsndt_common::{
+    BurstCounter, Counter, HopLimitKey, InterArrivalHistogram, IpFragmentCounter,
+    MulticastGroupKey, MulticastMemberKey, MulticastMembership, PacketSample, RxQueueKey,
+    INTER_ARRIVAL_NUM_BUCKETS, PACKET_SAMPLE_MAX_LEN,
+};
+
+use super::DataSource;
+use crate::keymap::{Action, Keymap};
+
+const MAGIC: &[u8; 8] = b"TSNDTSES";
+const VERSION: u64 = 1;
+const MIN_PLAYBACK_SPEED: f64 = 1.0 / 64.0;
+const MAX_PLAYBACK_SPEED: f64 = 1024.0;
+// How far the seek keys jump through the recording
+const SEEK_SEC: u64 = 10;
+
+/// Identifies multicast group counters by interface index and group address
+type GroupId = (u32, [u8; 16]);
+/// Identifies a multicast group member by interface index, group address and reporter address
+type MemberId = (u32, [u8; 16], [u8; 16]);
+
+/// Tells whether a file starts like a recorded session rather than a capture
+pub(crate) fn is_session_file(path: &Path) -> bool {
+    let mut magic = [0u8; MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && &magic == MAGIC
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn put_list<T>(buf: &mut Vec<u8>, items: &[T], mut put_item: impl FnMut(&mut Vec<u8>, &T)) {
+    put_varint(buf, items.len() as u64);
+    for item in items {
+        put_item(buf, item);
+    }
+}
+
+fn put_counter(buf: &mut Vec<u8>, counter: &Counter) {
+    put_varint(buf, counter.bytes);
+    put_varint(buf, counter.packets as u64);
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = self
+                .data
+                .split_first()
+                .ok_or_else(|| eyre!("The session is truncated"))?;
+            self.data = rest;
+            if shift >= u64::BITS {
+                return Err(eyre!("The session has an integer which is too large"));
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        u32::try_from(self.varint()?)
+            .map_err(|_| eyre!("The session has an integer which is too large"))
+    }
+
+    /// Reads the length of something which takes at least a byte per element, so that a corrupt
+    /// length cannot ask for more than what is left
+    fn len(&mut self) -> Result<usize> {
+        let len = self.varint()?;
+        if len > self.data.len() as u64 {
+            return Err(eyre!("The session is truncated"));
+        }
+        Ok(len as usize)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.len()?;
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.data.len() < N {
+            return Err(eyre!("The session is truncated"));
+        }
+        let (bytes, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let len = self.len()?;
+        let mut items = Vec::with_capacity(len);
+        for _ in 0..len {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn counter(&mut self) -> Result<Counter> {
+        Ok(Counter {
+            bytes: self.varint()?,
+            packets: self.u32()?,
+        })
+    }
+}
+
+/// Everything that the contexts read from a data source at a tick, with the per-CPU values as the
+/// source returned them
+#[derive(Clone, Default)]
+pub(super) struct Frame {
+    now_ns: u64,
+    realtime_offset_ns: u64,
+    interface_counters: Vec<(u32, Vec<Counter>)>,
+    // Peak packets and bytes of the current microburst read epoch, or 0 for older epochs
+    burst_peaks: Vec<(u32, Vec<(u64, u64)>)>,
+    inter_arrival_histograms: Vec<(u32, Vec<InterArrivalHistogram>)>,
+    fragment_counters: Vec<(u32, Vec<IpFragmentCounter>)>,
+    backends: Vec<(u32, String)>,
+    rx_queue_counters: Vec<((u32, u32), Vec<Counter>)>,
+    mac_counters: Vec<([u8; 6], Vec<Counter>)>,
+    hop_limit_counts: Vec<((u32, u32), Vec<u64>)>,
+    multicast_group_counters: Vec<(GroupId, Vec<Counter>)>,
+    multicast_members: Vec<(MemberId, MulticastMembership)>,
+    packet_samples: Vec<PacketSample>,
+}
+
+impl Frame {
+    /// Snapshots a source, given the microburst epoch which the contexts last set and the
+    /// packets sampled since the previous tick
+    pub(super) fn capture(
+        source: &dyn DataSource,
+        burst_epoch: u64,
+        packet_samples: &[PacketSample],
+    ) -> Result<Self> {
+        let mut frame = Frame {
+            now_ns: source.now_ns(),
+            realtime_offset_ns: source.realtime_offset_ns(),
+            packet_samples: packet_samples.to_vec(),
+            ..Default::default()
+        };
+
+        for interface in source.interfaces() {
+            let if_index = interface.index;
+            source.interface_counters(if_index, &mut |counters| {
+                frame.interface_counters.push((if_index, counters.to_vec()))
+            })?;
+            let mut burst_peaks = Vec::new();
+            source.burst_counters(if_index, &mut |bursts| {
+                burst_peaks.extend(bursts.iter().map(|burst| match burst.epoch == burst_epoch {
+                    true => (burst.peak_packets, burst.peak_bytes),
+                    false => (0, 0),
+                }))
+            })?;
+            frame.burst_peaks.push((if_index, burst_peaks));
+            let mut histograms = Vec::new();
+            source.inter_arrival_histograms(if_index, &mut |recorded| {
+                histograms.extend_from_slice(recorded)
+            })?;
+            frame.inter_arrival_histograms.push((if_index, histograms));
+            let mut fragment_counters = Vec::new();
+            source.fragment_counters(if_index, &mut |counters| {
+                fragment_counters.extend_from_slice(counters)
+            })?;
+            frame.fragment_counters.push((if_index, fragment_counters));
+            if let Some(backend) = source.collection_backend(if_index) {
+                frame.backends.push((if_index, backend));
+            }
+        }
+
+        source.rx_queue_counters(&mut |key, counters| {
+            frame
+                .rx_queue_counters
+                .push(((key.if_index, key.rx_queue), counters.to_vec()))
+        })?;
+        source.mac_counters(&mut |src_mac, counters| {
+            frame.mac_counters.push((*src_mac, counters.to_vec()))
+        })?;
+        source.hop_limit_counts(&mut |key, counts| {
+            frame
+                .hop_limit_counts
+                .push(((key.if_index, key.hop_limit), counts.to_vec()))
+        })?;
+        source.multicast_group_counters(&mut |key, counters| {
+            frame
+                .multicast_group_counters
+                .push(((key.if_index, key.group), counters.to_vec()))
+        })?;
+        source.multicast_members(&mut |key, membership| {
+            frame
+                .multicast_members
+                .push(((key.if_index, key.group, key.reporter), *membership))
+        })?;
+
+        Ok(frame)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_varint(&mut buf, self.now_ns);
+        put_varint(&mut buf, self.realtime_offset_ns);
+        put_list(
+            &mut buf,
+            &self.interface_counters,
+            |buf, (if_index, counters)| {
+                put_varint(buf, *if_index as u64);
+                put_list(buf, counters, put_counter);
+            },
+        );
+        put_list(&mut buf, &self.burst_peaks, |buf, (if_index, peaks)| {
+            put_varint(buf, *if_index as u64);
+            put_list(buf, peaks, |buf, (packets, bytes)| {
+                put_varint(buf, *packets);
+                put_varint(buf, *bytes);
+            });
+        });
+        put_list(
+            &mut buf,
+            &self.inter_arrival_histograms,
+            |buf, (if_index, histograms)| {
+                put_varint(buf, *if_index as u64);
+                put_list(buf, histograms, |buf, histogram| {
+                    for bucket in histogram.buckets {
+                        put_varint(buf, bucket);
+                    }
+                });
+            },
+        );
+        put_list(
+            &mut buf,
+            &self.fragment_counters,
+            |buf, (if_index, counters)| {
+                put_varint(buf, *if_index as u64);
+                put_list(buf, counters, |buf, counter| {
+                    put_varint(buf, counter.ipv4_first);
+                    put_varint(buf, counter.ipv4_middle);
+                    put_varint(buf, counter.ipv4_last);
+                    put_varint(buf, counter.ipv6);
+                });
+            },
+        );
+        put_list(&mut buf, &self.backends, |buf, (if_index, backend)| {
+            put_varint(buf, *if_index as u64);
+            put_bytes(buf, backend.as_bytes());
+        });
+        put_list(
+            &mut buf,
+            &self.rx_queue_counters,
+            |buf, ((if_index, rx_queue), counters)| {
+                put_varint(buf, *if_index as u64);
+                put_varint(buf, *rx_queue as u64);
+                put_list(buf, counters, put_counter);
+            },
+        );
+        put_list(&mut buf, &self.mac_counters, |buf, (src_mac, counters)| {
+            buf.extend_from_slice(src_mac);
+            put_list(buf, counters, put_counter);
+        });
+        put_list(
+            &mut buf,
+            &self.hop_limit_counts,
+            |buf, ((if_index, hop_limit), counts)| {
+                put_varint(buf, *if_index as u64);
+                put_varint(buf, *hop_limit as u64);
+                put_list(buf, counts, |buf, count| put_varint(buf, *count));
+            },
+        );
+        put_list(
+            &mut buf,
+            &self.multicast_group_counters,
+            |buf, ((if_index, group), counters)| {
+                put_varint(buf, *if_index as u64);
+                buf.extend_from_slice(group);
+                put_list(buf, counters, put_counter);
+            },
+        );
+        put_list(
+            &mut buf,
+            &self.multicast_members,
+            |buf, ((if_index, group, reporter), membership)| {
+                put_varint(buf, *if_index as u64);
+                buf.extend_from_slice(group);
+                buf.extend_from_slice(reporter);
+                put_varint(buf, membership.last_report_ns);
+                put_varint(buf, membership.report_count as u64);
+                put_varint(buf, membership.report_kind as u64);
+                put_varint(buf, membership.state as u64);
+            },
+        );
+        put_list(&mut buf, &self.packet_samples, |buf, sample| {
+            put_varint(buf, sample.timestamp_ns);
+            put_varint(buf, sample.if_index as u64);
+            put_varint(buf, sample.packet_len as u64);
+            let captured_len = (sample.captured_len as usize).min(PACKET_SAMPLE_MAX_LEN);
+            put_bytes(buf, &sample.data[..captured_len]);
+        });
+        buf
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder { data };
+        let d = &mut decoder;
+        Ok(Frame {
+            now_ns: d.varint()?,
+            realtime_offset_ns: d.varint()?,
+            interface_counters: d.list(|d| Ok((d.u32()?, d.list(Decoder::counter)?)))?,
+            burst_peaks: d.list(|d| Ok((d.u32()?, d.list(|d| Ok((d.varint()?, d.varint()?)))?)))?,
+            inter_arrival_histograms: d.list(|d| {
+                let if_index = d.u32()?;
+                let histograms = d.list(|d| {
+                    let mut histogram = InterArrivalHistogram::new();
+                    for bucket in 0..INTER_ARRIVAL_NUM_BUCKETS {
+                        histogram.buckets[bucket] = d.varint()?;
+                    }
+                    Ok(histogram)
+                })?;
+                Ok((if_index, histograms))
+            })?,
+            fragment_counters: d.list(|d| {
+                let if_index = d.u32()?;
+                let counters = d.list(|d| {
+                    Ok(IpFragmentCounter {
+                        ipv4_first: d.varint()?,
+                        ipv4_middle: d.varint()?,
+                        ipv4_last: d.varint()?,
+                        ipv6: d.varint()?,
+                    })
+                })?;
+                Ok((if_index, counters))
+            })?,
+            backends: d
+                .list(|d| Ok((d.u32()?, String::from_utf8_lossy(d.bytes()?).to_string())))?,
+            rx_queue_counters: d.list(|d| Ok(((d.u32()?, d.u32()?), d.list(Decoder::counter)?)))?,
+            mac_counters: d.list(|d| Ok((d.array()?, d.list(Decoder::counter)?)))?,
+            hop_limit_counts: d.list(|d| Ok(((d.u32()?, d.u32()?), d.list(Decoder::varint)?)))?,
+            multicast_group_counters: d
+                .list(|d| Ok(((d.u32()?, d.array()?), d.list(Decoder::counter)?)))?,
+            multicast_members: d.list(|d| {
+                let key = (d.u32()?, d.array()?, d.array()?);
+                let membership = MulticastMembership {
+                    last_report_ns: d.varint()?,
+                    report_count: d.u32()?,
+                    report_kind: d.u32()?,
+                    state: d.u32()?,
+                };
+                Ok((key, membership))
+            })?,
+            packet_samples: d.list(|d| {
+                let mut sample = PacketSample {
+                    timestamp_ns: d.varint()?,
+                    if_index: d.u32()?,
+                    packet_len: d.u32()?,
+                    captured_len: 0,
+                    data: [0; PACKET_SAMPLE_MAX_LEN],
+                };
+                let data = d.bytes()?;
+                let captured_len = data.len().min(PACKET_SAMPLE_MAX_LEN);
+                sample.data[..captured_len].copy_from_slice(&data[..captured_len]);
+                sample.captured_len = captured_len as u32;
+                Ok(sample)
+            })?,
+        })
+    }
+}
+
+/// Writes a session, one frame per tick
+pub(super) struct SessionWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    num_frames: u64,
+}
+
+impl SessionWriter {
+    /// Creates the file and writes the header, which describes the interfaces and the tick rate
+    /// of the recording
+    pub(super) fn create(
+        path: &Path,
+        interfaces: &[NetworkInterface],
+        tick_rate_ms: u64,
+    ) -> Result<Self> {
+        let file = File::create(path)
+            .wrap_err_with(|| format!("Failed to create session file {}", path.display()))?;
+        let mut header = MAGIC.to_vec();
+        put_varint(&mut header, VERSION);
+        put_varint(&mut header, tick_rate_ms);
+        put_list(&mut header, interfaces, |buf, interface| {
+            put_varint(buf, interface.index as u64);
+            put_bytes(buf, interface.name.as_bytes());
+        });
+
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&header)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            num_frames: 0,
+        })
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(super) fn num_frames(&self) -> u64 {
+        self.num_frames
+    }
+
+    /// Appends a frame, which is flushed straight away so that the recording survives a crash
+    pub(super) fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let body = frame.encode();
+        self.writer.write_all(&(body.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&body)?;
+        self.writer.flush()?;
+        self.num_frames += 1;
+        Ok(())
+    }
+}
+
+/// Counter values which only ever grow, so that they can be rebased
+trait Cumulative: Copy {
+    fn zero() -> Self;
+
+    fn wrapping_sub(self, base: Self) -> Self;
+
+    /// Whether any part of the value is lower than in `other`, as when a counter was reset
+    fn is_below(&self, other: &Self) -> bool;
+
+    fn is_zero(&self) -> bool;
+}
+
+impl Cumulative for Counter {
+    fn zero() -> Self {
+        Counter {
+            bytes: 0,
+            packets: 0,
+        }
+    }
+
+    fn wrapping_sub(self, base: Self) -> Self {
+        Counter {
+            bytes: self.bytes.wrapping_sub(base.bytes),
+            packets: self.packets.wrapping_sub(base.packets),
+        }
+    }
+
+    fn is_below(&self, other: &Self) -> bool {
+        self.bytes < other.bytes || self.packets < other.packets
+    }
+
+    fn is_zero(&self) -> bool {
+        self.packets == 0
+    }
+}
+
+impl Cumulative for InterArrivalHistogram {
+    fn zero() -> Self {
+        InterArrivalHistogram::new()
+    }
+
+    fn wrapping_sub(self, base: Self) -> Self {
+        let mut histogram = self;
+        for (bucket, base_bucket) in histogram.buckets.iter_mut().zip(base.buckets) {
+            *bucket = bucket.wrapping_sub(base_bucket);
+        }
+        histogram
+    }
+
+    fn is_below(&self, other: &Self) -> bool {
+        self.buckets
+            .iter()
+            .zip(other.buckets)
+            .any(|(bucket, other_bucket)| *bucket < other_bucket)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.buckets.iter().all(|bucket| *bucket == 0)
+    }
+}
+
+impl Cumulative for IpFragmentCounter {
+    fn zero() -> Self {
+        IpFragmentCounter::default()
+    }
+
+    fn wrapping_sub(self, base: Self) -> Self {
+        IpFragmentCounter {
+            ipv4_first: self.ipv4_first.wrapping_sub(base.ipv4_first),
+            ipv4_middle: self.ipv4_middle.wrapping_sub(base.ipv4_middle),
+            ipv4_last: self.ipv4_last.wrapping_sub(base.ipv4_last),
+            ipv6: self.ipv6.wrapping_sub(base.ipv6),
+        }
+    }
+
+    fn is_below(&self, other: &Self) -> bool {
+        self.ipv4_first < other.ipv4_first
+            || self.ipv4_middle < other.ipv4_middle
+            || self.ipv4_last < other.ipv4_last
+            || self.ipv6 < other.ipv6
+    }
+
+    fn is_zero(&self) -> bool {
+        self.ipv4_first == 0 && self.ipv4_middle == 0 && self.ipv4_last == 0 && self.ipv6 == 0
+    }
+}
+
+impl Cumulative for u64 {
+    fn zero() -> Self {
+        0
+    }
+
+    fn wrapping_sub(self, base: Self) -> Self {
+        u64::wrapping_sub(self, base)
+    }
+
+    fn is_below(&self, other: &Self) -> bool {
+        self < other
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+}
+
+/// Subtracts `bases` from `values` CPU by CPU
+fn wrapping_sub_each<'a, T: Cumulative>(
+    values: &'a [T],
+    bases: &'a [T],
+) -> impl Iterator<Item = T> + 'a {
+    values
+        .iter()
+        .enumerate()
+        .map(|(cpu_id, value)| match bases.get(cpu_id) {
+            Some(base) => value.wrapping_sub(*base),
+            None => *value,
+        })
+}
+
+fn wrapping_sub_all<T: Cumulative>(values: &[T], bases: &[T]) -> Vec<T> {
+    wrapping_sub_each(values, bases).collect()
+}
+
+/// What is subtracted from the recorded values of a counter, along with the recorded values at
+/// the time, which tell when the recording itself reset the counter
+struct Base<T> {
+    recorded: Vec<T>,
+    base: Vec<T>,
+}
+
+/// Values subtracted from the recorded counters before they are served, which stand in for the
+/// counters that the contexts removed and keep the counters steady across seeks. Counters are
+/// hidden while they have not grown past their base, like a map entry which was deleted. The
+/// subtraction wraps, since the values served can be higher than the ones recorded after
+/// seeking backward.
+struct Baselines<K, T> {
+    bases: HashMap<K, Base<T>>,
+    hidden: HashSet<K>,
+    // The values being served, which is reused from tick to tick
+    served: RefCell<Vec<T>>,
+}
+
+impl<K: Copy + Eq + Hash, T: Cumulative> Baselines<K, T> {
+    fn new() -> Self {
+        Self {
+            bases: HashMap::new(),
+            hidden: HashSet::new(),
+            served: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Fills in `served` with the values to serve for the recorded ones. Returns false if the
+    /// counter is hidden.
+    fn serve_into(&self, key: &K, recorded: &[T], served: &mut Vec<T>) -> bool {
+        served.clear();
+        match self.bases.get(key) {
+            Some(base)
+                if !recorded
+                    .iter()
+                    .zip(&base.recorded)
+                    .any(|(value, base_value)| value.is_below(base_value)) =>
+            {
+                served.extend(wrapping_sub_each(recorded, &base.base))
+            }
+            _ => served.extend_from_slice(recorded),
+        }
+        !(self.hidden.contains(key) && served.iter().all(Cumulative::is_zero))
+    }
+
+    /// Calls `visit` with the values to serve for the recorded ones, unless the counter is hidden
+    fn serve(&self, key: &K, recorded: &[T], visit: impl FnOnce(&[T])) {
+        let mut served = self.served.borrow_mut();
+        if self.serve_into(key, recorded, &mut served) {
+            visit(&served);
+        }
+    }
+
+    fn serve_all(&self, recorded: &[(K, Vec<T>)], mut visit: impl FnMut(&K, &[T])) {
+        for (key, values) in recorded {
+            self.serve(key, values, |served| visit(key, served));
+        }
+    }
+
+    fn remove(&mut self, key: K, recorded: &[(K, Vec<T>)]) {
+        if let Some(values) = find(recorded, &key) {
+            self.bases.insert(
+                key,
+                Base {
+                    recorded: values.clone(),
+                    base: values.clone(),
+                },
+            );
+            self.hidden.insert(key);
+        }
+    }
+
+    /// Rebases the counters for a jump from one frame to another, so that the values served
+    /// stay where they were and only what is counted after the jump shows up as traffic
+    fn rebase(&mut self, from: &[(K, Vec<T>)], to: &[(K, Vec<T>)]) {
+        let mut served: HashMap<K, Vec<T>> = from
+            .iter()
+            .filter_map(|(key, values)| {
+                let mut served = Vec::new();
+                self.serve_into(key, values, &mut served)
+                    .then_some((*key, served))
+            })
+            .collect();
+        self.bases.clear();
+        self.hidden.clear();
+        for (key, recorded) in to {
+            let base = match served.remove(key) {
+                Some(served) => wrapping_sub_all(recorded, &served),
+                None => {
+                    self.hidden.insert(*key);
+                    recorded.clone()
+                }
+            };
+            self.bases.insert(
+                *key,
+                Base {
+                    recorded: recorded.clone(),
+                    base,
+                },
+            );
+        }
+        // Counters which were not recorded yet at the frame jumped to carry on from the values
+        // served when they show up again
+        for (key, served) in served {
+            let zeros = vec![T::zero(); served.len()];
+            self.bases.insert(
+                key,
+                Base {
+                    recorded: Vec::new(),
+                    base: wrapping_sub_all(&zeros, &served),
+                },
+            );
+        }
+    }
+}
+
+fn find<'a, K: Eq, V>(recorded: &'a [(K, V)], key: &K) -> Option<&'a V> {
+    recorded
+        .iter()
+        .find(|(other_key, _)| other_key == key)
+        .map(|(_, value)| value)
+}
+
+/// Formats an offset into the recording as minutes and seconds
+fn format_offset(offset_ms: u64) -> String {
+    let offset_sec = offset_ms / 1000;
+    format!("{}:{:02}", offset_sec / 60, offset_sec % 60)
+}
+
+/// Plays back a recorded session, paced by the tick rate it was recorded at
+pub(crate) struct SessionSource {
+    path: PathBuf,
+    reader: BufReader<File>,
+    // Offset and length of each frame in the file
+    frame_offsets: Vec<(u64, usize)>,
+    interfaces: Vec<NetworkInterface>,
+    recorded_tick_rate_ms: u64,
+    tick_rate_ms: u64,
+    frame_index: usize,
+    // Position in the recording in frames, which advances by fractions of a frame when slowed
+    position: f64,
+    frame: Frame,
+    speed: f64,
+    paused: bool,
+    pending_steps: u32,
+    burst_epoch: u64,
+    // The microburst counters being served, which is reused from tick to tick
+    burst_counters: RefCell<Vec<BurstCounter>>,
+    packet_samples: Vec<PacketSample>,
+    interface_counters: Baselines<u32, Counter>,
+    inter_arrival_histograms: Baselines<u32, InterArrivalHistogram>,
+    fragment_counters: Baselines<u32, IpFragmentCounter>,
+    rx_queue_counters: Baselines<(u32, u32), Counter>,
+    mac_counters: Baselines<[u8; 6], Counter>,
+    hop_limit_counts: Baselines<(u32, u32), u64>,
+    multicast_group_counters: Baselines<GroupId, Counter>,
+    // Last report time of the members that the contexts removed, which stay hidden until they
+    // report again
+    removed_members: HashMap<MemberId, u64>,
+}
+
+impl SessionSource {
+    /// Opens a session for playback at `speed` times real time, or one tick at a time if `paused`
+    pub(crate) fn open(path: &Path, speed: f64, paused: bool, tick_rate_ms: u64) -> Result<Self> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(eyre!("Replay speed must be positive, got {}", speed));
+        }
+
+        let mut contents = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut contents))
+            .wrap_err_with(|| format!("Failed to read session file {}", path.display()))?;
+        let data = contents
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| eyre!("{} is not a recorded session", path.display()))?;
+        let mut decoder = Decoder { data };
+        let version = decoder.varint()?;
+        if version != VERSION {
+            return Err(eyre!(
+                "{} was recorded in session format version {}, which is not supported",
+                path.display(),
+                version
+            ));
+        }
+        let recorded_tick_rate_ms = decoder.varint()?.max(1);
+        let interfaces = decoder.list(|d| {
+            Ok(NetworkInterface {
+                index: d.u32()?,
+                name: String::from_utf8_lossy(d.bytes()?).to_string(),
+                addr: Vec::new(),
+                mac_addr: None,
+                internal: false,
+            })
+        })?;
+
+        // Frames are read as they are played, so only where they are is kept
+        let mut frame_offsets = Vec::new();
+        let mut offset = contents.len() - decoder.data.len();
+        while let Some(len_bytes) = contents.get(offset..offset + 4) {
+            let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+            if offset + 4 + len > contents.len() {
+                tracing::warn!(
+                    "Ignoring a truncated frame at the end of {}",
+                    path.display()
+                );
+                break;
+            }
+            frame_offsets.push(((offset + 4) as u64, len));
+            offset += 4 + len;
+        }
+        if frame_offsets.is_empty() {
+            return Err(eyre!("{} has no recorded ticks", path.display()));
+        }
+
+        let mut session_source = Self {
+            path: path.to_path_buf(),
+            reader: BufReader::new(File::open(path)?),
+            frame_offsets,
+            interfaces,
+            recorded_tick_rate_ms,
+            tick_rate_ms,
+            frame_index: 0,
+            position: 0.0,
+            frame: Frame::default(),
+            speed,
+            paused,
+            pending_steps: 0,
+            burst_epoch: 0,
+            burst_counters: RefCell::new(Vec::new()),
+            packet_samples: Vec::new(),
+            interface_counters: Baselines::new(),
+            inter_arrival_histograms: Baselines::new(),
+            fragment_counters: Baselines::new(),
+            rx_queue_counters: Baselines::new(),
+            mac_counters: Baselines::new(),
+            hop_limit_counts: Baselines::new(),
+            multicast_group_counters: Baselines::new(),
+            removed_members: HashMap::new(),
+        };
+        session_source.frame = session_source.read_frame(0)?;
+        Ok(session_source)
+    }
+
+    fn read_frame(&mut self, frame_index: usize) -> Result<Frame> {
+        let (offset, len) = self.frame_offsets[frame_index];
+        let mut body = vec![0; len];
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut body)?;
+        Frame::decode(&body).wrap_err_with(|| {
+            format!(
+                "Failed to decode tick {} of {}",
+                frame_index,
+                self.path.display()
+            )
+        })
+    }
+
+    fn last_frame_index(&self) -> usize {
+        self.frame_offsets.len() - 1
+    }
+
+    fn toggle_paused(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Advances a paused playback by a single tick
+    fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    fn change_speed(&mut self, factor: f64) {
+        self.speed = (self.speed * factor).clamp(MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED);
+    }
+
+    /// Jumps forward or backward through the recording. The counters carry on from where they
+    /// were, so that the contexts only see a tick with no traffic.
+    fn seek(&mut self, forward: bool) -> Result<()> {
+        let seek_frames = (SEEK_SEC * 1000 / self.recorded_tick_rate_ms).max(1) as usize;
+        let frame_index = if forward {
+            (self.frame_index + seek_frames).min(self.last_frame_index())
+        } else {
+            self.frame_index.saturating_sub(seek_frames)
+        };
+        if frame_index == self.frame_index {
+            return Ok(());
+        }
+
+        let frame = self.read_frame(frame_index)?;
+        let from = &self.frame;
+        self.interface_counters
+            .rebase(&from.interface_counters, &frame.interface_counters);
+        self.inter_arrival_histograms.rebase(
+            &from.inter_arrival_histograms,
+            &frame.inter_arrival_histograms,
+        );
+        self.fragment_counters
+            .rebase(&from.fragment_counters, &frame.fragment_counters);
+        self.rx_queue_counters
+            .rebase(&from.rx_queue_counters, &frame.rx_queue_counters);
+        self.mac_counters
+            .rebase(&from.mac_counters, &frame.mac_counters);
+        self.hop_limit_counts
+            .rebase(&from.hop_limit_counts, &frame.hop_limit_counts);
+        self.multicast_group_counters.rebase(
+            &from.multicast_group_counters,
+            &frame.multicast_group_counters,
+        );
+        self.removed_members.clear();
+        self.packet_samples.clear();
+
+        self.frame = frame;
+        self.frame_index = frame_index;
+        self.position = frame_index as f64;
+        Ok(())
+    }
+}
+
+impl DataSource for SessionSource {
+    fn interfaces(&self) -> &[NetworkInterface] {
+        &self.interfaces
+    }
+
+    /// Moves on to the frames recorded during the next tick. Returns false if the playback is
+    /// paused or finished, or has not yet reached the next frame, and the contexts should not
+    /// tick.
+    fn on_tick(&mut self) -> Result<bool> {
+        if self.frame_index == self.last_frame_index() {
+            return Ok(false);
+        }
+        if self.paused {
+            if self.pending_steps == 0 {
+                return Ok(false);
+            }
+            self.pending_steps -= 1;
+            self.position = (self.frame_index + 1) as f64;
+        } else {
+            let advance = self.speed * self.tick_rate_ms as f64 / self.recorded_tick_rate_ms as f64;
+            self.position = (self.position + advance).min(self.last_frame_index() as f64);
+        }
+
+        let frame_index = self.position as usize;
+        if frame_index == self.frame_index {
+            return Ok(false);
+        }
+        // Frames skipped over at higher speeds still hand over the packets sampled during them
+        for skipped_index in self.frame_index + 1..frame_index {
+            let skipped_frame = self.read_frame(skipped_index)?;
+            self.packet_samples.extend(skipped_frame.packet_samples);
+        }
+        self.frame = self.read_frame(frame_index)?;
+        self.packet_samples
+            .extend(self.frame.packet_samples.iter().copied());
+        self.frame_index = frame_index;
+
+        Ok(true)
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::TogglePause => self.toggle_paused(),
+            Action::Step => self.step(),
+            Action::Slower => self.change_speed(0.5),
+            Action::Faster => self.change_speed(2.0),
+            Action::SeekBackward | Action::SeekForward => {
+                if let Err(report) = self.seek(action == Action::SeekForward) {
+                    tracing::warn!("Failed to seek through the session: {}", report);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn get_command_help(&self, keymap: &Keymap) -> Vec<String> {
+        let state = if self.frame_index == self.last_frame_index() {
+            String::from("finished")
+        } else if self.paused {
+            String::from("paused")
+        } else {
+            format!("playing at {}x", self.speed)
+        };
+        vec![format!(
+            "Replaying session {} ({}/{}, {state}): {}",
+            self.path.display(),
+            format_offset(self.frame_index as u64 * self.recorded_tick_rate_ms),
+            format_offset(self.last_frame_index() as u64 * self.recorded_tick_rate_ms),
+            keymap.help(&[
+                (&[Action::TogglePause], "Pause/resume"),
+                (&[Action::Step], "Step one tick"),
+                (&[Action::Slower, Action::Faster], "Slower/faster"),
+                (
+                    &[Action::SeekBackward, Action::SeekForward],
+                    "Seek back/forward",
+                ),
+            ])
+        )]
+    }
+
+    fn collection_backend(&self, if_index: u32) -> Option<String> {
+        find(&self.frame.backends, &if_index).cloned()
+    }
+
+    fn attach(&mut self, _interface: &NetworkInterface) -> Result<()> {
+        Ok(())
+    }
+
+    fn detach(&mut self, _interface: &NetworkInterface) -> Result<()> {
+        Err(eyre!("Collection cannot be stopped on a recorded session"))
+    }
+
+    fn interface_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[Counter])) -> Result<()> {
+        let recorded = find(&self.frame.interface_counters, &if_index)
+            .ok_or_else(|| eyre!("No counters for interface with index {}", if_index))?;
+        self.interface_counters.serve(&if_index, recorded, visit);
+        Ok(())
+    }
+
+    fn rx_queue_counters(&self, visit: &mut dyn FnMut(&RxQueueKey, &[Counter])) -> Result<()> {
+        self.rx_queue_counters.serve_all(
+            &self.frame.rx_queue_counters,
+            |(if_index, rx_queue), counters| {
+                let key = RxQueueKey {
+                    if_index: *if_index,
+                    rx_queue: *rx_queue,
+                };
+                visit(&key, counters)
+            },
+        );
+        Ok(())
+    }
+
+    fn burst_counters(&self, if_index: u32, visit: &mut dyn FnMut(&[BurstCounter])) -> Result<()> {
+        // The peaks were recorded for the epoch current at the time, which stands in for the
+        // epoch that the contexts set now
+        if let Some(peaks) = find(&self.frame.burst_peaks, &if_index) {
+            let mut burst_counters = self.burst_counters.borrow_mut();
+            burst_counters.clear();
+            burst_counters.extend(peaks.iter().map(|(peak_packets, peak_bytes)| BurstCounter {
+                epoch: self.burst_epoch,
+                peak_packets: *peak_packets,
+                peak_bytes: *peak_bytes,
+                ..Default::default()
+            }));
+            visit(&burst_counters);
+        }
+        Ok(())
+    }
+
+    fn set_burst_epoch(&mut self, epoch: u64) -> Result<()> {
+        self.burst_epoch = epoch;
+        Ok(())
+    }
+
+    fn inter_arrival_histograms(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[InterArrivalHistogram]),
+    ) -> Result<()> {
+        if let Some(recorded) = find(&self.frame.inter_arrival_histograms, &if_index) {
+            self.inter_arrival_histograms
+                .serve(&if_index, recorded, visit);
+        }
+        Ok(())
+    }
+
+    fn mac_counters(&self, visit: &mut dyn FnMut(&[u8; 6], &[Counter])) -> Result<()> {
+        self.mac_counters.serve_all(&self.frame.mac_counters, visit);
+        Ok(())
+    }
+
+    fn remove_mac_counters(&mut self, src_mac: &[u8; 6]) -> Result<()> {
+        self.mac_counters.remove(*src_mac, &self.frame.mac_counters);
+        Ok(())
+    }
+
+    fn fragment_counters(
+        &self,
+        if_index: u32,
+        visit: &mut dyn FnMut(&[IpFragmentCounter]),
+    ) -> Result<()> {
+        if let Some(recorded) = find(&self.frame.fragment_counters, &if_index) {
+            self.fragment_counters.serve(&if_index, recorded, visit);
+        }
+        Ok(())
+    }
+
+    fn hop_limit_counts(&self, visit: &mut dyn FnMut(&HopLimitKey, &[u64])) -> Result<()> {
+        self.hop_limit_counts.serve_all(
+            &self.frame.hop_limit_counts,
+            |(if_index, hop_limit), counts| {
+                let key = HopLimitKey {
+                    if_index: *if_index,
+                    hop_limit: *hop_limit,
+                };
+                visit(&key, counts)
+            },
+        );
+        Ok(())
+    }
+
+    fn multicast_group_counters(
+        &self,
+        visit: &mut dyn FnMut(&MulticastGroupKey, &[Counter]),
+    ) -> Result<()> {
+        self.multicast_group_counters.serve_all(
+            &self.frame.multicast_group_counters,
+            |(if_index, group), counters| {
+                let key = MulticastGroupKey {
+                    if_index: *if_index,
+                    group: *group,
+                };
+                visit(&key, counters)
+            },
+        );
+        Ok(())
+    }
+
+    fn remove_multicast_group_counters(&mut self, key: &MulticastGroupKey) -> Result<()> {
+        self.multicast_group_counters.remove(
+            (key.if_index, key.group),
+            &self.frame.multicast_group_counters,
+        );
+        Ok(())
+    }
+
+    fn multicast_members(
+        &self,
+        visit: &mut dyn FnMut(&MulticastMemberKey, &MulticastMembership),
+    ) -> Result<()> {
+        for (member, membership) in &self.frame.multicast_members {
+            if self.removed_members.get(member) == Some(&membership.last_report_ns) {
+                continue;
+            }
+            let (if_index, group, reporter) = *member;
+            let key = MulticastMemberKey {
+                if_index,
+                group,
+                reporter,
+            };
+            visit(&key, membership);
+        }
+        Ok(())
+    }
+
+    fn remove_multicast_member(&mut self, key: &MulticastMemberKey) -> Result<()> {
+        let key = (key.if_index, key.group, key.reporter);
+        if let Some(membership) = find(&self.frame.multicast_members, &key) {
+            self.removed_members.insert(key, membership.last_report_ns);
+        }
+        Ok(())
+    }
+
+    fn packet_samples(&mut self, visit: &mut dyn FnMut(&PacketSample)) {
+        for sample in self.packet_samples.drain(..) {
+            visit(&sample);
+        }
+    }
+
+    /// Source time that the playback has reached
+    fn now_ns(&self) -> u64 {
+        self.frame.now_ns
+    }
+
+    fn realtime_offset_ns(&self) -> u64 {
+        self.frame.realtime_offset_ns
+    }
+}
@@ -0,0 +1,85 @@
+// Leaves the host as it was found when tsndt stops: the terminal is restored, the XDP programs
+// are detached when the data source is dropped, and the log is flushed. Signals ask the app to
+// stop, so that it returns through the same path as when quitting. A second signal exits without
+// dropping the data source, so the source registers an exit hook to undo what it attached. Panics
+// unwind through main, which drops the data source, once the hook below has given the terminal
+// back.
+
+use std::{
+    panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use color_eyre::eyre::Result;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::app;
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+type ExitHook = Box<dyn FnOnce() + Send>;
+
+static EXIT_HOOK: Mutex<Option<ExitHook>> = Mutex::new(None);
+
+/// Sets what to undo if a second signal exits before the data source is dropped, in place of any
+/// hook set before
+pub(crate) fn set_exit_hook(hook: impl FnOnce() + Send + 'static) {
+    if let Ok(mut exit_hook) = EXIT_HOOK.lock() {
+        *exit_hook = Some(Box::new(hook));
+    }
+}
+
+/// Removes the exit hook, once the data source undoes what it attached itself
+pub(crate) fn clear_exit_hook() {
+    if let Ok(mut exit_hook) = EXIT_HOOK.lock() {
+        *exit_hook = None;
+    }
+}
+
+/// Whether a signal asked tsndt to stop
+pub(crate) fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Asks the app to stop on SIGTERM, SIGINT, SIGHUP and SIGQUIT. A second signal runs the exit hook
+/// and exits right away, in case the app does not get to stop.
+pub(crate) fn install_signal_handlers() -> Result<()> {
+    for signal_kind in [
+        SignalKind::terminate(),
+        SignalKind::interrupt(),
+        SignalKind::hangup(),
+        SignalKind::quit(),
+    ] {
+        let mut signals = signal(signal_kind)?;
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                if SHUTDOWN_REQUESTED.swap(true, Ordering::Relaxed) {
+                    ratatui::restore();
+                    let exit_hook = EXIT_HOOK.lock().ok().and_then(|mut hook| hook.take());
+                    if let Some(exit_hook) = exit_hook {
+                        exit_hook();
+                    }
+                    app::sync_log();
+                    std::process::exit(128 + signal_kind.as_raw_value());
+                }
+                tracing::info!("Stopping on signal {}", signal_kind.as_raw_value());
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Restores the terminal and records the panic in the log before the panic is reported, since
+/// the report would otherwise be lost in the alternate screen
+pub(crate) fn install_panic_hook() {
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        ratatui::restore();
+        tracing::error!("{}", panic_info);
+        app::sync_log();
+        previous_hook(panic_info);
+    }));
+}